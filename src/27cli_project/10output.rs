@@ -0,0 +1,690 @@
+///* 输出匹配结果：行号、字节偏移和上下文
+// - 匹配行的前缀用 ':' 分隔，上下文行用 '-' 分隔，和 grep 一样：
+//   src/main.rs:12:fn main() {
+//   src/main.rs-13-    let x = 1;
+// - 不相邻的上下文分组之间输出一行 "--"
+// - 打开颜色时用 ANSI 转义序列高亮每一处匹配，配色和 grep 默认的 GREP_COLORS 一样
+// - --json 时每行输出一个 JSON 对象（JSON Lines）：
+//   {"type":"begin","data":{"path":"poem.txt"}}
+//   {"type":"match","data":{"path":"poem.txt","line_number":3,"absolute_offset":45,
+//     "line":"...","submatches":[{"match":"tell","start":34,"end":38}]}}
+//   {"type":"context",...}，字段和 match 一样，submatches 为空
+//   {"type":"end","data":{"path":"poem.txt","matches":2}}
+//   最后由 run 输出 {"type":"summary","data":{...}}；begin / end 只为有选中行的文件输出
+// - 二进制文件默认不输出选中的行，只输出 "Binary file X matches"（--json 时为 {"type":"binary",...}）
+use std::collections::VecDeque;
+use std::env;
+use std::fmt;
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::{numbered_lines, Match, Matcher};
+
+// * 匹配为粗体红色，文件名紫色，行号和偏移绿色，分隔符青色；\x1b[K 清除到行尾，避免背景色拖尾
+const MATCH_COLOR: &str = "\x1b[01;31m\x1b[K";
+const PATH_COLOR: &str = "\x1b[35m\x1b[K";
+const NUMBER_COLOR: &str = "\x1b[32m\x1b[K";
+const SEP_COLOR: &str = "\x1b[36m\x1b[K";
+const RESET: &str = "\x1b[m\x1b[K";
+
+// * --color=auto|always|never
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ColorChoice {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// auto 时只有标准输出是终端（并且不是哑终端）才输出颜色
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                io::stdout().is_terminal() && env::var_os("TERM").is_none_or(|term| term != "dumb")
+            }
+        }
+    }
+}
+
+impl fmt::Display for ColorChoice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ColorChoice::Auto => "auto",
+            ColorChoice::Always => "always",
+            ColorChoice::Never => "never",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for ColorChoice {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<ColorChoice, ()> {
+        match s {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(()),
+        }
+    }
+}
+
+// * 二进制文件（开头含有 NUL）怎么处理
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum BinaryMode {
+    // * 和 grep 一样，有选中的行时只输出一条提示
+    #[default]
+    Matches,
+    // * -a / --text：当作文本输出选中的行
+    Text,
+    // * --binary-skip：不搜索
+    Skip,
+}
+
+impl fmt::Display for BinaryMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            BinaryMode::Matches => "matches",
+            BinaryMode::Text => "text",
+            BinaryMode::Skip => "skip",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// * 输出什么：匹配的行、每个文件的匹配行数、文件名，或者什么都不输出
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum OutputMode {
+    #[default]
+    Lines,
+    // * -c
+    Count,
+    // * -l
+    FilesWithMatches,
+    // * -L
+    FilesWithoutMatch,
+    // * -q：只通过退出码告诉调用者有没有匹配
+    Quiet,
+    // * --json
+    Json,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct OutputOptions {
+    pub mode: OutputMode,
+    // * 每行前面是否输出文件名，多个输入或者搜索目录时由 run 打开
+    pub with_filename: bool,
+    // * -n
+    pub line_number: bool,
+    // * -b：输出行首在文件中的字节偏移
+    pub byte_offset: bool,
+    // * -B / -A，-C 同时设置两者
+    pub before_context: usize,
+    pub after_context: usize,
+    // * -m：每个文件最多选中多少行
+    pub max_count: Option<usize>,
+    // * 是否输出颜色，由 run 根据 ColorChoice 决定
+    pub color: bool,
+    pub binary: BinaryMode,
+}
+
+impl OutputOptions {
+    /// 每个文件最多需要找到多少行：-l / -L / -q 找到一行就够了
+    pub fn limit(&self) -> Option<usize> {
+        match self.mode {
+            OutputMode::FilesWithMatches | OutputMode::FilesWithoutMatch | OutputMode::Quiet => {
+                Some(1)
+            }
+            OutputMode::Lines | OutputMode::Count | OutputMode::Json => self.max_count,
+        }
+    }
+
+    /// 是否逐行输出选中的行（和上下文）
+    pub fn prints_lines(&self) -> bool {
+        matches!(self.mode, OutputMode::Lines | OutputMode::Json)
+    }
+
+    fn has_context(&self) -> bool {
+        self.prints_lines() && (self.before_context > 0 || self.after_context > 0)
+    }
+}
+
+/// --json 时最后输出的统计
+pub fn write_json_summary<W: Write>(
+    out: &mut W,
+    searched: usize,
+    files_matched: usize,
+    matched_lines: usize,
+    elapsed: Duration,
+) -> io::Result<()> {
+    writeln!(
+        out,
+        r#"{{"type":"summary","data":{{"searched":{},"files_matched":{},"matched_lines":{},"elapsed_secs":{:.6}}}}}"#,
+        searched,
+        files_matched,
+        matched_lines,
+        elapsed.as_secs_f64()
+    )
+}
+
+// * JSON 字符串：加上引号，转义引号、反斜杠和控制字符
+fn json_string(s: &str) -> String {
+    let mut json = String::with_capacity(s.len() + 2);
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+/// 输出上下文分组之间的 "--"，并行搜索时文件之间也用它分隔
+pub fn write_separator<W: Write>(out: &mut W, options: &OutputOptions) -> io::Result<()> {
+    paint(out, options.color, SEP_COLOR, "--")?;
+    writeln!(out)
+}
+
+fn paint<W: Write>(
+    out: &mut W,
+    color: bool,
+    code: &str,
+    text: impl fmt::Display,
+) -> io::Result<()> {
+    if color {
+        write!(out, "{}{}{}", code, text, RESET)
+    } else {
+        write!(out, "{}", text)
+    }
+}
+
+pub struct Printer {
+    options: OutputOptions,
+    // * 高亮和 --json 的 submatches 用来找出一行中的所有匹配
+    highlight: Option<Arc<Matcher>>,
+    // * --json：当前文件是否已经输出了 begin
+    begun: bool,
+    // 是否已经输出过上下文分组，决定下一组前要不要打印 "--"
+    printed_group: bool,
+    // 当前文件最后输出的行号，和下一行不相邻时需要分隔符
+    last_printed: Option<usize>,
+    // * 流式输出时缓存最近的 before_context 行，以及还要输出几行下文
+    before: VecDeque<(usize, usize, String)>,
+    after_remaining: usize,
+}
+
+impl Printer {
+    pub fn new(options: OutputOptions) -> Printer {
+        Printer {
+            options,
+            highlight: None,
+            begun: false,
+            printed_group: false,
+            last_printed: None,
+            before: VecDeque::new(),
+            after_remaining: 0,
+        }
+    }
+
+    /// 用 `matcher` 找出选中行中的每一处匹配：打开颜色时高亮，--json 时输出为 submatches
+    pub fn highlight(mut self, matcher: Arc<Matcher>) -> Printer {
+        self.highlight = Some(matcher);
+        self
+    }
+
+    /// 开始输出一个新文件：清空上一个文件遗留的上下文状态
+    pub fn begin_file(&mut self) {
+        self.begun = false;
+        self.last_printed = None;
+        self.before.clear();
+        self.after_remaining = 0;
+    }
+
+    /// 一个文件结束：-c / -l / -L 在这里输出，`count` 为选中的行数
+    pub fn end_file<W: Write>(&mut self, out: &mut W, path: &Path, count: usize) -> io::Result<()> {
+        match self.options.mode {
+            OutputMode::Count if self.options.with_filename => {
+                writeln!(out, "{}:{}", path.display(), count)
+            }
+            OutputMode::Count => writeln!(out, "{}", count),
+            OutputMode::FilesWithMatches if count > 0 => writeln!(out, "{}", path.display()),
+            OutputMode::FilesWithoutMatch if count == 0 => writeln!(out, "{}", path.display()),
+            OutputMode::Json if self.begun => writeln!(
+                out,
+                r#"{{"type":"end","data":{{"path":{},"matches":{}}}}}"#,
+                json_string(&path.to_string_lossy()),
+                count
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    /// 二进制文件中有选中的行：不输出这些行，只输出一条提示
+    pub fn binary_matches<W: Write>(&mut self, out: &mut W, path: &Path) -> io::Result<()> {
+        match self.options.mode {
+            OutputMode::Json => writeln!(
+                out,
+                r#"{{"type":"binary","data":{{"path":{}}}}}"#,
+                json_string(&path.to_string_lossy())
+            ),
+            _ => writeln!(out, "Binary file {} matches", path.display()),
+        }
+    }
+
+    /// 输出一个文件的所有匹配，`matches` 应该已经按 limit 截断
+    pub fn print<W: Write>(
+        &mut self,
+        out: &mut W,
+        path: &Path,
+        contents: &str,
+        matches: &[Match],
+    ) -> io::Result<()> {
+        self.begin_file();
+        if !self.options.prints_lines() || matches.is_empty() {
+            return self.end_file(out, path, matches.len());
+        }
+        if !self.options.has_context() {
+            for m in matches {
+                self.print_line(out, path, m.line_number, m.offset, m.line, ':')?;
+            }
+            return self.end_file(out, path, matches.len());
+        }
+
+        // * 有上下文时需要能按行号取到任意一行
+        let lines: Vec<(usize, usize, &str)> = numbered_lines(contents).collect();
+        let count = matches.len();
+        let mut matches = matches.iter().peekable();
+        // 下一个还没输出的行号（从 1 开始）
+        let mut next_line = 1;
+        while let Some(m) = matches.next() {
+            let first = m
+                .line_number
+                .saturating_sub(self.options.before_context)
+                .max(next_line);
+            for &(number, offset, line) in &lines[first - 1..m.line_number - 1] {
+                self.emit(out, path, number, offset, line, '-')?;
+            }
+            self.emit(out, path, m.line_number, m.offset, m.line, ':')?;
+            next_line = m.line_number + 1;
+
+            // * 下文一直延伸到下一个匹配之前；落在下文里的匹配行仍然按匹配行输出
+            let last = (m.line_number + self.options.after_context).min(lines.len());
+            while next_line <= last {
+                match matches.peek() {
+                    Some(next) if next.line_number <= last => break,
+                    _ => {}
+                }
+                let (number, offset, line) = lines[next_line - 1];
+                self.emit(out, path, number, offset, line, '-')?;
+                next_line += 1;
+            }
+        }
+        self.end_file(out, path, count)
+    }
+
+    /// 流式输出：按顺序喂入每一行，调用前先 begin_file，结束后调用 end_file
+    pub fn feed<W: Write>(
+        &mut self,
+        out: &mut W,
+        path: &Path,
+        number: usize,
+        offset: usize,
+        line: &str,
+        matched: bool,
+    ) -> io::Result<()> {
+        if !self.options.prints_lines() {
+            return Ok(());
+        }
+        if !self.options.has_context() {
+            if matched {
+                self.print_line(out, path, number, offset, line, ':')?;
+            }
+            return Ok(());
+        }
+
+        if matched {
+            while let Some((number, offset, line)) = self.before.pop_front() {
+                self.emit(out, path, number, offset, &line, '-')?;
+            }
+            self.emit(out, path, number, offset, line, ':')?;
+            self.after_remaining = self.options.after_context;
+        } else if self.after_remaining > 0 {
+            self.emit(out, path, number, offset, line, '-')?;
+            self.after_remaining -= 1;
+        } else if self.options.before_context > 0 {
+            if self.before.len() == self.options.before_context {
+                self.before.pop_front();
+            }
+            self.before.push_back((number, offset, line.to_string()));
+        }
+        Ok(())
+    }
+
+    /// 是否输出过上下文分组；"Binary file X matches" 这类提示不算
+    pub fn printed_group(&self) -> bool {
+        self.printed_group
+    }
+
+    /// 流式输出时是否还有下文没输出完；达到 -m 上限后靠它决定什么时候停止读取
+    pub fn pending_after_context(&self) -> bool {
+        self.after_remaining > 0
+    }
+
+    // * 有上下文时输出一行，和上一次输出的行不相邻就先输出分隔符
+    fn emit<W: Write>(
+        &mut self,
+        out: &mut W,
+        path: &Path,
+        number: usize,
+        offset: usize,
+        line: &str,
+        sep: char,
+    ) -> io::Result<()> {
+        if self.printed_group
+            && self.last_printed.is_none_or(|last| last + 1 != number)
+            && self.options.mode != OutputMode::Json
+        {
+            write_separator(out, &self.options)?;
+        }
+        self.printed_group = true;
+        self.last_printed = Some(number);
+        self.print_line(out, path, number, offset, line, sep)
+    }
+
+    fn print_line<W: Write>(
+        &mut self,
+        out: &mut W,
+        path: &Path,
+        number: usize,
+        offset: usize,
+        line: &str,
+        sep: char,
+    ) -> io::Result<()> {
+        if self.options.mode == OutputMode::Json {
+            return self.print_json(out, path, number, offset, line, sep == ':');
+        }
+        let color = self.options.color;
+        if self.options.with_filename {
+            paint(out, color, PATH_COLOR, path.display())?;
+            paint(out, color, SEP_COLOR, sep)?;
+        }
+        if self.options.line_number {
+            paint(out, color, NUMBER_COLOR, number)?;
+            paint(out, color, SEP_COLOR, sep)?;
+        }
+        if self.options.byte_offset {
+            paint(out, color, NUMBER_COLOR, offset)?;
+            paint(out, color, SEP_COLOR, sep)?;
+        }
+
+        // * 只高亮选中的行，上下文行原样输出
+        match &self.highlight {
+            Some(matcher) if color && sep == ':' => {
+                let mut last = 0;
+                for (start, end) in matcher.spans(line) {
+                    write!(out, "{}", &line[last..start])?;
+                    paint(out, color, MATCH_COLOR, &line[start..end])?;
+                    last = end;
+                }
+                writeln!(out, "{}", &line[last..])
+            }
+            _ => writeln!(out, "{}", line),
+        }
+    }
+
+    fn print_json<W: Write>(
+        &mut self,
+        out: &mut W,
+        path: &Path,
+        number: usize,
+        offset: usize,
+        line: &str,
+        matched: bool,
+    ) -> io::Result<()> {
+        let path = json_string(&path.to_string_lossy());
+        if !self.begun {
+            writeln!(out, r#"{{"type":"begin","data":{{"path":{}}}}}"#, path)?;
+            self.begun = true;
+        }
+        let spans = match &self.highlight {
+            Some(matcher) if matched => matcher.spans(line),
+            _ => Vec::new(),
+        };
+        let submatches: Vec<String> = spans
+            .into_iter()
+            .map(|(start, end)| {
+                format!(
+                    r#"{{"match":{},"start":{},"end":{}}}"#,
+                    json_string(&line[start..end]),
+                    start,
+                    end
+                )
+            })
+            .collect();
+        // * --fuzzy 时另外给出编辑距离，方便按接近程度排序
+        let distance = match &self.highlight {
+            Some(matcher) if matched => matcher.distance(line),
+            _ => None,
+        }
+        .map_or(String::new(), |distance| {
+            format!(r#","distance":{}"#, distance)
+        });
+        writeln!(
+            out,
+            r#"{{"type":"{}","data":{{"path":{},"line_number":{},"absolute_offset":{},"line":{},"submatches":[{}]{}}}}}"#,
+            if matched { "match" } else { "context" },
+            path,
+            number,
+            offset,
+            json_string(line),
+            submatches.join(","),
+            distance
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search;
+
+    fn render(options: OutputOptions, query: &str, contents: &str) -> String {
+        let mut out = Vec::new();
+        let matches = search(query, contents);
+        let options = OutputOptions {
+            with_filename: true,
+            ..options
+        };
+        Printer::new(options)
+            .print(&mut out, Path::new("poem.txt"), contents, &matches)
+            .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    const POEM: &str = "\
+I'm nobody! Who are you?
+Are you nobody, too?
+Then there's a pair of us - don't tell!
+They'd banish us, you know.
+
+How dreary to be somebody!
+How public, like a frog
+To tell your name the livelong day
+To an admiring bog!";
+
+    #[test]
+    fn line_number_and_offset() {
+        let options = OutputOptions {
+            line_number: true,
+            byte_offset: true,
+            ..OutputOptions::default()
+        };
+        assert_eq!(
+            "poem.txt:2:25:Are you nobody, too?\n",
+            render(options, "too", POEM)
+        );
+    }
+
+    #[test]
+    fn context_groups() {
+        let options = OutputOptions {
+            line_number: true,
+            before_context: 1,
+            after_context: 1,
+            ..OutputOptions::default()
+        };
+        let expected = "\
+poem.txt-2-Are you nobody, too?
+poem.txt:3:Then there's a pair of us - don't tell!
+poem.txt-4-They'd banish us, you know.
+--
+poem.txt-7-How public, like a frog
+poem.txt:8:To tell your name the livelong day
+poem.txt-9-To an admiring bog!
+";
+        assert_eq!(expected, render(options, "tell", POEM));
+    }
+
+    #[test]
+    fn streaming_matches_in_memory() {
+        let options = OutputOptions {
+            line_number: true,
+            before_context: 2,
+            after_context: 1,
+            ..OutputOptions::default()
+        };
+        let matches = search("us", POEM);
+        let mut printer = Printer::new(options.clone());
+        let mut in_memory = Vec::new();
+        printer
+            .print(&mut in_memory, Path::new("-"), POEM, &matches)
+            .unwrap();
+
+        let mut printer = Printer::new(options);
+        let mut streamed = Vec::new();
+        printer.begin_file();
+        for (number, offset, line) in numbered_lines(POEM) {
+            let matched = line.contains("us");
+            printer
+                .feed(&mut streamed, Path::new("-"), number, offset, line, matched)
+                .unwrap();
+        }
+
+        assert_eq!(in_memory, streamed);
+    }
+
+    #[test]
+    fn count_and_file_modes() {
+        let count = OutputOptions {
+            mode: OutputMode::Count,
+            ..OutputOptions::default()
+        };
+        assert_eq!("poem.txt:3\n", render(count, "body", POEM));
+
+        let with = OutputOptions {
+            mode: OutputMode::FilesWithMatches,
+            ..OutputOptions::default()
+        };
+        assert_eq!("poem.txt\n", render(with.clone(), "nobody", POEM));
+        assert_eq!("", render(with, "rust", POEM));
+
+        let without = OutputOptions {
+            mode: OutputMode::FilesWithoutMatch,
+            ..OutputOptions::default()
+        };
+        assert_eq!("poem.txt\n", render(without, "rust", POEM));
+    }
+
+    #[test]
+    fn highlight_every_match() {
+        let options = OutputOptions {
+            line_number: true,
+            after_context: 1,
+            color: true,
+            ..OutputOptions::default()
+        };
+        let matcher = Matcher::CaseInsensitive("us".to_string());
+        let contents = "US and us\nthem\n";
+        let matches = matcher.search(contents);
+        let mut out = Vec::new();
+        Printer::new(options)
+            .highlight(Arc::new(matcher))
+            .print(&mut out, Path::new("-"), contents, &matches)
+            .unwrap();
+
+        let expected = "\
+\x1b[32m\x1b[K1\x1b[m\x1b[K\x1b[36m\x1b[K:\x1b[m\x1b[K\
+\x1b[01;31m\x1b[KUS\x1b[m\x1b[K and \x1b[01;31m\x1b[Kus\x1b[m\x1b[K
+\x1b[32m\x1b[K2\x1b[m\x1b[K\x1b[36m\x1b[K-\x1b[m\x1b[Kthem
+";
+        assert_eq!(expected, String::from_utf8(out).unwrap());
+    }
+
+    #[test]
+    fn json_lines() {
+        let options = OutputOptions {
+            mode: OutputMode::Json,
+            after_context: 1,
+            // --json 不输出颜色
+            color: true,
+            ..OutputOptions::default()
+        };
+        let matcher = Matcher::Literal("us".to_string());
+        let contents = "us \"and\" us\n\tthem\n";
+        let matches = matcher.search(contents);
+        let mut out = Vec::new();
+        let mut printer = Printer::new(options).highlight(Arc::new(matcher));
+        printer
+            .print(&mut out, Path::new("a\\b.txt"), contents, &matches)
+            .unwrap();
+        // 没有选中行的文件不输出 begin / end
+        printer
+            .print(&mut out, Path::new("empty"), "", &[])
+            .unwrap();
+
+        let expected = r#"{"type":"begin","data":{"path":"a\\b.txt"}}
+{"type":"match","data":{"path":"a\\b.txt","line_number":1,"absolute_offset":0,"line":"us \"and\" us","submatches":[{"match":"us","start":0,"end":2},{"match":"us","start":9,"end":11}]}}
+{"type":"context","data":{"path":"a\\b.txt","line_number":2,"absolute_offset":12,"line":"\tthem","submatches":[]}}
+{"type":"end","data":{"path":"a\\b.txt","matches":1}}
+"#;
+        assert_eq!(expected, String::from_utf8(out).unwrap());
+    }
+
+    #[test]
+    fn color_choice() {
+        assert_eq!(Ok(ColorChoice::Always), "always".parse());
+        assert_eq!(Ok(ColorChoice::Never), "never".parse());
+        assert_eq!(Err(()), "yes".parse::<ColorChoice>());
+        assert!(ColorChoice::Always.enabled());
+        assert!(!ColorChoice::Never.enabled());
+    }
+
+    #[test]
+    fn overlapping_context_merges() {
+        let options = OutputOptions {
+            after_context: 2,
+            ..OutputOptions::default()
+        };
+        let expected = "\
+poem.txt:I'm nobody! Who are you?
+poem.txt:Are you nobody, too?
+poem.txt-Then there's a pair of us - don't tell!
+poem.txt-They'd banish us, you know.
+";
+        assert_eq!(expected, render(options, "nobody", POEM));
+    }
+}
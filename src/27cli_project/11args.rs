@@ -0,0 +1,303 @@
+///* 命令行参数解析
+// 由一张选项表（OptSpec）驱动，帮助文本也从这张表生成：
+// - 短选项 -n，可以合并成 -in；带值的短选项可以写成 -A 3 或 -A3，合并时值放在最后：-nA3
+// - 长选项 --line-number，带值的长选项可以写成 --context 3 或 --context=3
+// - 单独的 -- 之后全部当作位置参数，单独的 - 也是位置参数（表示标准输入）
+use std::error::Error;
+use std::fmt;
+
+pub struct OptSpec {
+    pub short: Option<char>,
+    pub long: &'static str,
+    // * 需要值的选项填值的名字（用于帮助文本），开关选项为 None
+    pub value: Option<&'static str>,
+    pub help: &'static str,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ArgsError {
+    // * --help / --version 并不是真正的错误，调用者打印文本后正常退出
+    Help(String),
+    Version(String),
+    UnknownOption(String),
+    MissingValue(String),
+    UnexpectedValue(String),
+    InvalidValue {
+        option: String,
+        value: String,
+    },
+    MissingArgument(&'static str),
+    UnexpectedArgument(String),
+    CommandLineOnly(String),
+    // * 选项只有和另一个选项一起使用才有意义
+    Requires(&'static str, &'static str),
+    // * 两个选项不能一起使用
+    Conflicts(&'static str, &'static str),
+    // * 配置文件中的错误，带上文件名和行号
+    ConfigFile {
+        path: String,
+        line: usize,
+        error: Box<ArgsError>,
+    },
+    ConfigUnreadable {
+        path: String,
+        message: String,
+    },
+}
+
+impl fmt::Display for ArgsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArgsError::Help(text) | ArgsError::Version(text) => write!(f, "{}", text),
+            ArgsError::UnknownOption(option) => write!(f, "unknown option '{}'", option),
+            ArgsError::MissingValue(option) => write!(f, "option '{}' requires a value", option),
+            ArgsError::UnexpectedValue(option) => {
+                write!(f, "option '{}' doesn't take a value", option)
+            }
+            ArgsError::InvalidValue { option, value } => {
+                write!(f, "invalid value '{}' for option '{}'", value, option)
+            }
+            ArgsError::MissingArgument(name) => write!(f, "missing required argument {}", name),
+            ArgsError::UnexpectedArgument(arg) => write!(f, "unexpected argument '{}'", arg),
+            ArgsError::CommandLineOnly(option) => {
+                write!(
+                    f,
+                    "option '{}' can only be given on the command line",
+                    option
+                )
+            }
+            ArgsError::Requires(option, other) => {
+                write!(f, "option '{}' requires '{}'", option, other)
+            }
+            ArgsError::Conflicts(option, other) => {
+                write!(f, "option '{}' can't be used with '{}'", option, other)
+            }
+            ArgsError::ConfigFile { path, line, error } => {
+                write!(f, "{}:{}: {}", path, line, error)
+            }
+            ArgsError::ConfigUnreadable { path, message } => {
+                write!(f, "can't read config file {}: {}", path, message)
+            }
+        }
+    }
+}
+
+impl Error for ArgsError {}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Parsed {
+    // * 按出现顺序记录 (长选项名, 值)，同一个选项后出现的覆盖先出现的由调用者决定
+    pub options: Vec<(&'static str, Option<String>)>,
+    pub positional: Vec<String>,
+}
+
+/// 解析除程序名以外的参数
+pub fn parse(
+    specs: &'static [OptSpec],
+    mut args: impl Iterator<Item = String>,
+) -> Result<Parsed, ArgsError> {
+    let mut parsed = Parsed::default();
+
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            parsed.positional.extend(args.by_ref());
+            break;
+        }
+
+        if let Some(long) = arg.strip_prefix("--") {
+            let (name, inline) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (long, None),
+            };
+            let spec = match specs.iter().find(|spec| spec.long == name) {
+                Some(spec) => spec,
+                None => return Err(ArgsError::UnknownOption(format!("--{}", name))),
+            };
+            let value = match (spec.value, inline) {
+                (None, None) => None,
+                (None, Some(_)) => return Err(ArgsError::UnexpectedValue(format!("--{}", name))),
+                (Some(_), Some(value)) => Some(value),
+                (Some(_), None) => match args.next() {
+                    Some(value) => Some(value),
+                    None => return Err(ArgsError::MissingValue(format!("--{}", name))),
+                },
+            };
+            parsed.options.push((spec.long, value));
+        } else if arg.len() > 1 && arg.starts_with('-') {
+            // * 合并的短选项：逐个字符处理，遇到带值的选项时剩下的部分就是值
+            let cluster = &arg[1..];
+            for (i, c) in cluster.char_indices() {
+                let spec = match specs.iter().find(|spec| spec.short == Some(c)) {
+                    Some(spec) => spec,
+                    None => return Err(ArgsError::UnknownOption(format!("-{}", c))),
+                };
+                if spec.value.is_none() {
+                    parsed.options.push((spec.long, None));
+                    continue;
+                }
+                let rest = &cluster[i + c.len_utf8()..];
+                let value = if !rest.is_empty() {
+                    rest.to_string()
+                } else {
+                    match args.next() {
+                        Some(value) => value,
+                        None => return Err(ArgsError::MissingValue(format!("-{}", c))),
+                    }
+                };
+                parsed.options.push((spec.long, Some(value)));
+                break;
+            }
+        } else {
+            parsed.positional.push(arg);
+        }
+    }
+
+    Ok(parsed)
+}
+
+/// 解析数字类型的选项值
+pub fn number<T: std::str::FromStr>(option: &str, value: &str) -> Result<T, ArgsError> {
+    value.parse().map_err(|_| ArgsError::InvalidValue {
+        option: format!("--{}", option),
+        value: value.to_string(),
+    })
+}
+
+/// 根据选项表生成帮助文本中的 Options 部分
+pub fn options_help(specs: &[OptSpec]) -> String {
+    let flags: Vec<String> = specs
+        .iter()
+        .map(|spec| {
+            let short = match spec.short {
+                Some(c) => format!("-{}, ", c),
+                None => "    ".to_string(),
+            };
+            match spec.value {
+                Some(value) => format!("{}--{} <{}>", short, spec.long, value),
+                None => format!("{}--{}", short, spec.long),
+            }
+        })
+        .collect();
+    let width = flags.iter().map(|flag| flag.len()).max().unwrap_or(0);
+
+    let mut text = String::from("Options:\n");
+    for (flag, spec) in flags.iter().zip(specs) {
+        text.push_str(&format!(
+            "  {:width$}  {}\n",
+            flag,
+            spec.help,
+            width = width
+        ));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPECS: &[OptSpec] = &[
+        OptSpec {
+            short: Some('i'),
+            long: "ignore-case",
+            value: None,
+            help: "Ignore case",
+        },
+        OptSpec {
+            short: Some('n'),
+            long: "line-number",
+            value: None,
+            help: "Print line numbers",
+        },
+        OptSpec {
+            short: Some('A'),
+            long: "after-context",
+            value: Some("NUM"),
+            help: "Print NUM lines after each match",
+        },
+        OptSpec {
+            short: None,
+            long: "include",
+            value: Some("GLOB"),
+            help: "Only search files matching GLOB",
+        },
+    ];
+
+    fn parse_args(args: &[&str]) -> Result<Parsed, ArgsError> {
+        parse(SPECS, args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn opt(name: &'static str, value: Option<&str>) -> (&'static str, Option<String>) {
+        (name, value.map(String::from))
+    }
+
+    #[test]
+    fn short_long_and_combined() {
+        let parsed = parse_args(&[
+            "-in",
+            "--after-context=2",
+            "-A3",
+            "--include",
+            "*.rs",
+            "to",
+            "-",
+        ])
+        .unwrap();
+        assert_eq!(
+            vec![
+                opt("ignore-case", None),
+                opt("line-number", None),
+                opt("after-context", Some("2")),
+                opt("after-context", Some("3")),
+                opt("include", Some("*.rs")),
+            ],
+            parsed.options
+        );
+        assert_eq!(vec!["to", "-"], parsed.positional);
+
+        let parsed = parse_args(&["-nA", "1", "to"]).unwrap();
+        assert_eq!(
+            vec![opt("line-number", None), opt("after-context", Some("1"))],
+            parsed.options
+        );
+    }
+
+    #[test]
+    fn terminator() {
+        let parsed = parse_args(&["-n", "--", "-i", "--include"]).unwrap();
+        assert_eq!(vec![opt("line-number", None)], parsed.options);
+        assert_eq!(vec!["-i", "--include"], parsed.positional);
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            "unknown option '-x'",
+            parse_args(&["-ix"]).unwrap_err().to_string()
+        );
+        assert_eq!(
+            "unknown option '--colour'",
+            parse_args(&["--colour=always"]).unwrap_err().to_string()
+        );
+        assert_eq!(
+            "option '-A' requires a value",
+            parse_args(&["-A"]).unwrap_err().to_string()
+        );
+        assert_eq!(
+            "option '--ignore-case' doesn't take a value",
+            parse_args(&["--ignore-case=yes"]).unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn help_text() {
+        let expected = "\
+Options:
+  -i, --ignore-case          Ignore case
+  -n, --line-number          Print line numbers
+  -A, --after-context <NUM>  Print NUM lines after each match
+      --include <GLOB>       Only search files matching GLOB
+";
+        assert_eq!(expected, options_help(SPECS));
+    }
+}
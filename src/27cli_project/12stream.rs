@@ -0,0 +1,152 @@
+///* 流式搜索：按行读取，不把整个文件读进内存
+// - 文件名为 - 或者没有给出文件名时读取标准输入，可以放在管道中使用：
+//   $ tail -n 100000 app.log | cargo run -- -n ERROR
+// - 超过 STREAM_THRESHOLD 的大文件也走这条路径，内存占用只和最长的一行有关
+// - 按字节读取每一行，不是合法 UTF-8 的行把非法字节换成 U+FFFD 后再匹配和输出，偏移仍然按原始字节计算
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+
+use crate::output::Printer;
+use crate::Matcher;
+
+// * 超过这个大小的文件不再整个读进内存
+pub const STREAM_THRESHOLD: u64 = 64 * 1024 * 1024;
+
+const BUFFER_SIZE: usize = 64 * 1024;
+
+pub fn open(path: &Path) -> io::Result<BufReader<File>> {
+    Ok(BufReader::with_capacity(BUFFER_SIZE, File::open(path)?))
+}
+
+/// 逐行搜索 `reader`，选中的行交给 `printer` 输出，返回选中的行数
+///
+/// 选中的行数达到 `limit`（-m 等）后不再匹配，输出完剩下的下文就停止读取
+pub fn search_reader<R: BufRead, W: Write>(
+    mut reader: R,
+    matcher: &Matcher,
+    limit: Option<usize>,
+    printer: &mut Printer,
+    out: &mut W,
+    path: &Path,
+) -> io::Result<usize> {
+    printer.begin_file();
+
+    // * 复用同一块缓冲区，每行只在这里读一次
+    let mut buf = Vec::new();
+    let mut number = 0;
+    let mut offset = 0;
+    let mut count = 0;
+    loop {
+        buf.clear();
+        let read = reader.read_until(b'\n', &mut buf)?;
+        if read == 0 {
+            break;
+        }
+        number += 1;
+
+        // 和 numbered_lines 一样去掉行尾的 \n 和 \r\n
+        let mut line = &buf[..];
+        if let Some(rest) = line.strip_suffix(b"\n") {
+            line = rest;
+        }
+        if let Some(rest) = line.strip_suffix(b"\r") {
+            line = rest;
+        }
+        let line = String::from_utf8_lossy(line);
+        let line = line.as_ref();
+
+        let reached = limit.is_some_and(|limit| count >= limit);
+        let matched = !reached && matcher.find(line).is_some();
+        if matched {
+            count += 1;
+        }
+        printer.feed(out, path, number, offset, line, matched)?;
+        offset += read;
+
+        if limit.is_some_and(|limit| count >= limit) && !printer.pending_after_context() {
+            break;
+        }
+    }
+
+    printer.end_file(out, path, count)?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::OutputOptions;
+
+    #[test]
+    fn crlf_and_offsets() {
+        let input = "Rust:\r\nsafe, fast, productive.\r\nPick three.";
+        let options = OutputOptions {
+            byte_offset: true,
+            ..OutputOptions::default()
+        };
+        let matcher = Matcher::Literal("e".to_string());
+        let mut out = Vec::new();
+        let count = search_reader(
+            input.as_bytes(),
+            &matcher,
+            None,
+            &mut Printer::new(options),
+            &mut out,
+            Path::new("-"),
+        )
+        .unwrap();
+
+        assert_eq!(2, count);
+        assert_eq!(
+            "7:safe, fast, productive.\n32:Pick three.\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn max_count_keeps_trailing_context() {
+        let input = "a1\nb\na2\na3\nc\n";
+        let options = OutputOptions {
+            after_context: 1,
+            ..OutputOptions::default()
+        };
+        let matcher = Matcher::Literal("a".to_string());
+        let mut out = Vec::new();
+        let count = search_reader(
+            input.as_bytes(),
+            &matcher,
+            Some(2),
+            &mut Printer::new(options),
+            &mut out,
+            Path::new("-"),
+        )
+        .unwrap();
+
+        assert_eq!(2, count);
+        assert_eq!("a1\nb\na2\na3\n", String::from_utf8(out).unwrap());
+    }
+
+    #[test]
+    fn invalid_utf8() {
+        let matcher = Matcher::Literal("caf".to_string());
+        let options = OutputOptions {
+            byte_offset: true,
+            ..OutputOptions::default()
+        };
+        let mut printer = Printer::new(options);
+        let mut out = Vec::new();
+        // Latin-1 编码的 "café"：显示时换成 U+FFFD，后面一行的偏移仍然按原始字节计算
+        let count = search_reader(
+            &b"caf\xe9\ncafe\n"[..],
+            &matcher,
+            None,
+            &mut printer,
+            &mut out,
+            Path::new("-"),
+        )
+        .unwrap();
+        assert_eq!(2, count);
+        assert_eq!("0:caf\u{fffd}\n5:cafe\n", String::from_utf8(out).unwrap());
+    }
+}
@@ -0,0 +1,245 @@
+///* 使用 ThreadPool 并行搜索多个文件
+// - 每个文件是线程池中的一个任务，结果先写进各自的缓冲区，再通过通道发回主线程
+// - 主线程按输入顺序输出：还没轮到的结果先暂存起来，所以输出和顺序搜索完全一致
+// - * 同时最多只有 jobs * WINDOW 个文件在搜索或者等着输出，前面的一个文件很慢时暂存的结果也不会无限增长
+// - 提前返回（比如 | head 之后写出失败）时设置取消标志，队列中还没开始的任务直接跳过
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::Arc;
+
+use crate::output::{self, OutputMode, OutputOptions, Printer};
+use crate::thread_pool::ThreadPool;
+use crate::{report_error, search_input, Input, Matcher, MinigrepError, Summary};
+
+// 每个线程最多对应多少个已经提交、还没有输出的文件
+const WINDOW: usize = 4;
+
+// drop 时设置取消标志；声明在 ThreadPool 之后，所以先于 ThreadPool 被 drop，join 时剩下的任务不再搜索
+struct Cancel(Arc<AtomicBool>);
+
+impl Drop for Cancel {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+}
+
+/// 用 `jobs` 个线程搜索 `inputs`（不能包含标准输入）
+pub fn search_parallel<W: Write>(
+    inputs: &[Input],
+    matcher: Arc<Matcher>,
+    options: &OutputOptions,
+    jobs: usize,
+    out: &mut W,
+) -> Result<Summary, MinigrepError> {
+    let pool = ThreadPool::with_logging(jobs, false);
+    let cancel = Cancel(Arc::new(AtomicBool::new(false)));
+    let (sender, receiver) = mpsc::channel();
+    let submit = |index: usize| {
+        let (path, walked) = match &inputs[index] {
+            Input::File { path, walked } => (path.clone(), *walked),
+            Input::Stdin => unreachable!("stdin is searched sequentially"),
+        };
+        let sender = sender.clone();
+        let matcher = Arc::clone(&matcher);
+        let options = options.clone();
+        let cancelled = Arc::clone(&cancel.0);
+
+        pool.execute(move || {
+            if cancelled.load(Ordering::SeqCst) {
+                return;
+            }
+            let searched = panic::catch_unwind(AssertUnwindSafe(|| {
+                let input = Input::File { path, walked };
+                let mut buf = Vec::new();
+                let mut printer = Printer::new(options.clone()).highlight(Arc::clone(&matcher));
+                let result = search_input(&input, &matcher, &options, &mut printer, &mut buf);
+                let grouped = printer.printed_group();
+                result.map(|count| (count, buf, grouped))
+            }));
+            // * panic 时也要发回 None，否则主线程会一直等这个结果；
+            // 主线程出错提前返回后接收端会被丢弃，这时发送失败可以忽略
+            let _ = sender.send((index, searched.ok()));
+        });
+    };
+
+    let context = options.mode == OutputMode::Lines
+        && (options.before_context > 0 || options.after_context > 0);
+    let mut summary = Summary::default();
+    let mut pending = HashMap::new();
+    // 下一个要提交的文件
+    let mut submitted = 0;
+    // 是否已经输出过上下文分组
+    let mut printed = false;
+    for (index, input) in inputs.iter().enumerate() {
+        while submitted < inputs.len() && submitted < index + jobs * WINDOW {
+            submit(submitted);
+            submitted += 1;
+        }
+        let result = loop {
+            if let Some(result) = pending.remove(&index) {
+                break result;
+            }
+            // submit 还持有 sender，通道不会断开
+            let (i, result) = receiver.recv().expect("the sender is still alive");
+            pending.insert(i, result);
+        };
+
+        let (buf, grouped) = match result {
+            None => return Err(io::Error::other("a search thread panicked").into()),
+            Some(Ok((count, buf, grouped))) => {
+                summary.add(count);
+                (buf, grouped)
+            }
+            Some(Err(e)) => {
+                report_error(input, e)?;
+                summary.errors += 1;
+                continue;
+            }
+        };
+        // * 每个任务的 Printer 都是新的，顺序搜索时文件之间的 "--" 需要在这里补上；
+        // 只看任务是否输出了上下文分组，"Binary file X matches" 前后不加分隔符
+        if context && grouped && printed {
+            output::write_separator(out, options)?;
+        }
+        out.write_all(&buf)?;
+        printed |= grouped;
+    }
+
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    #[test]
+    fn same_output_as_sequential() {
+        let dir = std::env::temp_dir().join(format!("minigrep_parallel_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let inputs: Vec<Input> = (0..20)
+            .map(|i| {
+                let path: PathBuf = dir.join(format!("{:02}.txt", i));
+                let contents: String = (0..50)
+                    .map(|n| format!("file {} line {}\n", i, n))
+                    .collect();
+                fs::write(&path, contents).unwrap();
+                Input::File { path, walked: true }
+            })
+            .collect();
+        let options = OutputOptions {
+            with_filename: true,
+            line_number: true,
+            after_context: 1,
+            ..OutputOptions::default()
+        };
+        let matcher = Matcher::Literal("line 2".to_string());
+
+        let mut sequential = Vec::new();
+        let mut printer = Printer::new(options.clone());
+        for input in &inputs {
+            search_input(input, &matcher, &options, &mut printer, &mut sequential).unwrap();
+        }
+
+        let mut parallel = Vec::new();
+        let summary =
+            search_parallel(&inputs, Arc::new(matcher), &options, 4, &mut parallel).unwrap();
+        assert!(summary.matched);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(!sequential.is_empty());
+        assert_eq!(
+            String::from_utf8(sequential).unwrap(),
+            String::from_utf8(parallel).unwrap()
+        );
+    }
+
+    #[test]
+    fn binary_files_do_not_add_separators() {
+        let dir =
+            std::env::temp_dir().join(format!("minigrep_parallel_bin_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let files: [(&str, &[u8]); 3] = [
+            ("1.txt", b"a\ntell\nb\n"),
+            ("2.bin", b"tell\0\x01\n"),
+            ("3.txt", b"c\ntell\nd\n"),
+        ];
+        let inputs: Vec<Input> = files
+            .iter()
+            .map(|(name, contents)| {
+                let path = dir.join(name);
+                fs::write(&path, contents).unwrap();
+                Input::File {
+                    path,
+                    walked: false,
+                }
+            })
+            .collect();
+        let options = OutputOptions {
+            with_filename: true,
+            before_context: 1,
+            after_context: 1,
+            ..OutputOptions::default()
+        };
+        let matcher = Matcher::Literal("tell".to_string());
+
+        let mut sequential = Vec::new();
+        let mut printer = Printer::new(options.clone());
+        for input in &inputs {
+            search_input(input, &matcher, &options, &mut printer, &mut sequential).unwrap();
+        }
+        let mut parallel = Vec::new();
+        search_parallel(&inputs, Arc::new(matcher), &options, 4, &mut parallel).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let sequential = String::from_utf8(sequential).unwrap();
+        assert_eq!(1, sequential.matches("--\n").count());
+        assert!(sequential.contains("Binary file"));
+        assert_eq!(sequential, String::from_utf8(parallel).unwrap());
+    }
+
+    // 模拟 | head 已经退出
+    struct ClosedPipe;
+
+    impl Write for ClosedPipe {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn write_errors_stop_the_search() {
+        let dir =
+            std::env::temp_dir().join(format!("minigrep_parallel_pipe_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        // 文件数远多于 jobs * WINDOW，第一次写出失败后其余的文件不会再提交
+        let inputs: Vec<Input> = (0..100)
+            .map(|i| {
+                let path = dir.join(format!("{:03}.txt", i));
+                fs::write(&path, "tell\n").unwrap();
+                Input::File { path, walked: true }
+            })
+            .collect();
+        let matcher = Arc::new(Matcher::Literal("tell".to_string()));
+        let result = search_parallel(
+            &inputs,
+            matcher,
+            &OutputOptions::default(),
+            2,
+            &mut ClosedPipe,
+        );
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(matches!(
+            result,
+            Err(MinigrepError::Io { source, .. }) if source.kind() == io::ErrorKind::BrokenPipe
+        ));
+    }
+}
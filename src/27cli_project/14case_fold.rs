@@ -0,0 +1,100 @@
+///* 忽略大小写的比较：Unicode 大小写折叠（case folding）
+// - 折叠比单纯的小写更进一步：'ß' 和 'ẞ' 折叠成 "ss"，希腊文的词尾 'ς' 折叠成 'σ'，连字 'ﬁ' 折叠成 "fi"
+// - 折叠表由 CaseFolding.txt 生成（14case_fold_table.rs），不是 to_lowercase 的近似
+// - 搜索时逐个字符折叠后和已经折叠好的 query 比较，不需要为每一行分配新的 String
+// - 匹配只能在原始行的字符边界开始和结束，所以区间总是原始行中完整的字符
+use std::str::Chars;
+
+#[path = "14case_fold_table.rs"]
+mod table;
+
+/// 一个字符折叠后的结果，可能不止一个字符
+pub enum Fold {
+    Table(Chars<'static>),
+    Same(Option<char>),
+}
+
+impl Iterator for Fold {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        match self {
+            Fold::Table(chars) => chars.next(),
+            Fold::Same(c) => c.take(),
+        }
+    }
+}
+
+/// 按 CaseFolding.txt 的完全折叠（C + F）折叠一个字符；regex 的 -i 也用它
+pub fn fold_char(c: char) -> Fold {
+    // * ASCII 最常见，不用查表
+    if c.is_ascii() {
+        return Fold::Same(Some(c.to_ascii_lowercase()));
+    }
+    match table::FOLDS.binary_search_by_key(&c, |&(from, _)| from) {
+        Ok(i) => Fold::Table(table::FOLDS[i].1.chars()),
+        Err(_) => Fold::Same(Some(c)),
+    }
+}
+
+/// 折叠整个字符串，只在构建 Matcher 时对 query 调用一次
+pub fn fold_str(s: &str) -> String {
+    s.chars().flat_map(fold_char).collect()
+}
+
+/// 在 `line` 中找第一处折叠后等于 `query` 的区间，`query` 需要已经折叠过
+pub fn find(line: &str, query: &str) -> Option<(usize, usize)> {
+    if query.is_empty() {
+        return Some((0, 0));
+    }
+    line.char_indices()
+        .find_map(|(start, _)| match_len(&line[start..], query).map(|len| (start, start + len)))
+}
+
+// * 从 text 开头逐个字符折叠后和 query 比较，query 正好在某个字符折叠结束时用完才算匹配，返回匹配的字节长度
+fn match_len(text: &str, query: &str) -> Option<usize> {
+    let mut query = query.chars();
+    for (i, c) in text.char_indices() {
+        for folded in fold_char(c) {
+            if query.next() != Some(folded) {
+                return None;
+            }
+        }
+        if query.as_str().is_empty() {
+            return Some(i + c.len_utf8());
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find_str<'a>(line: &'a str, query: &str) -> Option<&'a str> {
+        find(line, &fold_str(query)).map(|(start, end)| &line[start..end])
+    }
+
+    #[test]
+    fn length_changing_folds() {
+        assert_eq!(Some("Straße"), find_str("Die Straße", "STRASSE"));
+        assert_eq!(Some("STRASSE"), find_str("STRASSE", "straße"));
+        assert_eq!(Some("ẞ"), find_str("GROẞ", "ß"));
+        assert_eq!(Some("İSTANBUL"), find_str("xİSTANBUL", "i̇stanbul"));
+        assert_eq!(Some("STAN"), find_str("xİSTANBUL", "stan"));
+        assert_eq!(Some("ﬁle"), find_str("a ﬁle", "FILE"));
+        assert_eq!(Some("ΟΔΟΣ"), find_str("ΟΔΟΣ", "οδος"));
+        // U+1F88 的小写是一个字符 U+1F80，完全折叠则是 "ἀι"
+        assert_eq!("ἀι", fold_str("\u{1f88}"));
+        assert_eq!(Some("\u{1f88}"), find_str("x\u{1f88}", "ἀι"));
+    }
+
+    #[test]
+    fn whole_characters_only() {
+        // "s" 只是 'ß' 折叠结果的一部分，不能匹配半个字符
+        assert_eq!(None, find_str("ß", "s"));
+        assert_eq!(None, find_str("İ", "i"));
+        assert_eq!(Some(""), find_str("abc", ""));
+        assert_eq!(None, find_str("ab", "abc"));
+    }
+}
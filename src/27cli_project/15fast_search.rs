@@ -0,0 +1,291 @@
+///* 整个缓冲区上的快速子串搜索
+// - 单个字面量：先找模式中最少见的那个字节，找到后再比较整个模式；找字节用 SWAR，每次比较 8 个字节
+// - 多个字面量（-e / -f）用 Aho–Corasick：所有模式建成一个自动机，扫描一遍就能找到任意一个模式
+// - 两者都直接在字节上工作；模式本身是合法的 UTF-8，所以找到的位置总在字符边界上
+// - 基准测试见 tests::bench_whole_buffer
+use std::collections::VecDeque;
+
+const LO: u64 = 0x0101_0101_0101_0101;
+const HI: u64 = 0x8080_8080_8080_8080;
+
+// * 英文文本中常见的字节，越靠前越常见；不在表里的字节（大写字母、标点、非 ASCII）当作少见的
+const COMMON: &[u8] = b" etaoinsrhldcumfpgwybvkxjqz\n";
+
+/// 找第一个等于 `needle` 的字节
+///
+/// 一次读 8 个字节异或上 needle，再用 "has zero byte" 的位运算判断其中有没有 0
+pub fn memchr(needle: u8, haystack: &[u8]) -> Option<usize> {
+    let splat = LO * needle as u64;
+    let mut chunks = haystack.chunks_exact(8);
+    for (i, chunk) in chunks.by_ref().enumerate() {
+        let word = u64::from_le_bytes(chunk.try_into().unwrap()) ^ splat;
+        let zero = word.wrapping_sub(LO) & !word & HI;
+        if zero != 0 {
+            // 小端序下最低的置位对应最前面的字节
+            return Some(i * 8 + (zero.trailing_zeros() / 8) as usize);
+        }
+    }
+    let tail = haystack.len() - chunks.remainder().len();
+    chunks
+        .remainder()
+        .iter()
+        .position(|&b| b == needle)
+        .map(|i| tail + i)
+}
+
+/// 单个字面量的查找器
+pub struct Finder<'a> {
+    needle: &'a [u8],
+    // * 模式中最少见的字节的下标，用它做预过滤
+    rare: usize,
+}
+
+impl<'a> Finder<'a> {
+    pub fn new(needle: &'a str) -> Finder<'a> {
+        let needle = needle.as_bytes();
+        let rare = (0..needle.len())
+            .min_by_key(|&i| {
+                COMMON
+                    .iter()
+                    .position(|&b| b == needle[i])
+                    .map_or(0, |pos| COMMON.len() - pos)
+            })
+            .unwrap_or(0);
+        Finder { needle, rare }
+    }
+
+    /// 从 `from` 开始找第一处匹配，返回字节区间
+    pub fn find_at(&self, haystack: &[u8], from: usize) -> Option<(usize, usize)> {
+        let m = self.needle.len();
+        if m == 0 {
+            return (from <= haystack.len()).then_some((from, from));
+        }
+        let rare = self.needle[self.rare];
+        let mut i = from + self.rare;
+        while i < haystack.len() {
+            let pos = i + memchr(rare, &haystack[i..])?;
+            let start = pos - self.rare;
+            if haystack.get(start..start + m) == Some(self.needle) {
+                return Some((start, start + m));
+            }
+            i = pos + 1;
+        }
+        None
+    }
+}
+
+/// Aho–Corasick 多模式匹配，报告最左边的匹配，同一位置开始的取最长的
+pub struct AhoCorasick {
+    // * 把失败链接展开成完整的 DFA，扫描时每个字节只查一次表
+    // 只有模式里出现过的字节需要区分，其余字节归为同一类，表的宽度是字节类的个数
+    classes: Box<[u8; 256]>,
+    stride: usize,
+    table: Vec<u32>,
+    // * 能作为某个模式开头的字节，在根节点时用它快速跳过不可能开始匹配的字节
+    starts: Box<[bool; 256]>,
+    // * 在这个节点结束的所有模式的长度（包括沿失败链接能到达的节点）
+    lengths: Vec<Vec<usize>>,
+    max_len: usize,
+}
+
+impl AhoCorasick {
+    pub fn new<S: AsRef<str>>(patterns: &[S]) -> AhoCorasick {
+        // 先把所有模式插入 trie，子节点用 (字节, 节点) 列表表示
+        let mut children: Vec<Vec<(u8, usize)>> = vec![Vec::new()];
+        let mut lengths: Vec<Vec<usize>> = vec![Vec::new()];
+        let mut classes = Box::new([0u8; 256]);
+        let mut stride = 1;
+        let mut max_len = 0;
+        for pattern in patterns {
+            let pattern = pattern.as_ref().as_bytes();
+            let mut node = 0;
+            for &b in pattern {
+                if classes[b as usize] == 0 {
+                    classes[b as usize] = stride as u8;
+                    stride += 1;
+                }
+                node = match children[node].iter().find(|&&(c, _)| c == b) {
+                    Some(&(_, next)) => next,
+                    None => {
+                        children.push(Vec::new());
+                        lengths.push(Vec::new());
+                        let next = children.len() - 1;
+                        children[node].push((b, next));
+                        next
+                    }
+                };
+            }
+            lengths[node].push(pattern.len());
+            max_len = max_len.max(pattern.len());
+        }
+
+        let mut starts = Box::new([false; 256]);
+        for &(b, _) in &children[0] {
+            starts[b as usize] = true;
+        }
+
+        // * 按层次遍历计算失败链接，同时填好 DFA：没有子节点的转移和失败链接指向的节点相同
+        let mut table = vec![0u32; children.len() * stride];
+        let mut fail = vec![0; children.len()];
+        let mut queue = VecDeque::new();
+        for &(b, next) in &children[0] {
+            table[classes[b as usize] as usize] = next as u32;
+            queue.push_back(next);
+        }
+        while let Some(node) = queue.pop_front() {
+            let (row, fail_row) = (node * stride, fail[node] * stride);
+            for class in 0..stride {
+                table[row + class] = table[fail_row + class];
+            }
+            for &(b, next) in &children[node] {
+                let class = classes[b as usize] as usize;
+                fail[next] = table[fail_row + class] as usize;
+                table[row + class] = next as u32;
+                let inherited = lengths[fail[next]].clone();
+                lengths[next].extend(inherited);
+                queue.push_back(next);
+            }
+        }
+
+        AhoCorasick {
+            classes,
+            stride,
+            table,
+            starts,
+            lengths,
+            max_len,
+        }
+    }
+
+    /// 从 `from` 开始找最左边的匹配，返回字节区间
+    pub fn find_at(&self, haystack: &[u8], from: usize) -> Option<(usize, usize)> {
+        // 空模式在 from 处就匹配，但同一位置可能还有更长的模式
+        let mut best = self.lengths[0].contains(&0).then_some((from, from));
+        let mut node = 0;
+        let mut i = from;
+        while i < haystack.len() {
+            // * 之后结束的匹配最早从 i + 1 - max_len 开始，不可能比已经找到的更靠左
+            if best.is_some_and(|(start, _)| i >= start + self.max_len) {
+                break;
+            }
+            // 在根节点时跳过不可能开始匹配的字节
+            if node == 0 {
+                match haystack[i..].iter().position(|&b| self.starts[b as usize]) {
+                    Some(skip) => i += skip,
+                    None => break,
+                }
+            }
+            let class = self.classes[haystack[i] as usize] as usize;
+            node = self.table[node * self.stride + class] as usize;
+            for &len in &self.lengths[node] {
+                let (start, end) = (i + 1 - len, i + 1);
+                let better = match best {
+                    None => true,
+                    Some((best_start, best_end)) => {
+                        start < best_start || (start == best_start && end > best_end)
+                    }
+                };
+                if better {
+                    best = Some((start, end));
+                }
+            }
+            i += 1;
+        }
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{numbered_lines, search, Matcher};
+    use std::time::Instant;
+
+    #[test]
+    fn swar_memchr() {
+        let haystack = b"safe, fast, productive. Pick three.";
+        for (i, &b) in haystack.iter().enumerate() {
+            assert_eq!(
+                haystack.iter().position(|&x| x == b),
+                memchr(b, haystack),
+                "{}",
+                i
+            );
+        }
+        assert_eq!(None, memchr(b'z', haystack));
+        assert_eq!(Some(0), memchr(0x80, &[0x80]));
+    }
+
+    #[test]
+    fn finder() {
+        let haystack = b"safe, fast, productive. fast";
+        let finder = Finder::new("fast");
+        assert_eq!(Some((6, 10)), finder.find_at(haystack, 0));
+        assert_eq!(Some((24, 28)), finder.find_at(haystack, 7));
+        assert_eq!(None, finder.find_at(haystack, 25));
+        assert_eq!(Some((3, 3)), Finder::new("").find_at(haystack, 3));
+        assert_eq!(Some((0, 4)), Finder::new("safe").find_at(b"safe", 0));
+        assert_eq!(
+            Some((12, 22)),
+            Finder::new("productive").find_at(haystack, 0)
+        );
+    }
+
+    #[test]
+    fn aho_corasick_leftmost_longest() {
+        let ac = AhoCorasick::new(&["bc", "abcd", "ab", "e"]);
+        // "bc" 最先结束，但 "abcd" 开始得更早；同样从 0 开始时取更长的 "abcd"
+        assert_eq!(Some((0, 4)), ac.find_at(b"abcde", 0));
+        assert_eq!(Some((1, 3)), ac.find_at(b"abcde", 1));
+        assert_eq!(Some((4, 5)), ac.find_at(b"abcde", 2));
+        assert_eq!(None, ac.find_at(b"xyz", 0));
+
+        // 失败链接：在 "she" 里找到 "he"
+        let ac = AhoCorasick::new(&["he", "hers", "his", "shx"]);
+        assert_eq!(Some((1, 3)), ac.find_at(b"she", 0));
+        assert_eq!(Some((1, 5)), ac.find_at(b"shers", 0));
+    }
+
+    // * 基准测试：和原来逐行用 str::find 的实现比较
+    // $ cargo test --release -- --ignored --nocapture bench_whole_buffer
+    #[test]
+    #[ignore = "benchmark"]
+    fn bench_whole_buffer() {
+        let line = "Then there's a pair of us - don't tell! They'd banish us, you know.\n";
+        let mut contents = line.repeat(200_000);
+        contents.push_str("How dreary to be somebody! How public, like a frog\n");
+
+        let time = |name: &str, f: &dyn Fn() -> usize| {
+            let start = Instant::now();
+            let count = f();
+            println!("{:<40} {:>8} lines {:>10.2?}", name, count, start.elapsed());
+            count
+        };
+
+        let baseline = time("per-line str::find (search)", &|| {
+            search("frog", &contents).len()
+        });
+        let fast = time("whole-buffer Finder (Matcher::Literal)", &|| {
+            Matcher::Literal("frog".to_string()).search(&contents).len()
+        });
+        assert_eq!(baseline, fast);
+
+        // * 模式越多，逐个模式比较越慢，而 Aho–Corasick 仍然只扫描一遍
+        let words: Vec<String> = (0..100).map(|i| format!("word{}", i)).collect();
+        for count in [4, 100] {
+            let mut patterns = vec!["frog".to_string(), "somebody".to_string()];
+            patterns.extend(words.iter().take(count - 2).cloned());
+            let baseline = time(&format!("per-line str::contains x{}", count), &|| {
+                numbered_lines(&contents)
+                    .filter(|(_, _, line)| patterns.iter().any(|p| line.contains(p.as_str())))
+                    .count()
+            });
+            let fast = time(&format!("whole-buffer Aho-Corasick x{}", count), &|| {
+                Matcher::Multi(AhoCorasick::new(&patterns))
+                    .search(&contents)
+                    .len()
+            });
+            assert_eq!(baseline, fast);
+        }
+    }
+}
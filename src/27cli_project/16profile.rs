@@ -0,0 +1,284 @@
+///* 配置文件和环境变量
+// - 用户配置：$XDG_CONFIG_HOME/minigrep/config，没有设置 XDG_CONFIG_HOME 时为 ~/.config/minigrep/config
+// - 项目配置：从当前目录向上找到的第一个 .minigreprc
+// - 配置文件每行一个命令行选项，# 开头的行是注释；值可以用 = 连接，也可以用空白隔开（值里可以有空格）：
+//   --exclude=target
+//   --exclude target
+//   -A 3
+//   -n
+// - [name] 开始一个 profile，其中的选项只在环境变量 MINIGREP_PROFILE=name 时生效
+// - 优先级（后面的覆盖前面的）：默认值 < 用户配置 < 项目配置 < 环境变量 < 命令行
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::args::{self, ArgsError, OptSpec};
+
+const PROJECT_FILE: &str = ".minigreprc";
+
+// * 这些选项只在命令行上有意义
+const COMMAND_LINE_ONLY: &[&str] = &[
+    "help",
+    "version",
+    "print-config",
+    "no-config",
+    "regexp",
+    "file",
+    "replace",
+    "in-place",
+    "watch",
+    "interactive",
+];
+
+/// 一项设置是从哪里来的
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    Default,
+    User {
+        path: PathBuf,
+        profile: Option<String>,
+    },
+    Project {
+        path: PathBuf,
+        profile: Option<String>,
+    },
+    Env(&'static str),
+    CommandLine,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (kind, path, profile) = match self {
+            Source::Default => return write!(f, "default"),
+            Source::Env(name) => return write!(f, "environment variable {}", name),
+            Source::CommandLine => return write!(f, "command line"),
+            Source::User { path, profile } => ("user config", path, profile),
+            Source::Project { path, profile } => ("project config", path, profile),
+        };
+        write!(f, "{} {}", kind, path.display())?;
+        match profile {
+            Some(profile) => write!(f, " [{}]", profile),
+            None => Ok(()),
+        }
+    }
+}
+
+/// 同一个来源给出的一组选项，按出现顺序排列
+#[derive(Debug, PartialEq)]
+pub struct Layer {
+    pub source: Source,
+    pub options: Vec<(&'static str, Option<String>)>,
+}
+
+/// 读取用户配置和项目配置，按优先级从低到高排列
+pub fn load_files(specs: &'static [OptSpec]) -> Result<Vec<Layer>, ArgsError> {
+    let profile = env::var("MINIGREP_PROFILE").ok();
+    let mut layers = Vec::new();
+    let project = env::current_dir()
+        .ok()
+        .and_then(|dir| find_project_file(&dir));
+    for (path, project) in [(user_file(), false), (project, true)] {
+        let path = match path {
+            Some(path) => path,
+            None => continue,
+        };
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => {
+                return Err(ArgsError::ConfigUnreadable {
+                    path: path.display().to_string(),
+                    message: e.to_string(),
+                })
+            }
+        };
+        layers.extend(parse_file(
+            specs,
+            &path,
+            &text,
+            profile.as_deref(),
+            project,
+        )?);
+    }
+    Ok(layers)
+}
+
+fn user_file() -> Option<PathBuf> {
+    let dir = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(dir.join("minigrep").join("config"))
+}
+
+fn find_project_file(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|dir| dir.join(PROJECT_FILE))
+        .find(|path| path.is_file())
+}
+
+/// 解析一个配置文件：profile 之外的选项一层，选中的 profile 中的选项再一层
+pub fn parse_file(
+    specs: &'static [OptSpec],
+    path: &Path,
+    text: &str,
+    profile: Option<&str>,
+    project: bool,
+) -> Result<Vec<Layer>, ArgsError> {
+    let source = |profile: Option<&str>| {
+        let (path, profile) = (path.to_path_buf(), profile.map(String::from));
+        if project {
+            Source::Project { path, profile }
+        } else {
+            Source::User { path, profile }
+        }
+    };
+    let mut base = Layer {
+        source: source(None),
+        options: Vec::new(),
+    };
+    let mut selected = Layer {
+        source: source(profile),
+        options: Vec::new(),
+    };
+    // * 当前所在的 profile，None 表示还没有遇到 [name]
+    let mut section: Option<&str> = None;
+
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(name) = line
+            .strip_prefix('[')
+            .and_then(|rest| rest.strip_suffix(']'))
+        {
+            section = Some(name.trim());
+            continue;
+        }
+        let in_file = |error| ArgsError::ConfigFile {
+            path: path.display().to_string(),
+            line: i + 1,
+            error: Box::new(error),
+        };
+
+        // 选项和值在第一段空白处分开；--opt=value 的值里本身可能有空格，不再拆分
+        let words = match line.split_once(char::is_whitespace) {
+            Some((option, value)) if !option.contains('=') => {
+                vec![option.to_string(), value.trim_start().to_string()]
+            }
+            _ => vec![line.to_string()],
+        };
+        let parsed = args::parse(specs, words.into_iter()).map_err(in_file)?;
+        if let Some(arg) = parsed.positional.into_iter().next() {
+            return Err(in_file(ArgsError::UnexpectedArgument(arg)));
+        }
+        for (option, value) in parsed.options {
+            if COMMAND_LINE_ONLY.contains(&option) {
+                return Err(in_file(ArgsError::CommandLineOnly(format!("--{}", option))));
+            }
+            match section {
+                None => base.options.push((option, value)),
+                Some(name) if Some(name) == profile => selected.options.push((option, value)),
+                Some(_) => {}
+            }
+        }
+    }
+    Ok([base, selected]
+        .into_iter()
+        .filter(|layer| !layer.options.is_empty())
+        .collect())
+}
+
+/// 环境变量给出的选项，每个变量一层
+pub fn env_layers() -> Vec<Layer> {
+    // $ CASE_INSENSITIVE=1 USE_REGEX=1 cargo run '^Tr?u' poem.txt
+    [("CASE_INSENSITIVE", "ignore-case"), ("USE_REGEX", "regex")]
+        .into_iter()
+        .filter(|(var, _)| env::var(var).is_ok())
+        .map(|(var, option)| Layer {
+            source: Source::Env(var),
+            options: vec![(option, None)],
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::OPTIONS;
+
+    #[test]
+    fn sections_and_comments() {
+        let text = "\
+# 所有 profile 都生效
+--exclude=target
+--exclude  my build
+--include=a b.rs
+-n
+-A 3
+
+[ci]
+--color=never
+
+[local]
+--color=always
+";
+        let path = Path::new("/repo/.minigreprc");
+        let layers = parse_file(OPTIONS, path, text, Some("ci"), true).unwrap();
+        assert_eq!(
+            vec![
+                Layer {
+                    source: Source::Project {
+                        path: path.to_path_buf(),
+                        profile: None
+                    },
+                    options: vec![
+                        ("exclude", Some("target".to_string())),
+                        ("exclude", Some("my build".to_string())),
+                        ("include", Some("a b.rs".to_string())),
+                        ("line-number", None),
+                        ("after-context", Some("3".to_string())),
+                    ],
+                },
+                Layer {
+                    source: Source::Project {
+                        path: path.to_path_buf(),
+                        profile: Some("ci".to_string())
+                    },
+                    options: vec![("color", Some("never".to_string()))],
+                },
+            ],
+            layers
+        );
+
+        let layers = parse_file(OPTIONS, path, text, None, false).unwrap();
+        assert_eq!(1, layers.len());
+        assert_eq!(
+            "user config /repo/.minigreprc",
+            layers[0].source.to_string()
+        );
+    }
+
+    #[test]
+    fn file_errors() {
+        let path = Path::new("config");
+        let error = |text| {
+            parse_file(OPTIONS, path, text, None, false)
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            "config:2: unknown option '--colour'",
+            error("-n\n--colour=never")
+        );
+        assert_eq!("config:1: unexpected argument 'tell'", error("tell"));
+        assert_eq!("config:1: unexpected argument '3'", error("-n 3"));
+        assert_eq!(
+            "config:1: option '--help' can only be given on the command line",
+            error("--help")
+        );
+    }
+}
@@ -0,0 +1,270 @@
+///* 遍历目录时遵守 .gitignore / .ignore
+// - 规则的来源按优先级从低到高：全局忽略文件、搜索根目录上层直到 git 仓库根目录中的忽略文件、遍历时遇到的忽略文件
+//   同一个目录中 .ignore 比 .gitignore 优先；越深的目录越优先，最后一条匹配的规则决定结果
+// - 全局忽略文件为 ~/.gitconfig 中 [core] 的 excludesFile，没有配置时为 $XDG_CONFIG_HOME/git/ignore（默认 ~/.config/git/ignore）
+// - 规则的写法和 git 一样：
+//   target/     只匹配目录
+//   /build      以 / 开头或中间含有 / 的模式相对于忽略文件所在的目录，否则匹配任意深度的文件名
+//   !keep.log   取反：重新包含前面的规则忽略掉的文件（但目录被忽略后不会再进入其中）
+//   \#file      以 \ 转义开头的 # 和 !
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::walk::Glob;
+
+// * 同一个目录中后加载的文件优先
+const IGNORE_FILES: &[&str] = &[".gitignore", ".ignore"];
+
+#[derive(Debug, Clone)]
+struct Rule {
+    glob: Glob,
+    negated: bool,
+    dir_only: bool,
+    // * 模式含有 '/' 时相对于忽略文件所在的目录匹配整个路径
+    anchored: bool,
+    // * 规则对 dir 下面的路径生效，匹配时使用 prefix + 相对于 dir 的路径
+    // 上层目录中的规则 dir 为搜索根目录，prefix 为根目录相对于忽略文件所在目录的路径
+    dir: PathBuf,
+    prefix: PathBuf,
+}
+
+#[derive(Debug, Default)]
+pub struct Ignore {
+    enabled: bool,
+    rules: Vec<Rule>,
+}
+
+impl Ignore {
+    /// 不忽略任何文件（--no-ignore）
+    pub fn disabled() -> Ignore {
+        Ignore::default()
+    }
+
+    /// 开始遍历 `root` 前加载全局忽略文件和上层目录中的忽略文件
+    pub fn for_root(root: &Path) -> Ignore {
+        let mut ignore = Ignore {
+            enabled: true,
+            rules: Vec::new(),
+        };
+        let abs_root = match fs::canonicalize(root) {
+            Ok(path) => path,
+            Err(_) => return ignore,
+        };
+        // * 只有在 git 仓库中才向上查找，上层目录一直到仓库根目录
+        let repo = abs_root.ancestors().find(|dir| dir.join(".git").exists());
+
+        if let Some(path) = global_file() {
+            let prefix = repo.and_then(|repo| abs_root.strip_prefix(repo).ok());
+            if let Ok(text) = fs::read_to_string(&path) {
+                ignore.add(&text, root, prefix.unwrap_or(Path::new("")));
+            }
+        }
+        if let Some(repo) = repo {
+            let mut parents: Vec<&Path> = abs_root
+                .ancestors()
+                .skip(1)
+                .take_while(|dir| dir.starts_with(repo))
+                .collect();
+            parents.reverse();
+            for dir in parents {
+                let prefix = abs_root.strip_prefix(dir).unwrap_or(Path::new(""));
+                for name in IGNORE_FILES {
+                    if let Ok(text) = fs::read_to_string(dir.join(name)) {
+                        ignore.add(&text, root, prefix);
+                    }
+                }
+            }
+        }
+        ignore
+    }
+
+    /// 进入目录 `dir` 时加载其中的忽略文件，返回的值在离开这个目录时交给 leave
+    pub fn enter(&mut self, dir: &Path) -> usize {
+        let mark = self.rules.len();
+        if self.enabled {
+            for name in IGNORE_FILES {
+                if let Ok(text) = fs::read_to_string(dir.join(name)) {
+                    self.add(&text, dir, Path::new(""));
+                }
+            }
+        }
+        mark
+    }
+
+    /// 离开目录：只在这个目录中生效的规则不再需要
+    pub fn leave(&mut self, mark: usize) {
+        self.rules.truncate(mark);
+    }
+
+    /// 添加一个忽略文件中的所有规则
+    pub fn add(&mut self, text: &str, dir: &Path, prefix: &Path) {
+        for line in text.lines() {
+            if let Some(rule) = parse_line(line, dir, prefix) {
+                self.rules.push(rule);
+            }
+        }
+    }
+
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        if is_dir && path.file_name().is_some_and(|name| name == ".git") {
+            return true;
+        }
+        for rule in self.rules.iter().rev() {
+            if rule.dir_only && !is_dir {
+                continue;
+            }
+            let rel = match path.strip_prefix(&rule.dir) {
+                Ok(rel) => rule.prefix.join(rel),
+                Err(_) => continue,
+            };
+            let hit = if rule.anchored {
+                rule.glob.matches_path(&rel)
+            } else {
+                rule.glob.matches(&rel)
+            };
+            if hit {
+                return !rule.negated;
+            }
+        }
+        false
+    }
+}
+
+fn parse_line(line: &str, dir: &Path, prefix: &Path) -> Option<Rule> {
+    let line = line.trim_end_matches('\r');
+    // 行尾的空格被忽略，除非用 \ 转义
+    let line = match line.strip_suffix("\\ ") {
+        Some(rest) => format!("{} ", rest),
+        None => line.trim_end().to_string(),
+    };
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+
+    let (negated, pattern) = match line.strip_prefix('!') {
+        Some(rest) => (true, rest),
+        None => (false, line.as_str()),
+    };
+    let pattern = match pattern.strip_prefix('\\') {
+        Some(rest) if rest.starts_with('#') || rest.starts_with('!') => rest,
+        _ => pattern,
+    };
+    let (dir_only, pattern) = match pattern.strip_suffix('/') {
+        Some(rest) => (true, rest),
+        None => (false, pattern),
+    };
+    if pattern.is_empty() {
+        return None;
+    }
+    let anchored = pattern.contains('/');
+    let pattern = pattern.strip_prefix('/').unwrap_or(pattern);
+
+    Some(Rule {
+        glob: Glob::new(pattern),
+        negated,
+        dir_only,
+        anchored,
+        dir: dir.to_path_buf(),
+        prefix: prefix.to_path_buf(),
+    })
+}
+
+// * ~/.gitconfig 中 [core] 的 excludesFile，没有时使用 git 的默认位置
+fn global_file() -> Option<PathBuf> {
+    let home = env::var_os("HOME").map(PathBuf::from);
+    if let Some(home) = &home {
+        if let Ok(text) = fs::read_to_string(home.join(".gitconfig")) {
+            let mut in_core = false;
+            for line in text.lines().map(str::trim) {
+                if line.starts_with('[') {
+                    in_core = line.eq_ignore_ascii_case("[core]");
+                    continue;
+                }
+                let (key, value) = match line.split_once('=') {
+                    Some(pair) if in_core => pair,
+                    _ => continue,
+                };
+                if key.trim().eq_ignore_ascii_case("excludesfile") {
+                    let value = value.trim();
+                    return Some(match value.strip_prefix("~/") {
+                        Some(rest) => home.join(rest),
+                        None => PathBuf::from(value),
+                    });
+                }
+            }
+        }
+    }
+    let config = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => home?.join(".config"),
+    };
+    Some(config.join("git").join("ignore"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ignore(text: &str) -> Ignore {
+        let mut ignore = Ignore {
+            enabled: true,
+            rules: Vec::new(),
+        };
+        ignore.add(text, Path::new("root"), Path::new(""));
+        ignore
+    }
+
+    #[test]
+    fn patterns() {
+        let ignore = ignore(
+            "\
+# 注释
+*.log
+!keep.log
+target/
+/build
+docs/*.html
+\\#notes
+",
+        );
+        let ignored = |path: &str, is_dir| ignore.is_ignored(Path::new(path), is_dir);
+        assert!(ignored("root/a/debug.log", false));
+        assert!(!ignored("root/a/keep.log", false));
+        assert!(ignored("root/a/target", true));
+        assert!(!ignored("root/a/target", false));
+        assert!(ignored("root/build", true));
+        assert!(!ignored("root/a/build", true));
+        assert!(ignored("root/docs/index.html", false));
+        assert!(!ignored("root/a/docs/index.html", false));
+        assert!(ignored("root/#notes", false));
+        assert!(ignored("root/.git", true));
+        assert!(!ignored("root/src/main.rs", false));
+    }
+
+    #[test]
+    fn nested_files_and_parents() {
+        let mut ignore = ignore("*.txt\n");
+        // 子目录中的规则更优先，离开目录后失效
+        ignore.add("!keep.txt\n", Path::new("root/sub"), Path::new(""));
+        assert!(!ignore.is_ignored(Path::new("root/sub/keep.txt"), false));
+        assert!(ignore.is_ignored(Path::new("root/keep.txt"), false));
+
+        // 上层目录中的规则：搜索根目录 root 是仓库中的 crates/app
+        let mut ignore = Ignore {
+            enabled: true,
+            rules: Vec::new(),
+        };
+        ignore.add(
+            "/crates/app/gen\n",
+            Path::new("root"),
+            Path::new("crates/app"),
+        );
+        assert!(ignore.is_ignored(Path::new("root/gen"), true));
+        assert!(!ignore.is_ignored(Path::new("root/src/gen"), true));
+        assert!(!Ignore::disabled().is_ignored(Path::new("root/.git"), true));
+    }
+}
@@ -0,0 +1,467 @@
+///* --replace：把选中行中的每一处匹配替换成给定的文本
+// - 默认只预览（dry run）：输出统一格式的 diff（unified diff），不修改任何文件
+// - 加上 --in-place 才写回文件：先写到同一目录下的临时文件，再 rename 覆盖原文件，中途出错不会留下写了一半的文件
+// - 正则模式（-E）下替换文本可以引用捕获组：
+//   $0 为整个匹配，$1 .. $9 为对应的分组，${12} 用于多位数的分组，$$ 为 $ 本身
+//   没有参与匹配的分组替换为空；非正则模式下替换文本原样使用
+// - 只替换非空的匹配；-v 选中的行里没有匹配，不会被修改
+// - 遍历目录得到的二进制文件和非 UTF-8 文件不修改：搜索时它们会按字节查找，
+//   这里有匹配时只输出 "Binary file X matches, skipped"，让用户知道它们没有被替换
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::process;
+
+use crate::error::InvalidUtf8;
+use crate::output::OutputOptions;
+use crate::{numbered_lines, report_error, walk, Input, Match, Matcher, MinigrepError, Summary};
+
+// * diff 中每个修改前后保留的上下文行数，和 diff -u 一样
+const CONTEXT: usize = 3;
+
+#[derive(Debug, PartialEq)]
+enum Piece {
+    Text(String),
+    Group(usize),
+}
+
+/// 解析好的替换文本
+#[derive(Debug, PartialEq)]
+pub struct Template {
+    pieces: Vec<Piece>,
+}
+
+impl Template {
+    /// 不做任何展开的替换文本
+    pub fn literal(text: &str) -> Template {
+        Template {
+            pieces: vec![Piece::Text(text.to_string())],
+        }
+    }
+
+    /// 解析 $N / ${N} / $$；不能识别的 $ 原样保留
+    pub fn parse(text: &str) -> Template {
+        let mut pieces = Vec::new();
+        let mut literal = String::new();
+        let mut rest = text;
+        while let Some(pos) = rest.find('$') {
+            literal.push_str(&rest[..pos]);
+            rest = &rest[pos + 1..];
+
+            let (group, len) = if let Some(inner) = rest.strip_prefix('{') {
+                match inner.find('}') {
+                    Some(end) => (inner[..end].parse().ok(), end + 2),
+                    None => (None, 0),
+                }
+            } else {
+                let digit = rest.chars().next().and_then(|c| c.to_digit(10));
+                (digit.map(|d| d as usize), 1)
+            };
+            match group {
+                Some(group) => {
+                    if !literal.is_empty() {
+                        pieces.push(Piece::Text(std::mem::take(&mut literal)));
+                    }
+                    pieces.push(Piece::Group(group));
+                    rest = &rest[len..];
+                }
+                None if rest.starts_with('$') => {
+                    literal.push('$');
+                    rest = &rest[1..];
+                }
+                None => literal.push('$'),
+            }
+        }
+        literal.push_str(rest);
+        if !literal.is_empty() {
+            pieces.push(Piece::Text(literal));
+        }
+        Template { pieces }
+    }
+
+    fn expand(&self, line: &str, groups: &[Option<(usize, usize)>], out: &mut String) {
+        for piece in &self.pieces {
+            match piece {
+                Piece::Text(text) => out.push_str(text),
+                Piece::Group(i) => {
+                    if let Some(&Some((start, end))) = groups.get(*i) {
+                        out.push_str(&line[start..end]);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// 替换一行中所有不重叠的非空匹配
+pub fn replace_line(matcher: &Matcher, template: &Template, line: &str) -> String {
+    let mut replaced = String::with_capacity(line.len());
+    let mut last = 0;
+    let mut at = 0;
+    while let Some(groups) = matcher.captures_at(line, at) {
+        let (start, end) = match groups[0] {
+            Some(span) => span,
+            None => break,
+        };
+        if start < end {
+            replaced.push_str(&line[last..start]);
+            template.expand(line, &groups, &mut replaced);
+            last = end;
+        }
+        // 空匹配时向后移动一个字符，和 Matcher::spans 一样
+        at = match line[end..].chars().next() {
+            _ if end > start => end,
+            Some(c) => end + c.len_utf8(),
+            None => break,
+        };
+    }
+    replaced.push_str(&line[last..]);
+    replaced
+}
+
+/// 一行的修改
+#[derive(Debug, PartialEq)]
+pub struct Change<'a> {
+    pub line_number: usize,
+    pub offset: usize,
+    pub old: &'a str,
+    pub new: String,
+}
+
+/// 对选中的行做替换，只保留真正发生了变化的行
+pub fn changes<'a>(
+    matches: &[Match<'a>],
+    matcher: &Matcher,
+    template: &Template,
+) -> Vec<Change<'a>> {
+    matches
+        .iter()
+        .filter_map(|m| {
+            let new = replace_line(matcher, template, m.line);
+            (new != m.line).then_some(Change {
+                line_number: m.line_number,
+                offset: m.offset,
+                old: m.line,
+                new,
+            })
+        })
+        .collect()
+}
+
+/// 替换后的文件内容；只替换行的内容，行尾的 \n / \r\n 保持不变
+pub fn apply(contents: &str, changes: &[Change]) -> String {
+    let mut result = String::with_capacity(contents.len());
+    let mut last = 0;
+    for change in changes {
+        result.push_str(&contents[last..change.offset]);
+        result.push_str(&change.new);
+        last = change.offset + change.old.len();
+    }
+    result.push_str(&contents[last..]);
+    result
+}
+
+/// 把修改输出成 unified diff，相距不超过 2 × CONTEXT 行的修改合并成一个 hunk
+pub fn write_diff<W: Write>(
+    out: &mut W,
+    path: &Path,
+    contents: &str,
+    changes: &[Change],
+) -> io::Result<()> {
+    if changes.is_empty() {
+        return Ok(());
+    }
+    let lines: Vec<&str> = numbered_lines(contents).map(|(_, _, line)| line).collect();
+    // * 最后一行没有换行符时，和 diff 一样在这一行后面加上说明
+    let missing_newline = |number: usize| number == lines.len() && !contents.ends_with('\n');
+
+    writeln!(out, "--- {}", path.display())?;
+    writeln!(out, "+++ {}", path.display())?;
+    // 前面的 hunk 里替换文本可能带有换行，新文件的行号要加上增加的行数
+    let mut delta = 0isize;
+    let mut rest = changes;
+    while let Some(first) = rest.first() {
+        let mut hunk_len = 1;
+        while hunk_len < rest.len()
+            && rest[hunk_len].line_number <= rest[hunk_len - 1].line_number + 2 * CONTEXT + 1
+        {
+            hunk_len += 1;
+        }
+        let (hunk, next) = rest.split_at(hunk_len);
+        rest = next;
+
+        let from = first.line_number.saturating_sub(CONTEXT).max(1);
+        let to = (hunk[hunk_len - 1].line_number + CONTEXT).min(lines.len());
+        let old_len = to - from + 1;
+        let new_len = old_len
+            + hunk
+                .iter()
+                .map(|c| c.new.matches('\n').count())
+                .sum::<usize>();
+        writeln!(
+            out,
+            "@@ -{},{} +{},{} @@",
+            from,
+            old_len,
+            from as isize + delta,
+            new_len
+        )?;
+        delta += new_len as isize - old_len as isize;
+
+        let mut hunk = hunk.iter().peekable();
+        for number in from..=to {
+            match hunk.next_if(|c| c.line_number == number) {
+                Some(change) => {
+                    writeln!(out, "-{}", change.old)?;
+                    if missing_newline(number) {
+                        writeln!(out, "\\ No newline at end of file")?;
+                    }
+                    for line in change.new.split('\n') {
+                        writeln!(out, "+{}", line)?;
+                    }
+                }
+                None => writeln!(out, " {}", lines[number - 1])?,
+            }
+            if missing_newline(number) {
+                writeln!(out, "\\ No newline at end of file")?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 原子地替换文件内容：写临时文件，同步到磁盘后 rename 到原文件上
+pub fn write_atomic(path: &Path, contents: &str) -> io::Result<()> {
+    // * 符号链接写到它指向的文件上，而不是把链接本身换成普通文件
+    let path = fs::canonicalize(path)?;
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp = path.with_file_name(format!(".{}.minigrep-{}", name, process::id()));
+
+    let result = (|| {
+        let mut file = File::create(&temp)?;
+        file.write_all(contents.as_bytes())?;
+        file.set_permissions(fs::metadata(&path)?.permissions())?;
+        file.sync_all()?;
+        fs::rename(&temp, &path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+/// 对一个输入做替换：预览时输出 diff，`in_place` 时写回文件，返回选中的行数
+pub fn replace_input<W: Write>(
+    input: &Input,
+    matcher: &Matcher,
+    template: &Template,
+    options: &OutputOptions,
+    in_place: bool,
+    out: &mut W,
+) -> io::Result<usize> {
+    let (path, contents) = match input {
+        Input::Stdin if in_place => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "can't edit standard input in place",
+            ))
+        }
+        Input::Stdin => {
+            let mut contents = String::new();
+            io::stdin().read_to_string(&mut contents)?;
+            (input.path(), contents)
+        }
+        Input::File { path, walked } => {
+            let bytes = fs::read(path)?;
+            // * 目录中的二进制文件和非 UTF-8 文件不替换；和搜索不同，写回去会破坏文件，
+            //   所以有匹配时只提示一句跳过了
+            if *walked && (walk::is_binary(&bytes) || std::str::from_utf8(&bytes).is_err()) {
+                if !matcher.search(&String::from_utf8_lossy(&bytes)).is_empty() {
+                    writeln!(out, "Binary file {} matches, skipped", path.display())?;
+                }
+                return Ok(0);
+            }
+            match String::from_utf8(bytes) {
+                Ok(contents) => (path.as_path(), contents),
+                Err(e) => return Err(InvalidUtf8::error(e.utf8_error().valid_up_to())),
+            }
+        }
+    };
+
+    let mut matches = matcher.search(&contents);
+    if let Some(limit) = options.limit() {
+        matches.truncate(limit);
+    }
+    let changes = changes(&matches, matcher, template);
+    if in_place {
+        if !changes.is_empty() {
+            write_atomic(path, &apply(&contents, &changes))?;
+        }
+    } else {
+        write_diff(out, path, &contents, &changes)?;
+    }
+    Ok(matches.len())
+}
+
+/// 按顺序处理所有输入
+pub fn replace_all<W: Write>(
+    inputs: &[Input],
+    matcher: &Matcher,
+    template: &Template,
+    options: &OutputOptions,
+    in_place: bool,
+    out: &mut W,
+) -> Result<Summary, MinigrepError> {
+    let mut summary = Summary::default();
+    for input in inputs {
+        match replace_input(input, matcher, template, options, in_place, out) {
+            Ok(count) => summary.add(count),
+            Err(e) => {
+                report_error(input, e)?;
+                summary.errors += 1;
+            }
+        }
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::regex::Regex;
+
+    fn diff(contents: &str, matcher: &Matcher, template: &Template) -> String {
+        let matches = matcher.search(contents);
+        let mut out = Vec::new();
+        write_diff(
+            &mut out,
+            Path::new("poem.txt"),
+            contents,
+            &changes(&matches, matcher, template),
+        )
+        .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn templates() {
+        let matcher = Matcher::Regex(Regex::new(r"(\w+)@(\w+)").unwrap());
+        let replace =
+            |template: &str| replace_line(&matcher, &Template::parse(template), "a@b, c@d");
+        assert_eq!("b@a, d@c", replace("$2@$1"));
+        assert_eq!("<a@b>, <c@d>", replace("<$0>"));
+        assert_eq!("$a, $c", replace("$$$1"));
+        assert_eq!("a0, c0", replace("${1}0"));
+        // 不存在的分组替换为空，不能识别的 $ 原样保留
+        assert_eq!("$x, $x", replace("$x$9"));
+        // 非正则模式下原样替换
+        let literal = Template::literal("$1");
+        assert_eq!("$1, $1", replace_line(&matcher, &literal, "a@b, c@d"));
+    }
+
+    #[test]
+    fn unified_diff() {
+        let contents =
+            "1\n2\ntell\n4\n5\n6\n7\n8\n9\n10\n11\n12\ntell\n14\n15\n16\n17\n18\n19\n20\n21\ntell";
+        let matcher = Matcher::Literal("tell".to_string());
+        let expected = "\
+--- poem.txt
++++ poem.txt
+@@ -1,6 +1,7 @@
+ 1
+ 2
+-tell
++say
++it
+ 4
+ 5
+ 6
+@@ -10,7 +11,8 @@
+ 10
+ 11
+ 12
+-tell
++say
++it
+ 14
+ 15
+ 16
+@@ -19,4 +21,5 @@
+ 19
+ 20
+ 21
+-tell
+\\ No newline at end of file
++say
++it
+\\ No newline at end of file
+";
+        let template = Template::literal("say\nit");
+        assert_eq!(expected, diff(contents, &matcher, &template));
+
+        let matches = matcher.search(contents);
+        let applied = apply(contents, &changes(&matches, &matcher, &template));
+        assert_eq!(contents.replace("tell", "say\nit"), applied);
+        assert_eq!("", diff("a\r\nb\r\n", &matcher, &template));
+    }
+
+    #[test]
+    fn atomic_write() {
+        let path = std::env::temp_dir().join(format!("minigrep_replace_{}.txt", process::id()));
+        fs::write(&path, "safe, fast\r\nproductive\r\n").unwrap();
+        let input = Input::File {
+            path: path.clone(),
+            walked: false,
+        };
+        let matcher = Matcher::Literal("fast".to_string());
+        let options = OutputOptions::default();
+
+        // 默认只输出 diff，不修改文件
+        let mut out = Vec::new();
+        let template = Template::literal("quick");
+        replace_input(&input, &matcher, &template, &options, false, &mut out).unwrap();
+        assert!(String::from_utf8(out).unwrap().contains("+safe, quick\n"));
+        assert_eq!(
+            "safe, fast\r\nproductive\r\n",
+            fs::read_to_string(&path).unwrap()
+        );
+
+        let mut out = Vec::new();
+        let count = replace_input(&input, &matcher, &template, &options, true, &mut out).unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(1, count);
+        assert!(out.is_empty());
+        assert_eq!("safe, quick\r\nproductive\r\n", contents);
+    }
+
+    #[test]
+    fn walked_binary_files_are_reported() {
+        let path = std::env::temp_dir().join(format!("minigrep_replace_{}.bin", process::id()));
+        fs::write(&path, b"fast\0\xff").unwrap();
+        let input = Input::File {
+            path: path.clone(),
+            walked: true,
+        };
+        let template = Template::literal("quick");
+        let options = OutputOptions::default();
+
+        let mut out = Vec::new();
+        let matcher = Matcher::Literal("fast".to_string());
+        let count = replace_input(&input, &matcher, &template, &options, true, &mut out).unwrap();
+        let contents = fs::read(&path).unwrap();
+        assert_eq!(0, count);
+        assert_eq!(b"fast\0\xff".to_vec(), contents);
+        assert_eq!(
+            format!("Binary file {} matches, skipped\n", path.display()),
+            String::from_utf8(out).unwrap()
+        );
+
+        // 没有匹配时什么都不输出
+        let mut out = Vec::new();
+        let matcher = Matcher::Literal("slow".to_string());
+        replace_input(&input, &matcher, &template, &options, false, &mut out).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(out.is_empty());
+    }
+}
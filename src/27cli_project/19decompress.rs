@@ -0,0 +1,610 @@
+///* 透明解压：按文件开头的魔数识别压缩格式，解压后和普通文件一样搜索
+// - gzip（1f 8b）：日志轮转留下的 .gz；多个 member 首尾相接的文件（cat a.gz b.gz > c.gz）也能完整解压
+// - zlib（78 9c / 78 da）；78 01 和 78 5e 就是文本 "x\x01"、"x^"，只有扩展名是 .zz / .zlib 时才当作 zlib
+// - 两者的数据都是 DEFLATE（RFC 1951），这里自带一个解码器，不依赖外部 crate
+// - 解压是流式的：Decoder 实现了 BufRead，只保留最近 32 KiB 的输出用于回溯引用，大文件也不会整个放进内存
+// - 结束时检查 gzip 的 CRC-32 和长度、zlib 的 Adler-32，数据损坏时返回 InvalidData 错误
+use std::io::{self, BufRead, Read};
+use std::path::Path;
+
+// * DEFLATE 回溯引用最远 32 KiB
+const WINDOW: usize = 32 * 1024;
+const MAX_BITS: usize = 15;
+
+// 长度码 257..285 和距离码 0..29 的基数和额外位数
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+// * 动态 Huffman 块中码长的码长按这个顺序出现
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {
+                0xedb8_8320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+}
+
+pub fn crc32(crc: u32, data: &[u8]) -> u32 {
+    !data.iter().fold(!crc, |c, &b| {
+        CRC_TABLE[((c ^ b as u32) & 0xff) as usize] ^ (c >> 8)
+    })
+}
+
+fn adler32(adler: u32, data: &[u8]) -> u32 {
+    let (mut a, mut b) = (adler & 0xffff, adler >> 16);
+    // 每 5552 个字节取一次模，中间结果不会溢出
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Gzip,
+    Zlib,
+}
+
+impl Format {
+    /// 根据开头的几个字节（和文件名，标准输入时为 None）判断压缩格式，普通文件返回 None
+    pub fn detect(head: &[u8], path: Option<&Path>) -> Option<Format> {
+        // * 78 01 / 78 5e 是压缩级别较低时的 zlib 头部，但 "x^2 + y^2" 这样的文本也以它们开头
+        let zlib_extension = path
+            .and_then(|path| path.extension())
+            .is_some_and(|e| e.eq_ignore_ascii_case("zz") || e.eq_ignore_ascii_case("zlib"));
+        match head {
+            [0x1f, 0x8b, ..] => Some(Format::Gzip),
+            [0x78, 0x9c | 0xda, ..] => Some(Format::Zlib),
+            [0x78, 0x01 | 0x5e, ..] if zlib_extension => Some(Format::Zlib),
+            _ => None,
+        }
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid compressed data: {}", msg),
+    )
+}
+
+// * 按位读取，DEFLATE 从每个字节的最低位开始
+struct Bits<R> {
+    inner: R,
+    buf: u64,
+    count: u32,
+}
+
+impl<R: BufRead> Bits<R> {
+    fn need(&mut self, n: u32) -> io::Result<()> {
+        while self.count < n {
+            let byte = match self.inner.fill_buf()?.first() {
+                Some(&byte) => byte,
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "truncated compressed data",
+                    ))
+                }
+            };
+            self.inner.consume(1);
+            self.buf |= (byte as u64) << self.count;
+            self.count += 8;
+        }
+        Ok(())
+    }
+
+    fn bits(&mut self, n: u32) -> io::Result<u32> {
+        self.need(n)?;
+        let value = (self.buf & ((1 << n) - 1)) as u32;
+        self.buf >>= n;
+        self.count -= n;
+        Ok(value)
+    }
+
+    // 存储块和结尾的校验值从下一个整字节开始
+    fn align(&mut self) {
+        let skip = self.count % 8;
+        self.buf >>= skip;
+        self.count -= skip;
+    }
+
+    // 只在整字节处调用：没有剩下的位，底层也读不出数据
+    fn at_end(&mut self) -> io::Result<bool> {
+        Ok(self.count == 0 && self.inner.fill_buf()?.is_empty())
+    }
+}
+
+// * 规范 Huffman 编码：只需要每种码长的个数和按码排好序的符号
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> io::Result<Huffman> {
+        let mut counts = [0u16; MAX_BITS + 1];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+        // 码字不能超过某个长度能表示的个数
+        let mut left = 1i32;
+        for &count in &counts[1..] {
+            left = (left << 1) - count as i32;
+            if left < 0 {
+                return Err(invalid("over-subscribed Huffman code"));
+            }
+        }
+        let mut offsets = [0u16; MAX_BITS + 2];
+        for len in 1..=MAX_BITS {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0; offsets[MAX_BITS + 1] as usize];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[offsets[len as usize] as usize] = symbol as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+        Ok(Huffman { counts, symbols })
+    }
+
+    // * 逐位读入，同一长度的码字是连续的整数，落在这一段里就找到了符号
+    fn decode<R: BufRead>(&self, bits: &mut Bits<R>) -> io::Result<u16> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for &count in &self.counts[1..] {
+            code |= bits.bits(1)? as i32;
+            let count = count as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(invalid("bad Huffman code"))
+    }
+}
+
+enum State {
+    // * gzip 每个 member 都有自己的头部
+    Header,
+    Block,
+    Stored(usize),
+    Huffman(Box<(Huffman, Huffman)>),
+    Trailer,
+    Done,
+}
+
+/// 解压 `inner` 中的 gzip / zlib 数据
+pub struct Decoder<R> {
+    bits: Bits<R>,
+    format: Format,
+    state: State,
+    // * 当前块是不是最后一块
+    last: bool,
+    members: usize,
+    // * 解压出的数据：[..pos] 已经被读走，其中最近的 WINDOW 字节留着给回溯引用
+    out: Vec<u8>,
+    pos: usize,
+    // * [..checked] 已经计入校验值
+    checked: usize,
+    check: u32,
+    size: u32,
+}
+
+impl<R: BufRead> Decoder<R> {
+    pub fn new(inner: R, format: Format) -> Decoder<R> {
+        Decoder {
+            bits: Bits {
+                inner,
+                buf: 0,
+                count: 0,
+            },
+            format,
+            state: State::Header,
+            last: false,
+            members: 0,
+            out: Vec::new(),
+            pos: 0,
+            checked: 0,
+            check: 0,
+            size: 0,
+        }
+    }
+
+    // * 解压出大约一个窗口的数据，或者直到结束
+    fn fill(&mut self) -> io::Result<()> {
+        if self.pos > 2 * WINDOW {
+            let drop = self.pos - WINDOW;
+            self.out.drain(..drop);
+            self.pos -= drop;
+            self.checked -= drop;
+        }
+        let target = self.out.len() + WINDOW;
+        while self.out.len() < target {
+            self.state = match std::mem::replace(&mut self.state, State::Done) {
+                State::Header => match self.header()? {
+                    true => State::Block,
+                    false => State::Done,
+                },
+                State::Block => self.block_header()?,
+                State::Stored(0) => self.block_end(),
+                State::Stored(len) => {
+                    let byte = self.bits.bits(8)? as u8;
+                    self.out.push(byte);
+                    State::Stored(len - 1)
+                }
+                State::Huffman(tables) => match self.symbol(&tables)? {
+                    true => State::Huffman(tables),
+                    false => self.block_end(),
+                },
+                State::Trailer => self.trailer()?,
+                State::Done => break,
+            };
+        }
+        self.update_check();
+        Ok(())
+    }
+
+    fn update_check(&mut self) {
+        let data = &self.out[self.checked..];
+        self.check = match self.format {
+            Format::Gzip => crc32(self.check, data),
+            Format::Zlib => adler32(self.check, data),
+        };
+        self.size = self.size.wrapping_add(data.len() as u32);
+        self.checked = self.out.len();
+    }
+
+    // 读一个 member 的头部；后面没有数据（或者只有不是 gzip 的填充）时返回 false
+    fn header(&mut self) -> io::Result<bool> {
+        if self.members > 0 && (self.format == Format::Zlib || self.bits.at_end()?) {
+            return Ok(false);
+        }
+        self.members += 1;
+        self.check = match self.format {
+            Format::Gzip => 0,
+            Format::Zlib => 1,
+        };
+        self.size = 0;
+        if self.format == Format::Zlib {
+            let (cmf, flg) = (self.bits.bits(8)?, self.bits.bits(8)?);
+            if cmf & 0x0f != 8 || (cmf << 8 | flg) % 31 != 0 || flg & 0x20 != 0 {
+                return Err(invalid("bad zlib header"));
+            }
+            return Ok(true);
+        }
+
+        if self.bits.bits(16)? != 0x8b1f {
+            // * 和 gzip 一样，第一个 member 之后不认识的数据当作填充忽略
+            if self.members > 1 {
+                return Ok(false);
+            }
+            return Err(invalid("bad gzip header"));
+        }
+        if self.bits.bits(8)? != 8 {
+            return Err(invalid("unknown compression method"));
+        }
+        let flags = self.bits.bits(8)?;
+        // MTIME、XFL、OS
+        for _ in 0..6 {
+            self.bits.bits(8)?;
+        }
+        if flags & 0x04 != 0 {
+            let len = self.bits.bits(16)?;
+            for _ in 0..len {
+                self.bits.bits(8)?;
+            }
+        }
+        // 原文件名和注释，都以 0 结尾
+        for flag in [0x08, 0x10] {
+            if flags & flag != 0 {
+                while self.bits.bits(8)? != 0 {}
+            }
+        }
+        if flags & 0x02 != 0 {
+            self.bits.bits(16)?;
+        }
+        Ok(true)
+    }
+
+    fn block_header(&mut self) -> io::Result<State> {
+        self.last = self.bits.bits(1)? == 1;
+        Ok(match self.bits.bits(2)? {
+            0 => {
+                self.bits.align();
+                let len = self.bits.bits(16)?;
+                if len != !self.bits.bits(16)? & 0xffff {
+                    return Err(invalid("stored block length mismatch"));
+                }
+                State::Stored(len as usize)
+            }
+            1 => State::Huffman(Box::new(fixed_tables()?)),
+            2 => State::Huffman(Box::new(self.dynamic_tables()?)),
+            _ => return Err(invalid("bad block type")),
+        })
+    }
+
+    fn dynamic_tables(&mut self) -> io::Result<(Huffman, Huffman)> {
+        let literals = self.bits.bits(5)? as usize + 257;
+        let distances = self.bits.bits(5)? as usize + 1;
+        let code_lengths = self.bits.bits(4)? as usize + 4;
+        if literals > 286 || distances > 30 {
+            return Err(invalid("too many codes"));
+        }
+        let mut lengths = [0u8; 19];
+        for &i in &CODE_LENGTH_ORDER[..code_lengths] {
+            lengths[i] = self.bits.bits(3)? as u8;
+        }
+        let code = Huffman::new(&lengths)?;
+
+        let mut lengths = Vec::with_capacity(literals + distances);
+        while lengths.len() < literals + distances {
+            let (len, repeat) = match code.decode(&mut self.bits)? {
+                len @ 0..=15 => (len as u8, 1),
+                16 => match lengths.last() {
+                    Some(&prev) => (prev, 3 + self.bits.bits(2)?),
+                    None => return Err(invalid("repeat with no previous length")),
+                },
+                17 => (0, 3 + self.bits.bits(3)?),
+                _ => (0, 11 + self.bits.bits(7)?),
+            };
+            if lengths.len() + repeat as usize > literals + distances {
+                return Err(invalid("too many code lengths"));
+            }
+            lengths.extend(std::iter::repeat_n(len, repeat as usize));
+        }
+        if lengths[256] == 0 {
+            return Err(invalid("missing end-of-block code"));
+        }
+        Ok((
+            Huffman::new(&lengths[..literals])?,
+            Huffman::new(&lengths[literals..])?,
+        ))
+    }
+
+    // 解码一个符号，块结束时返回 false
+    fn symbol(&mut self, tables: &(Huffman, Huffman)) -> io::Result<bool> {
+        let symbol = tables.0.decode(&mut self.bits)? as usize;
+        if symbol < 256 {
+            self.out.push(symbol as u8);
+            return Ok(true);
+        }
+        if symbol == 256 {
+            return Ok(false);
+        }
+        let i = symbol - 257;
+        if i >= LENGTH_BASE.len() {
+            return Err(invalid("bad length code"));
+        }
+        let len = LENGTH_BASE[i] as usize + self.bits.bits(LENGTH_EXTRA[i] as u32)? as usize;
+        let i = tables.1.decode(&mut self.bits)? as usize;
+        if i >= DIST_BASE.len() {
+            return Err(invalid("bad distance code"));
+        }
+        let dist = DIST_BASE[i] as usize + self.bits.bits(DIST_EXTRA[i] as u32)? as usize;
+        if dist > self.out.len() {
+            return Err(invalid("distance too far back"));
+        }
+        // * 距离可以比长度短（重复最近的几个字节），只能逐字节复制
+        for _ in 0..len {
+            self.out.push(self.out[self.out.len() - dist]);
+        }
+        Ok(true)
+    }
+
+    fn block_end(&self) -> State {
+        if self.last {
+            State::Trailer
+        } else {
+            State::Block
+        }
+    }
+
+    fn trailer(&mut self) -> io::Result<State> {
+        self.update_check();
+        self.bits.align();
+        let ok = match self.format {
+            Format::Gzip => {
+                let crc = self.bits.bits(16)? | self.bits.bits(16)? << 16;
+                let size = self.bits.bits(16)? | self.bits.bits(16)? << 16;
+                crc == self.check && size == self.size
+            }
+            Format::Zlib => {
+                let mut adler = 0;
+                for _ in 0..4 {
+                    adler = adler << 8 | self.bits.bits(8)?;
+                }
+                adler == self.check
+            }
+        };
+        if !ok {
+            return Err(invalid("checksum mismatch"));
+        }
+        Ok(State::Header)
+    }
+}
+
+impl<R: BufRead> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for Decoder<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos == self.out.len() {
+            self.fill()?;
+        }
+        Ok(&self.out[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.out.len());
+    }
+}
+
+// * 固定 Huffman 块使用 RFC 1951 3.2.6 中规定的码长
+fn fixed_tables() -> io::Result<(Huffman, Huffman)> {
+    let mut lengths = [0u8; 288];
+    for (symbol, len) in lengths.iter_mut().enumerate() {
+        *len = match symbol {
+            0..=143 => 8,
+            144..=255 => 9,
+            256..=279 => 7,
+            _ => 8,
+        };
+    }
+    Ok((Huffman::new(&lengths)?, Huffman::new(&[5; 30])?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POEM: &str = "\
+I'm nobody! Who are you?
+Are you nobody, too?
+Then there's a pair of us - don't tell!
+They'd banish us, you know.
+
+How dreary to be somebody!
+How public, like a frog
+To tell your name the livelong day
+To an admiring bog!";
+
+    // $ gzip -9 poem.txt：动态 Huffman 块
+    const POEM_GZ: &[u8] = &[
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0x2d, 0x8e, 0xb1, 0x0e, 0x83,
+        0x30, 0x0c, 0x44, 0xf7, 0x7c, 0xc5, 0x31, 0xb1, 0xd0, 0xfe, 0x02, 0xea, 0xd6, 0xee, 0x48,
+        0x9d, 0x9d, 0xc6, 0x40, 0x44, 0x88, 0x2b, 0x13, 0x8a, 0xf2, 0xf7, 0x4d, 0x68, 0x37, 0xeb,
+        0xee, 0xe9, 0xf9, 0x1e, 0xed, 0x8a, 0x28, 0x56, 0x5c, 0x6e, 0xf0, 0x9c, 0x05, 0xa4, 0x8c,
+        0x2c, 0x7b, 0x6f, 0x6e, 0xbf, 0xe3, 0x5f, 0x76, 0x48, 0x22, 0xbd, 0x19, 0x66, 0x8e, 0x48,
+        0x33, 0x2b, 0xb7, 0x1b, 0x08, 0x6f, 0xf2, 0x0a, 0x19, 0xb1, 0x6f, 0xb8, 0xc0, 0x49, 0x6c,
+        0x13, 0x12, 0x87, 0xd0, 0x54, 0x2e, 0xb7, 0x0e, 0x96, 0xa2, 0xdf, 0xe6, 0x52, 0x77, 0xa7,
+        0x6a, 0x89, 0x72, 0x5c, 0x8d, 0xb9, 0xcb, 0x01, 0xa7, 0x4c, 0x9a, 0x8b, 0x14, 0x96, 0xb1,
+        0xc9, 0xca, 0xe7, 0x82, 0xb3, 0x7a, 0xef, 0x36, 0xf8, 0x57, 0x87, 0xe0, 0x17, 0x2e, 0x3f,
+        0x46, 0x95, 0xc9, 0x0c, 0x72, 0x8a, 0xab, 0x45, 0x11, 0x69, 0xe5, 0x3a, 0xa2, 0x10, 0x1f,
+        0x0e, 0x12, 0x27, 0x38, 0xca, 0x15, 0xa1, 0x08, 0x72, 0xab, 0x57, 0x5f, 0x22, 0x2b, 0x53,
+        0xf3, 0x05, 0x5e, 0x90, 0x2b, 0xba, 0xdc, 0x00, 0x00, 0x00,
+    ];
+
+    // zlib 压缩的短文本：固定 Huffman 块
+    const RUST_ZLIB: &[u8] = &[
+        0x78, 0xda, 0x0b, 0x2a, 0x2d, 0x2e, 0xb1, 0xe2, 0x2a, 0x4e, 0x4c, 0x4b, 0xd5, 0x51, 0x48,
+        0x4b, 0x2c, 0x2e, 0xd1, 0x51, 0x28, 0x28, 0xca, 0x4f, 0x29, 0x4d, 0x2e, 0xc9, 0x2c, 0x4b,
+        0xd5, 0xe3, 0x0a, 0xc8, 0x4c, 0xce, 0x56, 0x28, 0xc9, 0x28, 0x4a, 0x4d, 0xd5, 0x83, 0xca,
+        0x82, 0x48, 0x00, 0xdc, 0xf3, 0x12, 0x0a,
+    ];
+
+    fn decompress(data: &[u8]) -> io::Result<String> {
+        let format = Format::detect(data, None).expect("compressed data");
+        let mut text = String::new();
+        Decoder::new(data, format).read_to_string(&mut text)?;
+        Ok(text)
+    }
+
+    // 只用存储块的 gzip，用来构造多个 member 和超过窗口大小的数据
+    fn stored_gzip(data: &[u8]) -> Vec<u8> {
+        let mut gz = vec![0x1f, 0x8b, 8, 0x08, 0, 0, 0, 0, 0, 3];
+        gz.extend(b"name.txt\0");
+        let chunks: Vec<&[u8]> = data.chunks(0xffff).collect();
+        for (i, chunk) in chunks.iter().enumerate() {
+            gz.push((i + 1 == chunks.len()) as u8);
+            let len = chunk.len() as u16;
+            gz.extend(len.to_le_bytes());
+            gz.extend((!len).to_le_bytes());
+            gz.extend(*chunk);
+        }
+        gz.extend(crc32(0, data).to_le_bytes());
+        gz.extend((data.len() as u32).to_le_bytes());
+        gz
+    }
+
+    #[test]
+    fn huffman_blocks() {
+        assert_eq!(Some(Format::Gzip), Format::detect(POEM_GZ, None));
+        assert_eq!(None, Format::detect(b"x marks the spot", None));
+        assert_eq!(None, Format::detect(b"x^2 + y^2 = tell", None));
+        assert_eq!(
+            None,
+            Format::detect(b"x^2 + y^2 = tell", Some(Path::new("math.txt")))
+        );
+        assert_eq!(
+            Some(Format::Zlib),
+            Format::detect(&[0x78, 0x5e, 0x03, 0x00], Some(Path::new("data.ZZ")))
+        );
+        assert_eq!(POEM, decompress(POEM_GZ).unwrap());
+        assert_eq!(
+            "Rust:\nsafe, fast, productive.\nPick three. fast, fast",
+            decompress(RUST_ZLIB).unwrap()
+        );
+    }
+
+    #[test]
+    fn members_and_stored_blocks() {
+        let big = "safe, fast, productive.\n".repeat(10_000);
+        let mut gz = stored_gzip(big.as_bytes());
+        gz.extend(POEM_GZ);
+        // 最后的填充被忽略
+        gz.extend([0; 8]);
+        assert_eq!(format!("{}{}", big, POEM), decompress(&gz).unwrap());
+    }
+
+    #[test]
+    fn corrupt_data() {
+        let mut gz = POEM_GZ.to_vec();
+        let len = gz.len();
+        gz[len - 8] ^= 1;
+        let err = decompress(&gz).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+        assert_eq!(
+            "invalid compressed data: checksum mismatch",
+            err.to_string()
+        );
+
+        let err = decompress(&POEM_GZ[..40]).unwrap_err();
+        assert_eq!(io::ErrorKind::UnexpectedEof, err.kind());
+    }
+}
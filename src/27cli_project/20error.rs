@@ -0,0 +1,155 @@
+///* minigrep 的错误类型
+// - run 不再返回 Box<dyn Error>，而是 MinigrepError，调用者可以按种类处理
+// - 每种错误对应一个退出码，main 用它退出：
+//   2 用法错误（选项、参数、配置文件），3 模式错误，4 I/O 错误，5 输入不是合法的 UTF-8
+// - I/O 错误带上出错的文件；底层的 io::Error 通过 source() 取得
+// - 搜索时非法的 UTF-8 按字节处理，不算错误；-f 的模式文件和 --replace 要修改的文件必须是合法的 UTF-8
+//   读取代码只认识 io::Error，所以先包在 io::Error 里（InvalidUtf8），到 run 中再还原成 MinigrepError::InvalidUtf8
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::args::ArgsError;
+use crate::regex::ParseError;
+
+#[derive(Debug)]
+pub enum MinigrepError {
+    Usage(ArgsError),
+    // * path 为 None 表示不是某个输入文件的错误，比如写标准输出失败
+    Io {
+        path: Option<PathBuf>,
+        source: io::Error,
+    },
+    // * offset 为第一个非法字节在文件中的偏移
+    InvalidUtf8 {
+        path: PathBuf,
+        offset: usize,
+    },
+    Pattern(ParseError),
+}
+
+impl MinigrepError {
+    /// 读取 `path` 时的错误；io::Error 中包着的 InvalidUtf8 还原成 MinigrepError::InvalidUtf8
+    pub fn io(path: &Path, source: io::Error) -> MinigrepError {
+        match source
+            .get_ref()
+            .and_then(|e| e.downcast_ref::<InvalidUtf8>())
+        {
+            Some(InvalidUtf8 { offset }) => MinigrepError::InvalidUtf8 {
+                path: path.to_path_buf(),
+                offset: *offset,
+            },
+            None => MinigrepError::Io {
+                path: Some(path.to_path_buf()),
+                source,
+            },
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            MinigrepError::Usage(_) => 2,
+            MinigrepError::Pattern(_) => 3,
+            MinigrepError::Io { .. } => 4,
+            MinigrepError::InvalidUtf8 { .. } => 5,
+        }
+    }
+}
+
+impl fmt::Display for MinigrepError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MinigrepError::Usage(e) => write!(f, "{}", e),
+            MinigrepError::Io {
+                path: Some(path),
+                source,
+            } => write!(f, "{}: {}", path.display(), source),
+            MinigrepError::Io { path: None, source } => write!(f, "{}", source),
+            MinigrepError::InvalidUtf8 { path, offset } => write!(
+                f,
+                "{}: invalid UTF-8 at byte offset {}",
+                path.display(),
+                offset
+            ),
+            MinigrepError::Pattern(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for MinigrepError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MinigrepError::Usage(e) => Some(e),
+            MinigrepError::Io { source, .. } => Some(source),
+            MinigrepError::InvalidUtf8 { .. } => None,
+            MinigrepError::Pattern(e) => Some(e),
+        }
+    }
+}
+
+impl From<ArgsError> for MinigrepError {
+    fn from(e: ArgsError) -> MinigrepError {
+        MinigrepError::Usage(e)
+    }
+}
+
+impl From<ParseError> for MinigrepError {
+    fn from(e: ParseError) -> MinigrepError {
+        MinigrepError::Pattern(e)
+    }
+}
+
+impl From<io::Error> for MinigrepError {
+    fn from(source: io::Error) -> MinigrepError {
+        MinigrepError::Io { path: None, source }
+    }
+}
+
+/// 输入中的非法 UTF-8，包在 io::Error 里穿过只认识 io::Result 的读取代码
+#[derive(Debug)]
+pub struct InvalidUtf8 {
+    pub offset: usize,
+}
+
+impl InvalidUtf8 {
+    pub fn error(offset: usize) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, InvalidUtf8 { offset })
+    }
+}
+
+impl fmt::Display for InvalidUtf8 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid UTF-8 at byte offset {}", self.offset)
+    }
+}
+
+impl Error for InvalidUtf8 {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kinds_and_exit_codes() {
+        let path = Path::new("poem.txt");
+        let missing = io::Error::new(io::ErrorKind::NotFound, "not found");
+        let err = MinigrepError::io(path, missing);
+        assert_eq!("poem.txt: not found", err.to_string());
+        assert_eq!(4, err.exit_code());
+        assert!(err.source().is_some());
+
+        let err = MinigrepError::io(path, InvalidUtf8::error(12));
+        assert_eq!("poem.txt: invalid UTF-8 at byte offset 12", err.to_string());
+        assert_eq!(5, err.exit_code());
+
+        let err = MinigrepError::from(ArgsError::MissingArgument("QUERY"));
+        assert_eq!(2, err.exit_code());
+        let err = MinigrepError::from(crate::build_regex("(rust", true).unwrap_err());
+        assert_eq!(3, err.exit_code());
+        assert_eq!(
+            "regex parse error at position 0: unclosed group",
+            err.source().unwrap().to_string()
+        );
+    }
+}
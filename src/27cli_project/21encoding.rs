@@ -0,0 +1,73 @@
+///* 输入的字符编码
+// - 以 BOM 开头的 UTF-16 文件（FF FE 小端，FE FF 大端）先转成 UTF-8 再搜索，行号和 -b 的偏移都按转换后的文本计算
+// - 其他输入按字节逐行处理：不是合法 UTF-8 的行在匹配和显示时把非法的字节换成 U+FFFD，-b 的偏移仍然是原始文件中的
+// - 开头含有 NUL 的文件当作二进制文件，怎么处理由 output::BinaryMode 决定
+use std::io::{self, BufRead};
+
+/// 按 BOM 识别 UTF-16，返回转换后的文本；不是 UTF-16 时返回 None
+///
+/// 不成对的代理项换成 U+FFFD，最后多出的半个字符丢掉
+pub fn decode_utf16(bytes: &[u8]) -> Option<String> {
+    let (body, big_endian) = match bytes {
+        [0xff, 0xfe, rest @ ..] => (rest, false),
+        [0xfe, 0xff, rest @ ..] => (rest, true),
+        _ => return None,
+    };
+    let units = body.chunks_exact(2).map(|pair| {
+        let pair = [pair[0], pair[1]];
+        if big_endian {
+            u16::from_be_bytes(pair)
+        } else {
+            u16::from_le_bytes(pair)
+        }
+    });
+    Some(
+        char::decode_utf16(units)
+            .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+            .collect(),
+    )
+}
+
+/// 流式输入的开头是 UTF-16 BOM 时读完剩下的内容并转换
+pub fn read_utf16<R: BufRead>(mut reader: R) -> io::Result<Option<String>> {
+    if decode_utf16(reader.fill_buf()?).is_none() {
+        return Ok(None);
+    }
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    Ok(decode_utf16(&bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16(text: &str, big_endian: bool) -> Vec<u8> {
+        let mut bytes = if big_endian {
+            vec![0xfe, 0xff]
+        } else {
+            vec![0xff, 0xfe]
+        };
+        for unit in text.encode_utf16() {
+            if big_endian {
+                bytes.extend(unit.to_be_bytes());
+            } else {
+                bytes.extend(unit.to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    #[test]
+    fn byte_order_marks() {
+        let text = "Rust:\r\nsafe, fast 🦀\n";
+        assert_eq!(Some(text.to_string()), decode_utf16(&utf16(text, false)));
+        assert_eq!(Some(text.to_string()), decode_utf16(&utf16(text, true)));
+        assert_eq!(None, decode_utf16(text.as_bytes()));
+        // 单独的高位代理项
+        assert_eq!(
+            Some("a\u{fffd}".to_string()),
+            decode_utf16(&[0xff, 0xfe, b'a', 0, 0x3d, 0xd8])
+        );
+    }
+}
@@ -0,0 +1,385 @@
+///* --watch：一直运行，文件增长时只输出新增的选中行，相当于对多个文件做 tail -f | grep
+// - 不依赖系统的文件通知，每隔 POLL_INTERVAL 检查一次每个文件的大小和修改时间
+// - 启动时从每个文件的末尾开始；之后新出现的文件（包括目录中新建的文件）从头开始
+// - 日志轮转：
+//   文件被改名、在原来的路径上建了新文件（inode 变了）时，先读完旧文件剩下的内容，再从头读新文件
+//   文件被截断时从头开始读：大小比已经读到的位置小，或者已经读过的最后几个字节变了
+//   （copytruncate 之后很快又写入了更多内容，大小可能并不比原来小）
+// - 文件被删除（或者轮转时暂时不存在）时读完旧文件剩下的内容，然后关闭它
+// - 最后一行还没有写完（没有换行符）时先留着，等下一次读到换行符再匹配
+// - 行号、上下文、-b 的偏移都和普通搜索一样；-m 不起作用，--json 时不输出 end 和 summary
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File, Metadata};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::fast::memchr;
+use crate::output::{OutputOptions, Printer};
+use crate::walk::{self, WalkOptions};
+use crate::Matcher;
+
+pub const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+// 记住已经读过的最后多少个字节，用来发现文件被截断后又重新写入
+const TAIL: usize = 64;
+
+// * 正在跟踪的一个文件
+struct Watched {
+    file: File,
+    id: Option<(u64, u64)>,
+    // * 已经从 file 读到的位置
+    offset: u64,
+    modified: Option<SystemTime>,
+    // offset 之前的最后 TAIL 个字节
+    tail: Vec<u8>,
+    // * 还没有遇到换行符的最后一行，以及它在文件中的偏移
+    partial: Vec<u8>,
+    line_offset: usize,
+    line_number: usize,
+    printer: Printer,
+}
+
+pub struct Watcher {
+    roots: Vec<PathBuf>,
+    walk: WalkOptions,
+    matcher: Arc<Matcher>,
+    options: OutputOptions,
+    files: BTreeMap<PathBuf, Watched>,
+    started: bool,
+}
+
+impl Watcher {
+    /// `roots` 为命令行给出的文件和目录，目录每次轮询时重新遍历
+    pub fn new(
+        roots: Vec<PathBuf>,
+        walk: WalkOptions,
+        matcher: Arc<Matcher>,
+        options: OutputOptions,
+    ) -> Watcher {
+        Watcher {
+            roots,
+            walk,
+            matcher,
+            options,
+            files: BTreeMap::new(),
+            started: false,
+        }
+    }
+
+    /// 一直轮询，只在写输出失败时返回
+    pub fn run<W: Write>(mut self, out: &mut W) -> io::Error {
+        loop {
+            if let Err(e) = self.poll(out) {
+                return e;
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// 检查一遍所有文件，输出新增的行，返回新增的选中行数
+    pub fn poll<W: Write>(&mut self, out: &mut W) -> io::Result<usize> {
+        let mut count = 0;
+        let mut present = BTreeSet::new();
+        for path in self.list() {
+            // 轮转的过程中文件可能暂时不存在，下一次再看
+            let meta = match fs::metadata(&path) {
+                Ok(meta) if meta.is_file() => meta,
+                _ => continue,
+            };
+            match self.check(&path, &meta, out) {
+                Ok(n) => count += n,
+                Err(e) if e.kind() == io::ErrorKind::BrokenPipe => return Err(e),
+                Err(e) => eprintln!("minigrep: {}: {}", path.display(), e),
+            }
+            present.insert(path);
+        }
+        // * 不存在的文件不再跟踪，否则一直占着文件描述符；之后再出现时当作新文件从头读
+        let removed: Vec<PathBuf> = self
+            .files
+            .keys()
+            .filter(|path| !present.contains(*path))
+            .cloned()
+            .collect();
+        for path in removed {
+            let mut watched = self.files.remove(&path).unwrap();
+            eprintln!("minigrep: {}: file removed", path.display());
+            match self.read(&path, &mut watched, out) {
+                Ok(n) => count += n,
+                Err(e) if e.kind() == io::ErrorKind::BrokenPipe => return Err(e),
+                Err(e) => eprintln!("minigrep: {}: {}", path.display(), e),
+            }
+        }
+        self.started = true;
+        out.flush()?;
+        Ok(count)
+    }
+
+    fn list(&self) -> Vec<PathBuf> {
+        let mut paths = Vec::new();
+        for root in &self.roots {
+            if root.is_dir() {
+                match walk::walk(root, &self.walk) {
+                    Ok(files) => paths.extend(files),
+                    Err(e) => eprintln!("minigrep: {}: {}", root.display(), e),
+                }
+            } else {
+                paths.push(root.clone());
+            }
+        }
+        paths
+    }
+
+    fn check<W: Write>(&mut self, path: &Path, meta: &Metadata, out: &mut W) -> io::Result<usize> {
+        let mut count = 0;
+        let mut watched = match self.files.remove(path) {
+            Some(mut watched) => {
+                if watched.id != file_id(meta) {
+                    // * 旧文件被改名或删除：读完它剩下的内容，再换成新文件
+                    count += self.read(path, &mut watched, out)?;
+                    eprintln!(
+                        "minigrep: {}: file replaced, following the new file",
+                        path.display()
+                    );
+                    self.open(path, false)?
+                } else if changed(&watched, meta) && truncated(&mut watched, meta)? {
+                    eprintln!("minigrep: {}: file truncated", path.display());
+                    watched.file.seek(SeekFrom::Start(0))?;
+                    watched.offset = 0;
+                    watched.tail.clear();
+                    watched.partial.clear();
+                    watched.line_offset = 0;
+                    watched.line_number = 0;
+                    watched.printer.begin_file();
+                    watched
+                } else {
+                    watched
+                }
+            }
+            // 第一次轮询时已经存在的文件从末尾开始
+            None => self.open(path, !self.started)?,
+        };
+        if changed(&watched, meta) {
+            watched.modified = meta.modified().ok();
+            count += self.read(path, &mut watched, out)?;
+        }
+        self.files.insert(path.to_path_buf(), watched);
+        Ok(count)
+    }
+
+    fn open(&self, path: &Path, at_end: bool) -> io::Result<Watched> {
+        let file = File::open(path)?;
+        let meta = file.metadata()?;
+        let mut watched = Watched {
+            file,
+            id: file_id(&meta),
+            offset: 0,
+            modified: None,
+            tail: Vec::new(),
+            partial: Vec::new(),
+            line_offset: 0,
+            line_number: 0,
+            printer: Printer::new(self.options.clone()).highlight(Arc::clone(&self.matcher)),
+        };
+        watched.printer.begin_file();
+        if at_end {
+            // * 从末尾开始也要知道已经有多少行，-n 的行号才对得上；
+            //   分块数换行符，不把整个文件读进内存，只留下最后一行没写完的部分
+            let mut buf = vec![0; 64 * 1024];
+            loop {
+                let n = match watched.file.read(&mut buf) {
+                    Ok(0) => break,
+                    Ok(n) => n,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e),
+                };
+                let chunk = &buf[..n];
+                match chunk.iter().rposition(|&b| b == b'\n') {
+                    Some(i) => {
+                        watched.line_number += chunk[..=i].iter().filter(|&&b| b == b'\n').count();
+                        watched.line_offset = watched.offset as usize + i + 1;
+                        watched.partial.clear();
+                        watched.partial.extend_from_slice(&chunk[i + 1..]);
+                    }
+                    None => watched.partial.extend_from_slice(chunk),
+                }
+                watched.offset += n as u64;
+                remember(&mut watched.tail, chunk);
+            }
+            watched.modified = meta.modified().ok();
+        }
+        Ok(watched)
+    }
+
+    // 读入新增的数据，匹配其中完整的行
+    fn read<W: Write>(&self, path: &Path, watched: &mut Watched, out: &mut W) -> io::Result<usize> {
+        let old = watched.partial.len();
+        let read = watched.file.read_to_end(&mut watched.partial)?;
+        watched.offset += read as u64;
+        remember(&mut watched.tail, &watched.partial[old..]);
+
+        let mut count = 0;
+        let mut start = 0;
+        while let Some(i) = memchr(b'\n', &watched.partial[start..]) {
+            let end = start + i;
+            let mut line = &watched.partial[start..end];
+            if let Some(rest) = line.strip_suffix(b"\r") {
+                line = rest;
+            }
+            let line = String::from_utf8_lossy(line);
+            watched.line_number += 1;
+            let matched = self.matcher.find(&line).is_some();
+            if matched {
+                count += 1;
+            }
+            watched.printer.feed(
+                out,
+                path,
+                watched.line_number,
+                watched.line_offset,
+                &line,
+                matched,
+            )?;
+            watched.line_offset += end + 1 - start;
+            start = end + 1;
+        }
+        watched.partial.drain(..start);
+        Ok(count)
+    }
+}
+
+fn changed(watched: &Watched, meta: &Metadata) -> bool {
+    meta.len() != watched.offset || meta.modified().ok() != watched.modified
+}
+
+// 同一个文件被截断过：变短了，或者 offset 之前的内容和上次读到的不一样了
+fn truncated(watched: &mut Watched, meta: &Metadata) -> io::Result<bool> {
+    if meta.len() < watched.offset {
+        return Ok(true);
+    }
+    let mut before = vec![0; watched.tail.len()];
+    watched
+        .file
+        .seek(SeekFrom::Start(watched.offset - before.len() as u64))?;
+    let result = watched.file.read_exact(&mut before);
+    watched.file.seek(SeekFrom::Start(watched.offset))?;
+    match result {
+        Ok(()) => Ok(before != watched.tail),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(true),
+        Err(e) => Err(e),
+    }
+}
+
+fn remember(tail: &mut Vec<u8>, data: &[u8]) {
+    if data.len() >= TAIL {
+        tail.clear();
+        tail.extend_from_slice(&data[data.len() - TAIL..]);
+    } else {
+        tail.extend_from_slice(data);
+        let excess = tail.len().saturating_sub(TAIL);
+        tail.drain(..excess);
+    }
+}
+
+// * 用 (设备号, inode) 判断路径上是不是换了一个文件；其他平台上只能靠截断检测
+#[cfg(unix)]
+fn file_id(meta: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((meta.dev(), meta.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(_meta: &Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::OpenOptions;
+
+    fn append(path: &Path, text: &str) {
+        let mut file = OpenOptions::new().append(true).open(path).unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn new_lines_rotation_and_truncation() {
+        let dir = std::env::temp_dir().join(format!("minigrep_watch_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app.log");
+        fs::write(&path, "old ERROR\nok\n").unwrap();
+
+        let options = OutputOptions {
+            line_number: true,
+            ..OutputOptions::default()
+        };
+        let matcher = Arc::new(Matcher::Literal("ERROR".to_string()));
+        let mut watcher =
+            Watcher::new(vec![path.clone()], WalkOptions::default(), matcher, options);
+        let poll = |watcher: &mut Watcher| {
+            let mut out = Vec::new();
+            let count = watcher.poll(&mut out).unwrap();
+            (count, String::from_utf8(out).unwrap())
+        };
+
+        // 已有的内容不输出；没写完的行等到换行符出现再匹配
+        assert_eq!((0, String::new()), poll(&mut watcher));
+        append(&path, "ok\nnew ERR");
+        assert_eq!((0, String::new()), poll(&mut watcher));
+        append(&path, "OR\n");
+        assert_eq!((1, "4:new ERROR\n".to_string()), poll(&mut watcher));
+
+        // 轮转：旧文件最后写入的行也要输出，然后从头读新文件
+        append(&path, "last ERROR\n");
+        fs::rename(&path, dir.join("app.log.1")).unwrap();
+        fs::write(&path, "first ERROR\n").unwrap();
+        assert_eq!(
+            (2, "5:last ERROR\n1:first ERROR\n".to_string()),
+            poll(&mut watcher)
+        );
+
+        // 截断
+        fs::write(&path, "").unwrap();
+        poll(&mut watcher);
+        append(&path, "again ERROR\n");
+        let result = poll(&mut watcher);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!((1, "1:again ERROR\n".to_string()), result);
+    }
+
+    #[test]
+    fn refilled_and_removed_files() {
+        let dir = std::env::temp_dir().join(format!("minigrep_watch_gone_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app.log");
+        fs::write(&path, "a\nb\n").unwrap();
+
+        let matcher = Arc::new(Matcher::Literal("ERROR".to_string()));
+        let options = OutputOptions {
+            line_number: true,
+            ..OutputOptions::default()
+        };
+        let mut watcher = Watcher::new(vec![dir.clone()], WalkOptions::default(), matcher, options);
+        let mut out = Vec::new();
+        assert_eq!(0, watcher.poll(&mut out).unwrap());
+
+        // copytruncate 之后马上写入了更长的内容：大小没有变小，也要从头读
+        fs::write(&path, "x ERROR\ny\nz ERROR\n").unwrap();
+        assert_eq!(2, watcher.poll(&mut out).unwrap());
+
+        // 删除之前写入的行照样输出，然后不再跟踪这个文件
+        append(&path, "last ERROR\n");
+        fs::remove_file(&path).unwrap();
+        assert_eq!(1, watcher.poll(&mut out).unwrap());
+        let tracked = watcher.files.len();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(0, tracked);
+        assert_eq!(
+            "1:x ERROR\n3:z ERROR\n4:last ERROR\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+}
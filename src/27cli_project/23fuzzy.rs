@@ -0,0 +1,199 @@
+///* 近似匹配：允许 query 和文本之间有最多 K 处编辑（插入、删除、替换一个字符），即 Levenshtein 距离
+// - 在一行中找和 query 距离最小的子串（Sellers 算法）：按列动态规划，第 0 行全为 0，表示匹配可以从任意位置开始
+// - 每个格子同时记下这条路径的起点，找到终点后不需要回溯就知道匹配的区间
+// - 取一行中距离最小的匹配，距离相同时取终点最靠左的；同一终点取起点最靠右的，也就是更短的匹配
+// - whole_line 时整行参与比较（-x），第 0 行为 0, 1, 2, ...
+// - words 时（-w）匹配只能从单词的开头开始、在单词的结尾结束：不是单词开头的位置第 0 行为无穷大
+//   单词字符和 -w、\w 一样是 regex::is_word_char（字母、数字和下划线）
+// - 按字符计算，区间是原始行中的字节区间
+// - 忽略大小写时 query 和行都按 fold::fold_char 完全折叠后再比较，和 -i 一样 'ß' 与 "ss" 相同；
+//   编辑次数按折叠后的字符计算，匹配的区间扩展到完整的原始字符
+use std::cmp::Reverse;
+
+use crate::fold;
+use crate::regex::is_word_char;
+
+/// 一处近似匹配，distance 为编辑次数，越小越接近
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FuzzyMatch {
+    pub start: usize,
+    pub end: usize,
+    pub distance: usize,
+}
+
+pub struct Fuzzy {
+    query: Vec<char>,
+    max_edits: usize,
+    case_sensitive: bool,
+    whole_line: bool,
+    words: bool,
+}
+
+impl Fuzzy {
+    pub fn new(query: &str, max_edits: usize, case_sensitive: bool) -> Fuzzy {
+        let query = if case_sensitive {
+            query.chars().collect()
+        } else {
+            fold::fold_str(query).chars().collect()
+        };
+        Fuzzy {
+            query,
+            max_edits,
+            case_sensitive,
+            whole_line: false,
+            words: false,
+        }
+    }
+
+    /// 要求整行和 query 的距离不超过 max_edits
+    pub fn whole_line(mut self) -> Fuzzy {
+        self.whole_line = true;
+        self
+    }
+
+    /// 匹配的前后都不能是单词字符
+    pub fn words(mut self) -> Fuzzy {
+        self.words = true;
+        self
+    }
+
+    /// 从 `at` 开始找距离最小的匹配，超过 max_edits 时返回 None
+    pub fn find_at(&self, line: &str, at: usize) -> Option<FuzzyMatch> {
+        if self.whole_line && at > 0 {
+            return None;
+        }
+        // * (原始字符的起点, 终点, 字符)；忽略大小写时一个原始字符可能折叠成几个字符
+        let mut text: Vec<(usize, usize, char)> = Vec::new();
+        for (i, c) in line[at..].char_indices() {
+            let (start, end) = (at + i, at + i + c.len_utf8());
+            if self.case_sensitive {
+                text.push((start, end, c));
+            } else {
+                text.extend(fold::fold_char(c).map(|folded| (start, end, folded)));
+            }
+        }
+        // 字符下标转成字节偏移：起点取所在原始字符的开头，终点取前一个字符所在原始字符的结尾
+        let start_byte = |j: usize| text.get(j).map_or(line.len(), |&(i, _, _)| i);
+        let end_byte = |j: usize| {
+            j.checked_sub(1)
+                .and_then(|j| text.get(j))
+                .map_or(at, |&(_, i, _)| i)
+        };
+        // 第 j 个字符之前是不是单词边界（-w 时匹配只能从这里开始、在这里结束）
+        let boundary = |j: usize, after: bool| {
+            let c = if after {
+                text.get(j)
+            } else {
+                j.checked_sub(1).and_then(|j| text.get(j))
+            };
+            !self.words || !c.is_some_and(|&(_, _, c)| is_word_char(c))
+        };
+
+        // * column[i] 为 (query 前 i 个字符的最小编辑次数, 匹配起点的字符下标)
+        let mut column: Vec<(usize, usize)> = (0..=self.query.len()).map(|i| (i, 0)).collect();
+        let mut best = (usize::MAX, 0, 0);
+        if boundary(0, true) {
+            best.0 = column[self.query.len()].0;
+        }
+        for (j, &(_, _, c)) in text.iter().enumerate() {
+            let mut diagonal = column[0];
+            column[0] = if self.whole_line {
+                (j + 1, 0)
+            } else if boundary(j + 1, false) {
+                (0, j + 1)
+            } else {
+                (usize::MAX, j + 1)
+            };
+            for i in 1..=self.query.len() {
+                let cost = usize::from(self.query[i - 1] != c);
+                let candidates = [
+                    (diagonal.0.saturating_add(cost), diagonal.1),
+                    (column[i - 1].0.saturating_add(1), column[i - 1].1),
+                    (column[i].0.saturating_add(1), column[i].1),
+                ];
+                diagonal = column[i];
+                column[i] = candidates
+                    .into_iter()
+                    .min_by_key(|&(cost, start)| (cost, Reverse(start)))
+                    .unwrap();
+            }
+            let (distance, start) = column[self.query.len()];
+            if self.whole_line || (distance < best.0 && boundary(j + 1, true)) {
+                best = (distance, start, j + 1);
+            }
+        }
+
+        let (distance, start, end) = best;
+        (distance <= self.max_edits).then(|| FuzzyMatch {
+            start: start_byte(start),
+            end: end_byte(end),
+            distance,
+        })
+    }
+}
+
+/// 两个字符串之间的 Levenshtein 距离
+pub fn distance(a: &str, b: &str) -> usize {
+    Fuzzy::new(a, usize::MAX, true)
+        .whole_line()
+        .find_at(b, 0)
+        .map_or(0, |m| m.distance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edit_distance() {
+        assert_eq!(3, distance("kitten", "sitting"));
+        assert_eq!(0, distance("", ""));
+        assert_eq!(4, distance("rust", ""));
+        assert_eq!(1, distance("naïve", "naive"));
+    }
+
+    #[test]
+    fn closest_substring() {
+        let fuzzy = Fuzzy::new("productive", 2, true);
+        let line = "safe, fast, prodcutive.";
+        let m = fuzzy.find_at(line, 0).unwrap();
+        assert_eq!(2, m.distance);
+        assert_eq!("prodcutive", &line[m.start..m.end]);
+        assert_eq!(None, Fuzzy::new("productive", 1, true).find_at(line, 0));
+
+        // 取距离最小的，距离相同时取最靠左的
+        let fuzzy = Fuzzy::new("tell", 1, true);
+        let m = fuzzy.find_at("tall tell", 0).unwrap();
+        assert_eq!((5, 9, 0), (m.start, m.end, m.distance));
+        let m = fuzzy.find_at("tall toll", 0).unwrap();
+        assert_eq!((0, 4, 1), (m.start, m.end, m.distance));
+
+        let fuzzy = Fuzzy::new("RUST", 1, false);
+        assert_eq!(0, fuzzy.find_at("Trust me.", 0).unwrap().distance);
+        let fuzzy = Fuzzy::new("rust", 1, true).whole_line();
+        assert!(fuzzy.find_at("Rust", 0).is_some());
+        assert!(fuzzy.find_at("Rust!", 0).is_none());
+
+        let fuzzy = Fuzzy::new("tell", 1, true).words();
+        let m = fuzzy.find_at("telling, tall", 0).unwrap();
+        assert_eq!((9, 13, 1), (m.start, m.end, m.distance));
+        assert!(fuzzy.find_at("tellers", 0).is_none());
+    }
+
+    #[test]
+    fn case_folding() {
+        // 和 -i 一样按完全折叠比较
+        let fuzzy = Fuzzy::new("strasse", 0, false);
+        let line = "Große Straße!";
+        let m = fuzzy.find_at(line, 0).unwrap();
+        assert_eq!("Straße", &line[m.start..m.end]);
+        let fuzzy = Fuzzy::new("ΣΟΦΟΣ", 0, false);
+        assert!(fuzzy.find_at("σοφος", 0).is_some());
+        assert!(Fuzzy::new("ΣΟΦΟΣ", 0, true).find_at("σοφος", 0).is_none());
+
+        // 匹配在折叠出的字符中间结束时，区间包含整个原始字符
+        let fuzzy = Fuzzy::new("gros", 0, false);
+        let m = fuzzy.find_at(line, 0).unwrap();
+        assert_eq!("Groß", &line[m.start..m.end]);
+    }
+}
@@ -0,0 +1,618 @@
+///* --interactive：在终端里浏览搜索结果
+// - 不依赖外部 crate：用 stty 把终端切到 raw 模式，用 ANSI 转义序列画界面，输入输出都走 /dev/tty
+//   所以标准输出被重定向时也能用；退出时（包括出错时）由 RawMode 的 Drop 恢复终端
+// - 第一行是 query，输入时立即重新过滤；中间按文件分组列出选中的行；下面预览选中行前后的内容
+// - 按键：
+//   ↑ / ↓ / Ctrl-P / Ctrl-N 移动，PgUp / PgDn 翻页，Backspace 删除一个字符，Ctrl-U 清空 query
+//   Enter 用 $VISUAL 或 $EDITOR（默认 vi）打开选中的文件并跳到那一行，Esc / Ctrl-C 退出
+// - query 按命令行的设置匹配（-i、-E、-w、-x、--fuzzy 等都有效），只用第一个模式
+// - 所有文件启动时读进内存，二进制文件跳过；从编辑器返回后重新读取刚编辑的文件
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::decompress::{Decoder, Format};
+use crate::{encoding, numbered_lines, report_error, walk, Config, Input, Matcher, MinigrepError};
+
+// * 结果太多时（比如 query 为空）只列出前面这些，保证每次按键后都能很快刷新
+pub const MAX_HITS: usize = 10_000;
+
+const TTY: &str = "/dev/tty";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Key {
+    Char(char),
+    Backspace,
+    ClearQuery,
+    Enter,
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Quit,
+}
+
+/// 把一次读到的字节解析成按键；不认识的转义序列和控制字符丢掉
+///
+/// 单独的 ESC 是退出键，方向键等转义序列一般会一次读到
+pub fn parse_keys(bytes: &[u8]) -> Vec<Key> {
+    let mut keys = Vec::new();
+    let mut rest = bytes;
+    while let Some(&byte) = rest.first() {
+        let (key, len) = match rest {
+            [0x1b, b'[', b'A', ..] | [0x1b, b'O', b'A', ..] => (Some(Key::Up), 3),
+            [0x1b, b'[', b'B', ..] | [0x1b, b'O', b'B', ..] => (Some(Key::Down), 3),
+            [0x1b, b'[', b'5', b'~', ..] => (Some(Key::PageUp), 4),
+            [0x1b, b'[', b'6', b'~', ..] => (Some(Key::PageDown), 4),
+            // 其他 CSI 序列：跳到结尾的字母为止
+            [0x1b, b'[', tail @ ..] => {
+                let end = tail.iter().position(|b| (0x40..=0x7e).contains(b));
+                (None, 2 + end.map_or(tail.len(), |i| i + 1))
+            }
+            [0x1b, ..] | [0x03, ..] => (Some(Key::Quit), 1),
+            [b'\r' | b'\n', ..] => (Some(Key::Enter), 1),
+            [0x7f | 0x08, ..] => (Some(Key::Backspace), 1),
+            [0x15, ..] => (Some(Key::ClearQuery), 1),
+            [0x10, ..] => (Some(Key::Up), 1),
+            [0x0e, ..] => (Some(Key::Down), 1),
+            _ if byte < 0x20 => (None, 1),
+            _ => {
+                // 一个 UTF-8 字符；不完整或者非法时丢掉这个字节
+                let len = match byte {
+                    0xc0..=0xdf => 2,
+                    0xe0..=0xef => 3,
+                    0xf0..=0xf7 => 4,
+                    _ => 1,
+                };
+                let c = rest
+                    .get(..len)
+                    .and_then(|bytes| std::str::from_utf8(bytes).ok())
+                    .and_then(|s| s.chars().next());
+                match c {
+                    Some(c) => (Some(Key::Char(c)), len),
+                    None => (None, 1),
+                }
+            }
+        };
+        keys.extend(key);
+        rest = &rest[len.min(rest.len())..];
+    }
+    keys
+}
+
+/// 按键之后要做的事
+#[derive(Debug, PartialEq)]
+pub enum Action {
+    Redraw,
+    Open(PathBuf, usize),
+    Quit,
+}
+
+// 一个读进内存的文件
+struct Source {
+    path: PathBuf,
+    contents: String,
+    lines: Vec<String>,
+}
+
+impl Source {
+    fn new(path: PathBuf, contents: String) -> Source {
+        let lines = numbered_lines(&contents)
+            .map(|(_, _, line)| line.to_string())
+            .collect();
+        Source {
+            path,
+            contents,
+            lines,
+        }
+    }
+}
+
+/// 一个选中的行：文件下标、行号和第一处匹配在行内的区间
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hit {
+    pub file: usize,
+    pub line_number: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+// 列表中的一行：文件名或者选中的行（hits 的下标）
+enum Row {
+    File(usize),
+    Hit(usize),
+}
+
+pub struct Browser {
+    config: Config,
+    sources: Vec<Source>,
+    query: String,
+    hits: Vec<Hit>,
+    // * 模式有语法错误时显示在 query 后面，列表保留上一次的结果
+    error: Option<String>,
+    selected: usize,
+    // * 列表第一行对应的 Row 下标
+    scroll: usize,
+    // * 列表区域的行数，由最近一次 render 决定，翻页时使用
+    page: usize,
+}
+
+impl Browser {
+    pub fn new(config: Config, files: Vec<(PathBuf, String)>) -> Browser {
+        let query = config.patterns.first().cloned().unwrap_or_default();
+        let mut browser = Browser {
+            config,
+            sources: files
+                .into_iter()
+                .map(|(path, contents)| Source::new(path, contents))
+                .collect(),
+            query,
+            hits: Vec::new(),
+            error: None,
+            selected: 0,
+            scroll: 0,
+            page: 10,
+        };
+        browser.refilter();
+        browser
+    }
+
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    pub fn hits(&self) -> &[Hit] {
+        &self.hits
+    }
+
+    // * 用当前的 query 重新搜索所有文件
+    fn refilter(&mut self) {
+        self.config.patterns = vec![self.query.clone()];
+        let matcher = match Matcher::new(&self.config) {
+            Ok(matcher) => matcher,
+            Err(e) => {
+                self.error = Some(e.to_string());
+                return;
+            }
+        };
+        self.error = None;
+        self.hits.clear();
+        for (file, source) in self.sources.iter().enumerate() {
+            let remaining = MAX_HITS - self.hits.len();
+            self.hits.extend(
+                matcher
+                    .search(&source.contents)
+                    .into_iter()
+                    .take(remaining)
+                    .map(|m| Hit {
+                        file,
+                        line_number: m.line_number,
+                        start: m.start,
+                        end: m.end,
+                    }),
+            );
+            if self.hits.len() == MAX_HITS {
+                break;
+            }
+        }
+        self.selected = 0;
+        self.scroll = 0;
+    }
+
+    /// 编辑器修改了文件之后重新读取它
+    fn reload(&mut self, path: &Path) {
+        if let Some(source) = self.sources.iter_mut().find(|source| source.path == path) {
+            if let Ok(Some(contents)) = load(path) {
+                *source = Source::new(path.to_path_buf(), contents);
+            }
+        }
+        let selected = self.selected;
+        self.refilter();
+        self.selected = selected.min(self.hits.len().saturating_sub(1));
+    }
+
+    pub fn handle(&mut self, key: Key) -> Action {
+        let last = self.hits.len().saturating_sub(1);
+        match key {
+            Key::Char(c) => {
+                self.query.push(c);
+                self.refilter();
+            }
+            Key::Backspace => {
+                if self.query.pop().is_some() {
+                    self.refilter();
+                }
+            }
+            Key::ClearQuery => {
+                self.query.clear();
+                self.refilter();
+            }
+            Key::Up => self.selected = self.selected.saturating_sub(1),
+            Key::Down => self.selected = (self.selected + 1).min(last),
+            Key::PageUp => self.selected = self.selected.saturating_sub(self.page),
+            Key::PageDown => self.selected = (self.selected + self.page).min(last),
+            Key::Enter => {
+                if let Some(hit) = self.hits.get(self.selected) {
+                    let path = self.sources[hit.file].path.clone();
+                    return Action::Open(path, hit.line_number);
+                }
+            }
+            Key::Quit => return Action::Quit,
+        }
+        Action::Redraw
+    }
+
+    fn rows(&self) -> Vec<Row> {
+        let mut rows = Vec::new();
+        for (i, hit) in self.hits.iter().enumerate() {
+            if i == 0 || self.hits[i - 1].file != hit.file {
+                rows.push(Row::File(hit.file));
+            }
+            rows.push(Row::Hit(i));
+        }
+        rows
+    }
+
+    /// 画出整个界面，每个元素是屏幕上的一行（已经截断到 `width` 个字符）
+    pub fn render(&mut self, width: usize, height: usize) -> Vec<String> {
+        let height = height.max(3);
+        // 第一行 query，最后一行状态；终端够高时留三分之一给预览
+        let preview = if height >= 12 { (height - 2) / 3 } else { 0 };
+        let list = height - 2 - preview;
+        self.page = list.max(1);
+
+        let mut screen = Vec::with_capacity(height);
+        let mut prompt = format!("> {}", self.query);
+        if let Some(error) = &self.error {
+            prompt.push_str(&format!("  ({})", error));
+        }
+        screen.push(truncate(&prompt, width));
+
+        // * 保证选中的行在列表里可见；向上滚动时顺便露出它所在文件的文件名
+        let rows = self.rows();
+        let selected_row = rows
+            .iter()
+            .position(|row| matches!(row, Row::Hit(i) if *i == self.selected))
+            .unwrap_or(0);
+        if selected_row < self.scroll + 1 {
+            self.scroll = selected_row.saturating_sub(1);
+        } else if selected_row >= self.scroll + list {
+            self.scroll = selected_row + 1 - list;
+        }
+        for row in rows.iter().skip(self.scroll).take(list) {
+            screen.push(match *row {
+                Row::File(file) => format!(
+                    "\x1b[1;35m{}\x1b[0m",
+                    truncate(&self.sources[file].path.to_string_lossy(), width)
+                ),
+                Row::Hit(i) => self.hit_row(i, width),
+            });
+        }
+        screen.resize(1 + list, String::new());
+
+        if preview > 0 {
+            self.render_preview(&mut screen, preview, width);
+        }
+
+        let mut status = format!(
+            "{} matching lines in {} files",
+            self.hits.len(),
+            self.file_count()
+        );
+        if self.hits.len() == MAX_HITS {
+            status.push_str(&format!(" (showing the first {})", MAX_HITS));
+        }
+        status.push_str(" | ↑↓ move, Enter open in $EDITOR, Esc quit");
+        screen.push(format!(
+            "\x1b[7m{}\x1b[0m",
+            pad(&truncate(&status, width), width)
+        ));
+        screen
+    }
+
+    fn file_count(&self) -> usize {
+        let mut files: Vec<usize> = self.hits.iter().map(|hit| hit.file).collect();
+        files.dedup();
+        files.len()
+    }
+
+    // 选中的行反显，匹配的部分用红色
+    fn hit_row(&self, i: usize, width: usize) -> String {
+        let hit = self.hits[i];
+        let line = &self.sources[hit.file].lines[hit.line_number - 1];
+        let prefix = format!("{:>6}: ", hit.line_number);
+        let room = width.saturating_sub(prefix.chars().count());
+        let before = truncate(&line[..hit.start], room);
+        let matched = truncate(&line[hit.start..hit.end], room - before.chars().count());
+        let after = truncate(
+            &line[hit.end..],
+            room - before.chars().count() - matched.chars().count(),
+        );
+        let (on, off) = if i == self.selected {
+            ("\x1b[7m", "\x1b[27m")
+        } else {
+            ("", "")
+        };
+        format!(
+            "{}{}{}\x1b[1;31m{}\x1b[22;39m{}{}\x1b[0m",
+            on, prefix, before, matched, after, off
+        )
+    }
+
+    fn render_preview(&self, screen: &mut Vec<String>, rows: usize, width: usize) {
+        let hit = match self.hits.get(self.selected) {
+            Some(hit) => *hit,
+            None => {
+                screen.resize(screen.len() + rows, String::new());
+                return;
+            }
+        };
+        let source = &self.sources[hit.file];
+        let title = format!("── {}:{} ", source.path.display(), hit.line_number);
+        let rule = "─".repeat(width.saturating_sub(title.chars().count()));
+        screen.push(format!(
+            "\x1b[2m{}\x1b[0m",
+            truncate(&(title + &rule), width)
+        ));
+
+        // 选中的行放在预览区域的中间
+        let lines = rows - 1;
+        let first = (hit.line_number - 1).saturating_sub(lines / 2);
+        for number in first..first + lines {
+            screen.push(match source.lines.get(number) {
+                Some(line) => {
+                    let marker = if number + 1 == hit.line_number {
+                        '>'
+                    } else {
+                        ' '
+                    };
+                    let text = format!("{}{:>6}  {}", marker, number + 1, line);
+                    truncate(&text, width)
+                }
+                None => String::new(),
+            });
+        }
+    }
+}
+
+// 按字符截断，制表符换成空格，避免把界面撑乱
+fn truncate(text: &str, width: usize) -> String {
+    text.chars()
+        .map(|c| if c == '\t' { ' ' } else { c })
+        .filter(|c| !c.is_control())
+        .take(width)
+        .collect()
+}
+
+fn pad(text: &str, width: usize) -> String {
+    format!("{:width$}", text, width = width)
+}
+
+// * 终端的 raw 模式：创建时用 stty 保存原来的设置并关闭回显和行缓冲，Drop 时恢复
+struct RawMode {
+    saved: String,
+}
+
+impl RawMode {
+    fn enable() -> io::Result<RawMode> {
+        let saved = stty(&["-g"])?;
+        stty(&["raw", "-echo"])?;
+        Ok(RawMode {
+            saved: saved.trim().to_string(),
+        })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = stty(&[&self.saved]);
+    }
+}
+
+fn stty(args: &[&str]) -> io::Result<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(File::open(TTY)?)
+        .stderr(Stdio::inherit())
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!("stty {} failed", args.join(" "))));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+// * 每次重画前取一次终端大小，窗口大小改变后下一次按键就会按新的大小画
+fn terminal_size() -> (usize, usize) {
+    let size = stty(&["size"]).unwrap_or_default();
+    let mut numbers = size.split_whitespace().map(|n| n.parse::<usize>().ok());
+    match (numbers.next().flatten(), numbers.next().flatten()) {
+        (Some(rows), Some(cols)) if rows > 0 && cols > 0 => (cols, rows),
+        _ => (80, 24),
+    }
+}
+
+/// 读取一个文件的全部内容用于浏览；二进制文件返回 None
+fn load(path: &Path) -> io::Result<Option<String>> {
+    let mut bytes = fs::read(path)?;
+    if let Some(format) = Format::detect(&bytes, Some(path)) {
+        let mut decoded = Vec::new();
+        Decoder::new(&bytes[..], format).read_to_end(&mut decoded)?;
+        bytes = decoded;
+    }
+    if let Some(contents) = encoding::decode_utf16(&bytes) {
+        return Ok(Some(contents));
+    }
+    if walk::is_binary(&bytes) {
+        return Ok(None);
+    }
+    Ok(Some(String::from_utf8_lossy(&bytes).into_owned()))
+}
+
+/// 读入所有输入，进入交互界面，直到用户退出；返回退出时是否有选中的行
+pub fn browse(config: Config, inputs: &[Input]) -> Result<bool, MinigrepError> {
+    let mut files = Vec::new();
+    for input in inputs {
+        let path = input.path();
+        match load(path) {
+            Ok(Some(contents)) => files.push((path.to_path_buf(), contents)),
+            Ok(None) => {}
+            Err(e) => report_error(input, e)?,
+        }
+    }
+
+    let tty = Path::new(TTY);
+    let mut terminal = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(tty)
+        .map_err(|e| MinigrepError::io(tty, e))?;
+    let mut browser = Browser::new(config, files);
+    let result = interact(&mut browser, &mut terminal);
+    result.map_err(|e| MinigrepError::io(tty, e))?;
+    Ok(!browser.hits().is_empty())
+}
+
+fn interact(browser: &mut Browser, terminal: &mut File) -> io::Result<()> {
+    let mut raw = Some(RawMode::enable()?);
+    // 切换到备用屏幕并隐藏光标，退出时切回来，原来终端上的内容不受影响
+    write!(terminal, "\x1b[?1049h\x1b[?25l")?;
+    let result = (|| -> io::Result<()> {
+        let mut buf = [0; 64];
+        loop {
+            let (width, height) = terminal_size();
+            let screen = browser.render(width, height);
+            let mut frame = String::from("\x1b[H");
+            for (i, line) in screen.iter().enumerate() {
+                if i > 0 {
+                    frame.push_str("\r\n");
+                }
+                frame.push_str(line);
+                frame.push_str("\x1b[K");
+            }
+            terminal.write_all(frame.as_bytes())?;
+            terminal.flush()?;
+
+            let n = terminal.read(&mut buf)?;
+            if n == 0 {
+                return Ok(());
+            }
+            for key in parse_keys(&buf[..n]) {
+                match browser.handle(key) {
+                    Action::Redraw => {}
+                    Action::Quit => return Ok(()),
+                    Action::Open(path, line) => {
+                        // * 编辑器需要正常的终端：先恢复，回来后再进入 raw 模式
+                        write!(terminal, "\x1b[?25h\x1b[?1049l")?;
+                        raw = None;
+                        let status = open_editor(&path, line);
+                        raw = Some(RawMode::enable()?);
+                        write!(terminal, "\x1b[?1049h\x1b[?25l")?;
+                        status?;
+                        browser.reload(&path);
+                    }
+                }
+            }
+        }
+    })();
+    write!(terminal, "\x1b[?25h\x1b[?1049l")?;
+    drop(raw);
+    result
+}
+
+// $VISUAL / $EDITOR 可以带参数，比如 "code -w"；vi、nano、emacs 等都认识 +LINE
+fn open_editor(path: &Path, line: usize) -> io::Result<()> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or("vi");
+    Command::new(program)
+        .args(words)
+        .arg(format!("+{}", line))
+        .arg(path)
+        .stdin(File::open(TTY)?)
+        .stdout(OpenOptions::new().write(true).open(TTY)?)
+        .stderr(OpenOptions::new().write(true).open(TTY)?)
+        .status()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys() {
+        assert_eq!(
+            vec![
+                Key::Up,
+                Key::Down,
+                Key::PageDown,
+                Key::Char('é'),
+                Key::Enter
+            ],
+            parse_keys(b"\x1b[A\x1bOB\x1b[6~\xc3\xa9\r")
+        );
+        // 不认识的转义序列和控制字符被丢掉，单独的 ESC 和 Ctrl-C 退出
+        assert_eq!(
+            vec![Key::Char('a'), Key::Backspace, Key::Quit],
+            parse_keys(b"\x1b[1;5Ca\x01\x7f\x1b")
+        );
+        assert_eq!(vec![Key::ClearQuery, Key::Quit], parse_keys(b"\x15\x03"));
+    }
+
+    #[test]
+    fn filter_and_navigate() {
+        let config =
+            Config::new(["minigrep", "-i", "ru"].iter().map(|arg| arg.to_string())).unwrap();
+        let files = vec![
+            (PathBuf::from("poem.txt"), "Rust:\nTrust me.\n".to_string()),
+            (PathBuf::from("b.txt"), "rusty\nnothing\n".to_string()),
+        ];
+        let mut browser = Browser::new(config, files);
+        assert_eq!(3, browser.hits().len());
+
+        // 输入时重新过滤
+        browser.handle(Key::Char('s'));
+        browser.handle(Key::Char('t'));
+        browser.handle(Key::Char(' '));
+        assert_eq!("rust ", browser.query());
+        let hits = browser.hits();
+        assert_eq!(1, hits.len());
+        assert_eq!(
+            (0, 2, 1, 6),
+            (
+                hits[0].file,
+                hits[0].line_number,
+                hits[0].start,
+                hits[0].end
+            )
+        );
+        browser.handle(Key::Backspace);
+        browser.handle(Key::Down);
+        browser.handle(Key::Down);
+        browser.handle(Key::Down);
+        assert_eq!(
+            Action::Open(PathBuf::from("b.txt"), 1),
+            browser.handle(Key::Enter)
+        );
+
+        let screen = browser.render(40, 12);
+        assert_eq!(12, screen.len());
+        assert_eq!("> rust", screen[0]);
+        assert!(screen[1].contains("poem.txt"));
+        assert!(screen[4].contains("b.txt"));
+        assert!(screen[5].starts_with("\x1b[7m     1: "));
+        // 预览区域从标题开始，选中的行用 > 标出
+        assert!(screen[8].contains("b.txt:1"));
+        assert!(screen.contains(&">     1  rusty".to_string()));
+        assert!(screen[11].contains("3 matching lines in 2 files"));
+
+        // 模式有语法错误时保留原来的结果
+        browser.config.use_regex = true;
+        browser.handle(Key::Char('('));
+        assert_eq!(3, browser.hits().len());
+        assert!(browser.render(80, 12)[0].ends_with("unclosed group)"));
+    }
+}
@@ -1,82 +1,1984 @@
 ///* 使用 iterator 优化
+use std::cmp::Reverse;
 use std::env;
-use std::error::Error;
 use std::fs;
+use std::io::{self, BufRead};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Arc;
+use std::time::Instant;
+
+// * 正则引擎放在单独的文件里（文件名以数字开头，需要用 #[path] 指定）
+#[path = "8regex.rs"]
+pub mod regex;
+
+#[path = "9walk_dir.rs"]
+pub mod walk;
+
+#[path = "10output.rs"]
+pub mod output;
+
+#[path = "11args.rs"]
+pub mod args;
+
+#[path = "12stream.rs"]
+pub mod stream;
+
+#[path = "13parallel.rs"]
+pub mod parallel;
+
+#[path = "14case_fold.rs"]
+pub mod fold;
+
+#[path = "15fast_search.rs"]
+pub mod fast;
+
+#[path = "16profile.rs"]
+pub mod profile;
+
+#[path = "17ignore.rs"]
+pub mod ignore;
+
+#[path = "18replace.rs"]
+pub mod replace;
+
+#[path = "19decompress.rs"]
+pub mod decompress;
+
+#[path = "20error.rs"]
+pub mod error;
+
+#[path = "21encoding.rs"]
+pub mod encoding;
+
+#[path = "22watch.rs"]
+pub mod watch;
+
+#[path = "23fuzzy.rs"]
+pub mod fuzzy;
+
+#[path = "24tui.rs"]
+pub mod tui;
+
+// * 并行搜索复用 web server 章节的 ThreadPool
+#[path = "../40web_server/lib.rs"]
+pub mod thread_pool;
+
+use args::{ArgsError, OptSpec, Parsed};
+use decompress::{Decoder, Format};
+use error::InvalidUtf8;
+pub use error::MinigrepError;
+use fast::{AhoCorasick, Finder};
+use fuzzy::Fuzzy;
+use output::{BinaryMode, ColorChoice, OutputMode, OutputOptions, Printer};
+use profile::{Layer, Source};
+use regex::Regex;
+use replace::Template;
+use walk::{Glob, WalkOptions};
+use watch::Watcher;
+
+// * 这个程序也直接用 rustc 编译，那时没有 CARGO_PKG_VERSION，用和 Cargo.toml 一致的版本号
+const VERSION: &str = match option_env!("CARGO_PKG_VERSION") {
+    Some(version) => version,
+    None => "0.1.0",
+};
+
+const USAGE: &str = "\
+Usage: minigrep [OPTIONS] QUERY [FILE]...
+       minigrep [OPTIONS] [-e PATTERN]... [-f FILE]... [FILE]...
+
+A line is selected if it matches any of the patterns.
+With no FILE, or when FILE is -, read standard input.
+gzip and zlib compressed input is decompressed transparently.";
+
+// * 选项表：解析和 --help 的文本都由它驱动，新增选项只需要在这里加一项，再在 Config::new 中处理
+pub const OPTIONS: &[OptSpec] = &[
+    OptSpec {
+        short: Some('i'),
+        long: "ignore-case",
+        value: None,
+        help: "Ignore case distinctions",
+    },
+    OptSpec {
+        short: Some('s'),
+        long: "case-sensitive",
+        value: None,
+        help: "Match case exactly (overrides CASE_INSENSITIVE)",
+    },
+    OptSpec {
+        short: Some('E'),
+        long: "regex",
+        value: None,
+        help: "Treat QUERY as a regular expression",
+    },
+    OptSpec {
+        short: Some('F'),
+        long: "fixed-strings",
+        value: None,
+        help: "Treat QUERY as a literal string (overrides USE_REGEX)",
+    },
+    OptSpec {
+        short: Some('e'),
+        long: "regexp",
+        value: Some("PATTERN"),
+        help: "Use PATTERN for matching (can be repeated)",
+    },
+    OptSpec {
+        short: Some('f'),
+        long: "file",
+        value: Some("FILE"),
+        help: "Read patterns from FILE, one per line",
+    },
+    OptSpec {
+        short: Some('v'),
+        long: "invert-match",
+        value: None,
+        help: "Select non-matching lines",
+    },
+    OptSpec {
+        short: None,
+        long: "no-invert-match",
+        value: None,
+        help: "Turn off --invert-match",
+    },
+    OptSpec {
+        short: Some('w'),
+        long: "word-regexp",
+        value: None,
+        help: "Only match whole words (letters, digits and _)",
+    },
+    OptSpec {
+        short: None,
+        long: "no-word-regexp",
+        value: None,
+        help: "Turn off --word-regexp",
+    },
+    OptSpec {
+        short: Some('x'),
+        long: "line-regexp",
+        value: None,
+        help: "Only match whole lines",
+    },
+    OptSpec {
+        short: None,
+        long: "no-line-regexp",
+        value: None,
+        help: "Turn off --line-regexp",
+    },
+    OptSpec {
+        short: None,
+        long: "fuzzy",
+        value: Some("K"),
+        help: "Match QUERY with up to K inserted, deleted or substituted characters",
+    },
+    OptSpec {
+        short: Some('c'),
+        long: "count",
+        value: None,
+        help: "Print only a count of selected lines per file",
+    },
+    OptSpec {
+        short: None,
+        long: "no-count",
+        value: None,
+        help: "Turn off --count",
+    },
+    OptSpec {
+        short: Some('l'),
+        long: "files-with-matches",
+        value: None,
+        help: "Print only names of files with selected lines",
+    },
+    OptSpec {
+        short: None,
+        long: "no-files-with-matches",
+        value: None,
+        help: "Turn off --files-with-matches",
+    },
+    OptSpec {
+        short: Some('L'),
+        long: "files-without-match",
+        value: None,
+        help: "Print only names of files without selected lines",
+    },
+    OptSpec {
+        short: None,
+        long: "no-files-without-match",
+        value: None,
+        help: "Turn off --files-without-match",
+    },
+    OptSpec {
+        short: Some('m'),
+        long: "max-count",
+        value: Some("NUM"),
+        help: "Stop reading a file after NUM selected lines",
+    },
+    OptSpec {
+        short: Some('q'),
+        long: "quiet",
+        value: None,
+        help: "Print nothing; exit 0 on first match",
+    },
+    OptSpec {
+        short: None,
+        long: "no-quiet",
+        value: None,
+        help: "Turn off --quiet",
+    },
+    OptSpec {
+        short: None,
+        long: "json",
+        value: None,
+        help: "Print results as JSON Lines",
+    },
+    OptSpec {
+        short: None,
+        long: "no-json",
+        value: None,
+        help: "Turn off --json",
+    },
+    OptSpec {
+        short: None,
+        long: "replace",
+        value: Some("TEXT"),
+        help: "Replace every match with TEXT and print a diff ($1 refers to a group with -E)",
+    },
+    OptSpec {
+        short: None,
+        long: "in-place",
+        value: None,
+        help: "With --replace, write the changes back to the files",
+    },
+    OptSpec {
+        short: None,
+        long: "watch",
+        value: None,
+        help: "Keep running and print new matching lines as the files grow",
+    },
+    OptSpec {
+        short: None,
+        long: "interactive",
+        value: None,
+        help: "Browse the results in the terminal, refining QUERY as you type",
+    },
+    OptSpec {
+        short: Some('n'),
+        long: "line-number",
+        value: None,
+        help: "Prefix each line with its line number",
+    },
+    OptSpec {
+        short: None,
+        long: "no-line-number",
+        value: None,
+        help: "Turn off --line-number",
+    },
+    OptSpec {
+        short: Some('b'),
+        long: "byte-offset",
+        value: None,
+        help: "Prefix each line with its byte offset",
+    },
+    OptSpec {
+        short: None,
+        long: "no-byte-offset",
+        value: None,
+        help: "Turn off --byte-offset",
+    },
+    OptSpec {
+        short: Some('A'),
+        long: "after-context",
+        value: Some("NUM"),
+        help: "Print NUM lines of trailing context",
+    },
+    OptSpec {
+        short: Some('B'),
+        long: "before-context",
+        value: Some("NUM"),
+        help: "Print NUM lines of leading context",
+    },
+    OptSpec {
+        short: Some('C'),
+        long: "context",
+        value: Some("NUM"),
+        help: "Print NUM lines of context around each match",
+    },
+    OptSpec {
+        short: Some('a'),
+        long: "text",
+        value: None,
+        help: "Print matching lines of binary files as text",
+    },
+    OptSpec {
+        short: None,
+        long: "no-text",
+        value: None,
+        help: "Turn off --text",
+    },
+    OptSpec {
+        short: None,
+        long: "binary-skip",
+        value: None,
+        help: "Skip binary files",
+    },
+    OptSpec {
+        short: None,
+        long: "no-binary-skip",
+        value: None,
+        help: "Turn off --binary-skip",
+    },
+    OptSpec {
+        short: None,
+        long: "color",
+        value: Some("WHEN"),
+        help: "Highlight matches: auto, always or never (default auto)",
+    },
+    OptSpec {
+        short: None,
+        long: "include",
+        value: Some("GLOB"),
+        help: "Only search files matching GLOB (repeatable)",
+    },
+    OptSpec {
+        short: None,
+        long: "exclude",
+        value: Some("GLOB"),
+        help: "Skip files and directories matching GLOB (repeatable)",
+    },
+    OptSpec {
+        short: None,
+        long: "no-ignore",
+        value: None,
+        help: "Don't respect .gitignore, .ignore or the global git ignore file",
+    },
+    OptSpec {
+        short: None,
+        long: "ignore",
+        value: None,
+        help: "Respect ignore files again (turns off --no-ignore)",
+    },
+    OptSpec {
+        short: None,
+        long: "follow",
+        value: None,
+        help: "Follow symbolic links when searching directories",
+    },
+    OptSpec {
+        short: None,
+        long: "no-follow",
+        value: None,
+        help: "Turn off --follow",
+    },
+    OptSpec {
+        short: Some('j'),
+        long: "jobs",
+        value: Some("N"),
+        help: "Search files with N threads (0 = one per CPU)",
+    },
+    OptSpec {
+        short: None,
+        long: "print-config",
+        value: None,
+        help: "Print the effective settings and where each came from, then exit",
+    },
+    OptSpec {
+        short: None,
+        long: "no-config",
+        value: None,
+        help: "Don't read config files",
+    },
+    OptSpec {
+        short: Some('h'),
+        long: "help",
+        value: None,
+        help: "Print this help and exit",
+    },
+    OptSpec {
+        short: Some('V'),
+        long: "version",
+        value: None,
+        help: "Print version information and exit",
+    },
+];
+
+const ENVIRONMENT_HELP: &str = "\
+Exit status:
+  0 if a line is selected, 1 if no lines were selected,
+  2 for a usage error or if some files could not be searched
+  (with -q, 0 whenever a line is selected, even if an error occurred),
+  3 for an invalid pattern, 4 for an I/O error, 5 for input that is not UTF-8
+
+Config files:
+  User config     $XDG_CONFIG_HOME/minigrep/config (default ~/.config/minigrep/config)
+  Project config  the nearest .minigreprc in the current directory or its parents
+  One option per line, e.g. --exclude=target, --exclude target or -A 3;
+  Lines starting with # are comments.
+  Options after a [NAME] line only apply when MINIGREP_PROFILE=NAME.
+  A switch set in a config file can be turned off with --no-SWITCH
+  (--ignore for --no-ignore).
+
+Environment:
+  CASE_INSENSITIVE  If set, ignore case unless -s/--case-sensitive is given
+  USE_REGEX         If set, use regex mode unless -F/--fixed-strings is given
+  MINIGREP_PROFILE  Name of the config file profile to apply
+
+Precedence: command-line options > environment variables > project config
+            > user config > defaults
+";
+
+pub fn help() -> String {
+    format!(
+        "{}\n\n{}\n{}",
+        USAGE,
+        args::options_help(OPTIONS),
+        ENVIRONMENT_HELP
+    )
+}
 
 pub struct Config {
-    pub query: String,
-    pub filename: String,
+    // * 要匹配的模式，任意一个匹配就选中这一行：-e 给出的，或者第一个位置参数
+    pub patterns: Vec<String>,
+    // * -f 给出的模式文件，在 run 中读取
+    pub pattern_files: Vec<PathBuf>,
+    // * 要搜索的文件或目录，"-" 表示标准输入
+    pub filenames: Vec<String>,
     pub case_sensitive: bool,
+    // * 为 true 时把模式当作正则表达式
+    pub use_regex: bool,
+    // * -v：选中不匹配的行
+    pub invert_match: bool,
+    // * -w / -x：匹配必须是完整的单词 / 整行，同时给出时 -x 优先
+    pub word_regexp: bool,
+    pub line_regexp: bool,
+    // * --fuzzy：允许的最大编辑次数，按 Levenshtein 距离近似匹配
+    pub max_edits: Option<usize>,
+    // * filename 是目录时递归搜索使用的过滤选项
+    pub walk: WalkOptions,
+    // * 行号、字节偏移、上下文等输出选项
+    pub output: OutputOptions,
+    // * 什么时候高亮匹配，auto 要到 run 中才知道标准输出是不是终端
+    pub color: ColorChoice,
+    // * 搜索文件的线程数，1 表示不使用线程池
+    pub jobs: usize,
+    // * --replace：替换文本，默认只输出 diff
+    pub replace: Option<String>,
+    // * --in-place：把替换写回文件
+    pub in_place: bool,
+    // * --watch：一直运行，只输出文件中新增的选中行
+    pub watch: bool,
+    // * --interactive：在终端界面里浏览结果
+    pub interactive: bool,
+    // * --print-config：只输出生效的设置
+    pub print_config: bool,
+    // * 按应用顺序记录每个选项来自哪里，同一个选项后面的覆盖前面的
+    pub origins: Vec<(&'static str, Source)>,
 }
 
 impl Config {
-    pub fn new(mut args: std::env::Args) -> Result<Config, &'static str> {
+    /// 只使用命令行参数和环境变量，不读取配置文件
+    pub fn new(mut args: impl Iterator<Item = String>) -> Result<Config, ArgsError> {
+        args.next();
+
+        // $ cargo run -- --include='*.rs' --exclude=target --follow ThreadPool src
+        // $ cargo run -- -inC2 tell poem.txt
+        let parsed = args::parse(OPTIONS, args)?;
+        Config::build(Vec::new(), parsed)
+    }
+
+    /// 和 new 一样，另外读取用户配置和项目配置；给出 --no-config 时跳过
+    pub fn load(mut args: impl Iterator<Item = String>) -> Result<Config, ArgsError> {
         args.next();
+
+        let parsed = args::parse(OPTIONS, args)?;
+        let files = if parsed.options.iter().any(|(name, _)| *name == "no-config") {
+            Vec::new()
+        } else {
+            profile::load_files(OPTIONS)?
+        };
+        Config::build(files, parsed)
+    }
+
+    // * 按优先级从低到高应用每一层的选项：配置文件 < 环境变量 < 命令行
+    fn build(mut layers: Vec<Layer>, parsed: Parsed) -> Result<Config, ArgsError> {
+        layers.extend(profile::env_layers());
+        layers.push(Layer {
+            source: Source::CommandLine,
+            options: parsed.options,
+        });
+
+        let mut walk = WalkOptions::default();
+        let mut output = OutputOptions::default();
+        let mut case_sensitive = true;
+        let mut use_regex = false;
+        let mut patterns = Vec::new();
+        let mut pattern_files = Vec::new();
+        let mut invert_match = false;
+        let mut word_regexp = false;
+        let mut line_regexp = false;
+        let mut max_edits = None;
+        let mut color = ColorChoice::default();
+        let mut jobs = 1;
+        let mut replace = None;
+        let mut in_place = false;
+        let mut watch = false;
+        let mut interactive = false;
+        let mut print_config = false;
+        let mut origins = Vec::new();
+        for (source, (name, value)) in layers.into_iter().flat_map(|layer| {
+            layer
+                .options
+                .into_iter()
+                .map(move |option| (layer.source.clone(), option))
+        }) {
+            // 只有带值的选项 value 才是 Some
+            let value = value.unwrap_or_default();
+            match name {
+                "help" => return Err(ArgsError::Help(help())),
+                "version" => return Err(ArgsError::Version(format!("minigrep {}\n", VERSION))),
+                "print-config" => print_config = true,
+                "no-config" => {}
+                "ignore-case" => case_sensitive = false,
+                "case-sensitive" => case_sensitive = true,
+                "regex" => use_regex = true,
+                "fixed-strings" => use_regex = false,
+                "regexp" => patterns.push(value),
+                "file" => pattern_files.push(PathBuf::from(value)),
+                "invert-match" => invert_match = true,
+                "no-invert-match" => invert_match = false,
+                "word-regexp" => word_regexp = true,
+                "no-word-regexp" => word_regexp = false,
+                "line-regexp" => line_regexp = true,
+                "no-line-regexp" => line_regexp = false,
+                "fuzzy" => max_edits = Some(args::number(name, &value)?),
+                "count" => output.mode = OutputMode::Count,
+                "files-with-matches" => output.mode = OutputMode::FilesWithMatches,
+                "files-without-match" => output.mode = OutputMode::FilesWithoutMatch,
+                "quiet" => output.mode = OutputMode::Quiet,
+                "json" => output.mode = OutputMode::Json,
+                // * 只关掉对应的模式：配置文件给了 --json、命令行给了 --no-count 时仍然是 json
+                "no-count"
+                | "no-files-with-matches"
+                | "no-files-without-match"
+                | "no-quiet"
+                | "no-json" => {
+                    let negated = match name {
+                        "no-count" => OutputMode::Count,
+                        "no-files-with-matches" => OutputMode::FilesWithMatches,
+                        "no-files-without-match" => OutputMode::FilesWithoutMatch,
+                        "no-quiet" => OutputMode::Quiet,
+                        _ => OutputMode::Json,
+                    };
+                    if output.mode == negated {
+                        output.mode = OutputMode::Lines;
+                    }
+                }
+                "replace" => replace = Some(value),
+                "in-place" => in_place = true,
+                "watch" => watch = true,
+                "interactive" => interactive = true,
+                "max-count" => output.max_count = Some(args::number(name, &value)?),
+                "line-number" => output.line_number = true,
+                "no-line-number" => output.line_number = false,
+                "byte-offset" => output.byte_offset = true,
+                "no-byte-offset" => output.byte_offset = false,
+                "after-context" => output.after_context = args::number(name, &value)?,
+                "before-context" => output.before_context = args::number(name, &value)?,
+                "context" => {
+                    output.before_context = args::number(name, &value)?;
+                    output.after_context = output.before_context;
+                }
+                "color" => {
+                    color = value.parse().map_err(|_| ArgsError::InvalidValue {
+                        option: "--color".to_string(),
+                        value,
+                    })?
+                }
+                "include" => walk.include.push(Glob::new(&value)),
+                "exclude" => walk.exclude.push(Glob::new(&value)),
+                "text" => output.binary = BinaryMode::Text,
+                "binary-skip" => output.binary = BinaryMode::Skip,
+                "no-text" | "no-binary-skip" => {
+                    let negated = match name {
+                        "no-text" => BinaryMode::Text,
+                        _ => BinaryMode::Skip,
+                    };
+                    if output.binary == negated {
+                        output.binary = BinaryMode::Matches;
+                    }
+                }
+                "no-ignore" => walk.no_ignore = true,
+                "ignore" => walk.no_ignore = false,
+                "follow" => walk.follow_symlinks = true,
+                "no-follow" => walk.follow_symlinks = false,
+                "jobs" => jobs = args::number(name, &value)?,
+                _ => unreachable!("option '{}' is not handled", name),
+            }
+            origins.push((name, source));
+        }
+
         // *使用迭代器并去掉 clone
         // *使用 Iterator trait 代替索引
-        let query = match args.next() {
-            Some(arg) => arg,
-            None => return Err("Didn't get a query string"),
-        };
+        let mut args = parsed.positional.into_iter();
+        // * 没有 -e / -f 时第一个位置参数是模式
+        if patterns.is_empty() && pattern_files.is_empty() {
+            match args.next() {
+                Some(arg) => patterns.push(arg),
+                // --print-config 不需要模式
+                None if print_config => {}
+                None => return Err(ArgsError::MissingArgument("QUERY")),
+            }
+        }
+
+        // * 没有给出文件名时读取标准输入
+        let mut filenames: Vec<String> = args.collect();
+        if filenames.is_empty() {
+            filenames.push("-".to_string());
+        }
 
-        let filename = match args.next() {
-            Some(arg) => arg,
-            None => return Err("Didn't get a file name"),
+        // * 近似匹配把 query 当作普通的字符串
+        if max_edits.is_some() && use_regex {
+            return Err(ArgsError::Conflicts("--fuzzy", "--regex"));
+        }
+        if in_place && replace.is_none() {
+            return Err(ArgsError::Requires("--in-place", "--replace"));
+        }
+        // * --watch 和 --interactive 都要反复读取文件，不能用标准输入，也不按 -c / -l / -L / -q 输出
+        if watch && interactive {
+            return Err(ArgsError::Conflicts("--interactive", "--watch"));
+        }
+        let continuous = if watch {
+            Some("--watch")
+        } else if interactive {
+            Some("--interactive")
+        } else {
+            None
         };
+        if let Some(continuous) = continuous {
+            if filenames.iter().any(|filename| filename == "-") {
+                return Err(ArgsError::Requires(continuous, "FILE"));
+            }
+            let conflict = match output.mode {
+                OutputMode::Count => Some("--count"),
+                OutputMode::FilesWithMatches => Some("--files-with-matches"),
+                OutputMode::FilesWithoutMatch => Some("--files-without-match"),
+                OutputMode::Quiet => Some("--quiet"),
+                OutputMode::Lines | OutputMode::Json => None,
+            };
+            if let Some(option) = conflict.or(replace.as_ref().map(|_| "--replace")) {
+                return Err(ArgsError::Conflicts(continuous, option));
+            }
+        }
 
-        let case_sensitive = env::var("CASE_INSENSITIVE").is_err();
+        if jobs == 0 {
+            jobs = std::thread::available_parallelism().map_or(1, |n| n.get());
+        }
 
         Ok(Config {
-            query,
-            filename,
+            patterns,
+            pattern_files,
+            filenames,
             case_sensitive,
+            use_regex,
+            invert_match,
+            word_regexp,
+            line_regexp,
+            max_edits,
+            walk,
+            output,
+            color,
+            jobs,
+            replace,
+            in_place,
+            watch,
+            interactive,
+            print_config,
+            origins,
         })
     }
+
+    /// --print-config 的输出：每项生效的设置，以及它来自哪里
+    pub fn describe(&self) -> String {
+        let lines: Vec<(String, String)> = SETTINGS
+            .iter()
+            .map(|&(name, options)| {
+                let mut applied = self
+                    .origins
+                    .iter()
+                    .filter(|(option, _)| options.contains(option))
+                    .map(|(_, source)| source.to_string());
+                // * 列表类的设置每一层都会追加，需要列出所有来源；其他设置只看最后一次
+                let mut sources: Vec<String> = Vec::new();
+                if name == "include" || name == "exclude" {
+                    for source in applied {
+                        if !sources.contains(&source) {
+                            sources.push(source);
+                        }
+                    }
+                } else {
+                    sources.extend(applied.next_back());
+                }
+                if sources.is_empty() {
+                    sources.push(Source::Default.to_string());
+                }
+                (
+                    format!("{} = {}", name, self.setting(name)),
+                    sources.join(", "),
+                )
+            })
+            .collect();
+
+        let width = lines
+            .iter()
+            .map(|(setting, _)| setting.len())
+            .max()
+            .unwrap_or(0);
+        lines
+            .iter()
+            .map(|(setting, source)| format!("{:width$}  # {}\n", setting, source, width = width))
+            .collect()
+    }
+
+    fn setting(&self, name: &str) -> String {
+        let globs = |globs: &[Glob]| {
+            let patterns: Vec<String> = globs.iter().map(Glob::pattern).collect();
+            patterns.join(", ")
+        };
+        match name {
+            "case-sensitive" => self.case_sensitive.to_string(),
+            "regex" => self.use_regex.to_string(),
+            "invert-match" => self.invert_match.to_string(),
+            "word-regexp" => self.word_regexp.to_string(),
+            "line-regexp" => self.line_regexp.to_string(),
+            "fuzzy" => self.max_edits.map_or("none".to_string(), |n| n.to_string()),
+            "mode" => match self.output.mode {
+                OutputMode::Lines => "lines",
+                OutputMode::Count => "count",
+                OutputMode::FilesWithMatches => "files-with-matches",
+                OutputMode::FilesWithoutMatch => "files-without-match",
+                OutputMode::Quiet => "quiet",
+                OutputMode::Json => "json",
+            }
+            .to_string(),
+            "line-number" => self.output.line_number.to_string(),
+            "byte-offset" => self.output.byte_offset.to_string(),
+            "before-context" => self.output.before_context.to_string(),
+            "after-context" => self.output.after_context.to_string(),
+            "max-count" => self
+                .output
+                .max_count
+                .map_or("none".to_string(), |n| n.to_string()),
+            "color" => self.color.to_string(),
+            "include" => globs(&self.walk.include),
+            "exclude" => globs(&self.walk.exclude),
+            "no-ignore" => self.walk.no_ignore.to_string(),
+            "follow" => self.walk.follow_symlinks.to_string(),
+            "binary" => self.output.binary.to_string(),
+            "jobs" => self.jobs.to_string(),
+            _ => unreachable!("setting '{}' is not described", name),
+        }
+    }
 }
 
-pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
-    let contents = fs::read_to_string(config.filename)?;
+// * --print-config 显示的设置，以及会改变这项设置的选项
+const SETTINGS: &[(&str, &[&str])] = &[
+    ("case-sensitive", &["ignore-case", "case-sensitive"]),
+    ("regex", &["regex", "fixed-strings"]),
+    ("invert-match", &["invert-match", "no-invert-match"]),
+    ("word-regexp", &["word-regexp", "no-word-regexp"]),
+    ("line-regexp", &["line-regexp", "no-line-regexp"]),
+    ("fuzzy", &["fuzzy"]),
+    (
+        "mode",
+        &[
+            "count",
+            "files-with-matches",
+            "files-without-match",
+            "quiet",
+            "json",
+            "no-count",
+            "no-files-with-matches",
+            "no-files-without-match",
+            "no-quiet",
+            "no-json",
+        ],
+    ),
+    ("line-number", &["line-number", "no-line-number"]),
+    ("byte-offset", &["byte-offset", "no-byte-offset"]),
+    ("before-context", &["before-context", "context"]),
+    ("after-context", &["after-context", "context"]),
+    ("max-count", &["max-count"]),
+    (
+        "binary",
+        &["text", "binary-skip", "no-text", "no-binary-skip"],
+    ),
+    ("color", &["color"]),
+    ("include", &["include"]),
+    ("exclude", &["exclude"]),
+    ("no-ignore", &["no-ignore", "ignore"]),
+    ("follow", &["follow", "no-follow"]),
+    ("jobs", &["jobs"]),
+];
 
-    let results = if config.case_sensitive {
-        search(&config.query, &contents)
+/// 一次运行的结果，决定进程的退出码
+#[derive(Debug, Default, PartialEq)]
+pub struct Summary {
+    // * 是否有选中的行
+    pub matched: bool,
+    // * 出错并跳过的输入数
+    pub errors: usize,
+    // * 下面几项用于 --json 的 summary：搜索过的输入数、有选中行的输入数、选中的行数
+    pub searched: usize,
+    pub files_matched: usize,
+    pub matched_lines: usize,
+}
+
+impl Summary {
+    /// 和 grep 一样：0 有选中的行，1 没有，2 出错；-q 时只要有选中的行就是 0
+    pub fn exit_code(&self, quiet: bool) -> i32 {
+        if self.errors > 0 && !(quiet && self.matched) {
+            2
+        } else if self.matched {
+            0
+        } else {
+            1
+        }
+    }
+
+    /// 记录一个输入的搜索结果
+    pub fn add(&mut self, count: usize) {
+        self.searched += 1;
+        self.matched_lines += count;
+        if count > 0 {
+            self.matched = true;
+            self.files_matched += 1;
+        }
+    }
+}
+
+pub fn run(mut config: Config) -> Result<Summary, MinigrepError> {
+    for path in &config.pattern_files {
+        let patterns = read_patterns(path).map_err(|e| MinigrepError::io(path, e))?;
+        config.patterns.extend(patterns);
+    }
+    // * 正则只在这里编译一次，语法错误通过 ? 转成 MinigrepError::Pattern 返回，而不是 panic
+    // * 打印时高亮也要用到 matcher，放进 Arc 和并行搜索的线程共享
+    let matcher = Arc::new(Matcher::new(&config)?);
+    let inputs = collect_inputs(&config)?;
+
+    let mut options = config.output.clone();
+    options.color = config.color.enabled();
+    // 和 grep 一样：多个输入或者搜索目录时才在每行前面输出文件名
+    options.with_filename = config.filenames.len() > 1 || inputs.iter().any(|input| input.walked());
+
+    // * 锁住标准输出，避免每行都加锁；写入出错（如管道被关闭）时直接返回错误
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+
+    // * 标准输入只能在主线程上按顺序读，这种情况下不走线程池；-q 找到第一个匹配就结束，也不需要线程池
+    let parallel = config.jobs > 1
+        && options.mode != OutputMode::Quiet
+        && !inputs.iter().any(|input| matches!(input, Input::Stdin));
+    // * 替换模式不输出匹配的行，而是输出 diff 或者直接修改文件
+    if let Some(replacement) = &config.replace {
+        let template = if config.use_regex {
+            Template::parse(replacement)
+        } else {
+            Template::literal(replacement)
+        };
+        let summary = replace::replace_all(
+            &inputs,
+            &matcher,
+            &template,
+            &options,
+            config.in_place,
+            &mut out,
+        )?;
+        return Ok(summary);
+    }
+
+    if config.interactive {
+        let matched = tui::browse(config, &inputs)?;
+        return Ok(Summary {
+            matched,
+            ..Summary::default()
+        });
+    }
+
+    if config.watch {
+        let roots = config.filenames.iter().map(PathBuf::from).collect();
+        let watcher = Watcher::new(roots, config.walk.clone(), matcher, options);
+        return Err(watcher.run(&mut out).into());
+    }
+
+    let start = Instant::now();
+    let summary = if parallel {
+        parallel::search_parallel(&inputs, matcher, &options, config.jobs, &mut out)?
     } else {
-        search_case_insensitive(&config.query, &contents)
+        let mut summary = Summary::default();
+        let mut printer = Printer::new(options.clone()).highlight(Arc::clone(&matcher));
+        for input in &inputs {
+            match search_input(input, &matcher, &options, &mut printer, &mut out) {
+                Ok(count) => summary.add(count),
+                Err(e) => {
+                    report_error(input, e)?;
+                    summary.errors += 1;
+                }
+            }
+            if summary.matched && options.mode == OutputMode::Quiet {
+                break;
+            }
+        }
+        summary
     };
 
-    for line in results {
-        println!("{}", line);
+    if options.mode == OutputMode::Json {
+        output::write_json_summary(
+            &mut out,
+            summary.searched,
+            summary.files_matched,
+            summary.matched_lines,
+            start.elapsed(),
+        )?;
+    }
+    Ok(summary)
+}
+
+// * 模式文件每行一个模式；和 grep 一样，空行匹配所有行，空文件不匹配任何行
+fn read_patterns(path: &Path) -> io::Result<Vec<String>> {
+    let contents = String::from_utf8(fs::read(path)?)
+        .map_err(|e| InvalidUtf8::error(e.utf8_error().valid_up_to()))?;
+    Ok(numbered_lines(&contents)
+        .map(|(_, _, line)| line.to_string())
+        .collect())
+}
+
+/// 一个要搜索的输入
+pub enum Input {
+    Stdin,
+    // * walked 为 true 表示是遍历目录得到的文件：输出时默认带文件名，
+    //   --replace 不修改其中的二进制文件和非 UTF-8 文件（搜索对所有文件一视同仁）
+    File { path: PathBuf, walked: bool },
+}
+
+impl Input {
+    pub fn walked(&self) -> bool {
+        matches!(self, Input::File { walked: true, .. })
+    }
+
+    /// 输出和错误信息中使用的路径
+    pub fn path(&self) -> &Path {
+        match self {
+            Input::Stdin => Path::new("(standard input)"),
+            Input::File { path, .. } => path,
+        }
+    }
+}
+
+// * 展开命令行中的文件和目录，目录按顺序展开成其中的文件
+fn collect_inputs(config: &Config) -> Result<Vec<Input>, MinigrepError> {
+    let mut inputs = Vec::new();
+    for filename in &config.filenames {
+        let path = Path::new(filename);
+        if filename == "-" {
+            inputs.push(Input::Stdin);
+        } else if path.is_dir() {
+            let walked = walk::walk(path, &config.walk).map_err(|e| MinigrepError::io(path, e))?;
+            for path in walked {
+                inputs.push(Input::File { path, walked: true });
+            }
+        } else {
+            inputs.push(Input::File {
+                path: path.to_path_buf(),
+                walked: false,
+            });
+        }
     }
+    Ok(inputs)
+}
 
+/// 搜索一个输入，结果通过 `printer` 写到 `out`，返回选中的行数
+pub fn search_input<W: io::Write>(
+    input: &Input,
+    matcher: &Matcher,
+    options: &OutputOptions,
+    printer: &mut Printer,
+    out: &mut W,
+) -> io::Result<usize> {
+    let path = match input {
+        Input::Stdin => {
+            let stdin = io::stdin();
+            let mut reader = stdin.lock();
+            let path = input.path();
+            // $ cat app.log.1.gz | cargo run -- ERROR
+            if let Some(format) = Format::detect(reader.fill_buf()?, None) {
+                let reader = Decoder::new(reader, format);
+                return search_stream(reader, matcher, options, printer, out, path);
+            }
+            return search_stream(reader, matcher, options, printer, out, path);
+        }
+        Input::File { path, .. } => path.as_path(),
+    };
+
+    if fs::metadata(path)?.len() > stream::STREAM_THRESHOLD {
+        let mut reader = stream::open(path)?;
+        if let Some(format) = Format::detect(reader.fill_buf()?, Some(path)) {
+            let reader = Decoder::new(reader, format);
+            return search_stream(reader, matcher, options, printer, out, path);
+        }
+        return search_stream(reader, matcher, options, printer, out, path);
+    }
+
+    let contents = fs::read(path)?;
+    // * 压缩文件边解压边搜索，输出和普通文件一样，行号和偏移都是解压后的
+    if let Some(format) = Format::detect(&contents, Some(path)) {
+        let reader = Decoder::new(&contents[..], format);
+        return search_stream(reader, matcher, options, printer, out, path);
+    }
+    // * 二进制文件、UTF-16 和不是合法 UTF-8 的文件交给 search_stream 按字节处理
+    match String::from_utf8(contents) {
+        Ok(contents) if !walk::is_binary(contents.as_bytes()) => {
+            search_text(&contents, matcher, options, printer, out, path)
+        }
+        Ok(contents) => search_stream(contents.as_bytes(), matcher, options, printer, out, path),
+        Err(e) => search_stream(e.as_bytes(), matcher, options, printer, out, path),
+    }
+}
+
+// 已经读进内存的文本：在整个缓冲区上查找
+fn search_text<W: io::Write>(
+    contents: &str,
+    matcher: &Matcher,
+    options: &OutputOptions,
+    printer: &mut Printer,
+    out: &mut W,
+    path: &Path,
+) -> io::Result<usize> {
+    let mut matches = matcher.search(contents);
+    if let Some(limit) = options.limit() {
+        matches.truncate(limit);
+    }
+    printer.print(out, path, contents, &matches)?;
+    Ok(matches.len())
+}
+
+// 逐行搜索；先看开头的字节决定是不是 UTF-16，是不是二进制文件
+fn search_stream<R: BufRead, W: io::Write>(
+    mut reader: R,
+    matcher: &Matcher,
+    options: &OutputOptions,
+    printer: &mut Printer,
+    out: &mut W,
+    path: &Path,
+) -> io::Result<usize> {
+    if let Some(contents) = encoding::read_utf16(&mut reader)? {
+        return search_text(&contents, matcher, options, printer, out, path);
+    }
+    if walk::is_binary(reader.fill_buf()?) {
+        match options.binary {
+            BinaryMode::Skip => return Ok(0),
+            // * 找到第一个选中的行就够了，选中的行本身不输出
+            BinaryMode::Matches if options.prints_lines() => {
+                let quiet = OutputOptions {
+                    mode: OutputMode::Quiet,
+                    ..OutputOptions::default()
+                };
+                let mut sink = io::sink();
+                let count = stream::search_reader(
+                    reader,
+                    matcher,
+                    Some(1),
+                    &mut Printer::new(quiet),
+                    &mut sink,
+                    path,
+                )?;
+                if count > 0 {
+                    printer.binary_matches(out, path)?;
+                }
+                return Ok(count);
+            }
+            _ => {}
+        }
+    }
+    stream::search_reader(reader, matcher, options.limit(), printer, out, path)
+}
+
+// * 遍历目录得到的文件出错时只打印到标准错误；命令行直接给出的文件出错则中断
+pub fn report_error(input: &Input, e: io::Error) -> Result<(), MinigrepError> {
+    // 写标准输出失败时没必要继续搜索
+    if e.kind() == io::ErrorKind::BrokenPipe {
+        return Err(e.into());
+    }
+    // * 和 grep 一样：读不了的输入报告之后继续搜索其他输入，最后退出码为 2
+    eprintln!("minigrep: {}", MinigrepError::io(input.path(), e));
     Ok(())
 }
 
-pub fn search<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
-    // *使用迭代器适配器来使代码更简明
+/// 每一行的匹配方式，在 run 中只构建一次，内存搜索和流式搜索共用
+pub enum Matcher {
+    Literal(String),
+    // * 保存的是已经做过大小写折叠的 query
+    CaseInsensitive(String),
+    Regex(Regex),
+    // * 多个区分大小写的字面量，用一个 Aho–Corasick 自动机同时查找
+    Multi(AhoCorasick),
+    // * 其他多个模式的情况，任意一个匹配即可
+    Any(Vec<Matcher>),
+    // * -w：匹配前后都不能是单词字符（Unicode 字母、数字和下划线）
+    Word(Box<Matcher>),
+    // * -x：匹配必须是整行；正则直接加上锚点，近似匹配自己处理，都不用它
+    Line(Box<Matcher>),
+    // * --fuzzy：编辑距离不超过 K 的近似匹配
+    Fuzzy(Fuzzy),
+    // * -v：选中不匹配的行，选中的区间为空
+    Invert(Box<Matcher>),
+}
+
+impl Matcher {
+    pub fn new(config: &Config) -> Result<Matcher, regex::ParseError> {
+        // Aho–Corasick 在同一位置只给出最长的匹配，-w / -x 可能需要更短的那个，所以逐个模式匹配
+        let literal = !config.use_regex && config.max_edits.is_none();
+        let whole = config.word_regexp || config.line_regexp;
+        let matcher = if config.patterns.len() > 1 && config.case_sensitive && literal && !whole {
+            Matcher::Multi(AhoCorasick::new(&config.patterns))
+        } else {
+            let mut matchers = config
+                .patterns
+                .iter()
+                .map(|pattern| Matcher::single(pattern, config))
+                .collect::<Result<Vec<_>, _>>()?;
+            if matchers.len() == 1 {
+                matchers.remove(0)
+            } else {
+                Matcher::Any(matchers)
+            }
+        };
+        Ok(if config.invert_match {
+            Matcher::Invert(Box::new(matcher))
+        } else {
+            matcher
+        })
+    }
+
+    fn single(pattern: &str, config: &Config) -> Result<Matcher, regex::ParseError> {
+        let matcher = if let Some(max_edits) = config.max_edits {
+            // * 单词和整行的限制要在计算距离时考虑，所以不套 Word / Line
+            let fuzzy = Fuzzy::new(pattern, max_edits, config.case_sensitive);
+            return Ok(Matcher::Fuzzy(if config.line_regexp {
+                fuzzy.whole_line()
+            } else if config.word_regexp {
+                fuzzy.words()
+            } else {
+                fuzzy
+            }));
+        } else if config.use_regex {
+            // * 最左优先的正则在行首给出的匹配不一定是最长的，-x 时加上锚点让它只能匹配整行
+            // 先单独编译一次，语法错误的位置才和用户写的模式对得上
+            let regex = build_regex(pattern, config.case_sensitive)?;
+            if config.line_regexp {
+                // (?i) 只能出现在模式开头，包进分组之前先取出来
+                let (pattern, case_sensitive) = match pattern.strip_prefix("(?i)") {
+                    Some(rest) => (rest, false),
+                    None => (pattern, config.case_sensitive),
+                };
+                let anchored = format!("^(?:{})$", pattern);
+                return Ok(Matcher::Regex(build_regex(&anchored, case_sensitive)?));
+            }
+            Matcher::Regex(regex)
+        } else if config.case_sensitive {
+            Matcher::Literal(pattern.to_string())
+        } else {
+            Matcher::CaseInsensitive(fold::fold_str(pattern))
+        };
+        Ok(if config.line_regexp {
+            Matcher::Line(Box::new(matcher))
+        } else if config.word_regexp {
+            Matcher::Word(Box::new(matcher))
+        } else {
+            matcher
+        })
+    }
+
+    /// 一行中第一处匹配的字节区间
+    pub fn find(&self, line: &str) -> Option<(usize, usize)> {
+        self.find_at(line, 0)
+    }
+
+    /// 一行中所有不重叠的匹配区间，用于高亮；-v 选中的行里没有可以高亮的内容
+    pub fn spans(&self, line: &str) -> Vec<(usize, usize)> {
+        let mut spans = Vec::new();
+        if let Matcher::Invert(_) = self {
+            return spans;
+        }
+        let mut at = 0;
+        while let Some((start, end)) = self.find_at(line, at) {
+            if start < end {
+                spans.push((start, end));
+            }
+            // 空匹配时向后移动一个字符，避免死循环
+            at = match line[end..].chars().next() {
+                _ if end > start => end,
+                Some(c) => end + c.len_utf8(),
+                None => break,
+            };
+        }
+        spans
+    }
+
+    /// 从 `at` 开始找最左边的匹配，多个模式在同一位置开始时取最长的
+    pub fn find_at(&self, line: &str, at: usize) -> Option<(usize, usize)> {
+        match self {
+            Matcher::Literal(query) => line[at..]
+                .find(query.as_str())
+                .map(|start| (at + start, at + start + query.len())),
+            Matcher::CaseInsensitive(query) => {
+                fold::find(&line[at..], query).map(|(start, end)| (at + start, at + end))
+            }
+            Matcher::Regex(regex) => regex.find_at(line, at),
+            Matcher::Multi(ac) => ac.find_at(line.as_bytes(), at),
+            Matcher::Any(matchers) => matchers
+                .iter()
+                .filter_map(|matcher| matcher.find_at(line, at))
+                .min_by_key(|&(start, end)| (start, Reverse(end))),
+            Matcher::Word(matcher) => {
+                // 不在单词边界上时从下一个字符开始重新找
+                let mut at = at;
+                loop {
+                    let (start, end) = matcher.find_at(line, at)?;
+                    let before = line[..start].chars().next_back();
+                    let after = line[end..].chars().next();
+                    if start < end
+                        && !before.is_some_and(regex::is_word_char)
+                        && !after.is_some_and(regex::is_word_char)
+                    {
+                        return Some((start, end));
+                    }
+                    at = start + line[start..].chars().next()?.len_utf8();
+                }
+            }
+            Matcher::Line(matcher) => match matcher.find_at(line, at) {
+                Some((0, end)) if end == line.len() => Some((0, end)),
+                _ => None,
+            },
+            Matcher::Fuzzy(fuzzy) => fuzzy.find_at(line, at).map(|m| (m.start, m.end)),
+            Matcher::Invert(matcher) => match matcher.find_at(line, at) {
+                Some(_) => None,
+                None => Some((at, at)),
+            },
+        }
+    }
+
+    /// 和 find_at 一样，另外给出捕获组的区间（下标 0 为整个匹配），用于 --replace
+    pub fn captures_at(&self, line: &str, at: usize) -> Option<Vec<Option<(usize, usize)>>> {
+        match self {
+            Matcher::Regex(regex) => regex.captures_at(line, at),
+            Matcher::Any(matchers) => matchers
+                .iter()
+                .filter_map(|matcher| matcher.captures_at(line, at))
+                .min_by_key(|groups| groups[0].map(|(start, end)| (start, Reverse(end)))),
+            // * 先确定满足 -w / -x 的区间，正则从同一位置找到的也是这个区间时才有捕获组
+            Matcher::Word(matcher) | Matcher::Line(matcher) => {
+                let span = self.find_at(line, at)?;
+                matcher
+                    .captures_at(line, span.0)
+                    .filter(|groups| groups[0] == Some(span))
+                    .or_else(|| Some(vec![Some(span)]))
+            }
+            // -v 选中的行里没有可以替换的内容
+            Matcher::Invert(_) => None,
+            _ => self.find_at(line, at).map(|span| vec![Some(span)]),
+        }
+    }
+
+    /// --fuzzy 时选中的一行和 query 的编辑距离，越小越接近；其他匹配方式没有距离
+    pub fn distance(&self, line: &str) -> Option<usize> {
+        match self {
+            Matcher::Fuzzy(fuzzy) => fuzzy.find_at(line, 0).map(|m| m.distance),
+            Matcher::Any(matchers) => matchers
+                .iter()
+                .filter_map(|matcher| matcher.distance(line))
+                .min(),
+            _ => None,
+        }
+    }
+
+    /// 搜索已经读进内存的整个文件
+    pub fn search<'a>(&self, contents: &'a str) -> Vec<Match<'a>> {
+        let bytes = contents.as_bytes();
+        match self {
+            // * 字面量直接在整个缓冲区上查找，只有找到匹配的行才需要处理
+            Matcher::Literal(query) => {
+                let finder = Finder::new(query);
+                search_buffer(contents, |from| finder.find_at(bytes, from), self)
+            }
+            Matcher::Multi(ac) => search_buffer(contents, |from| ac.find_at(bytes, from), self),
+            _ => search_with(contents, |line| self.find(line)),
+        }
+    }
+}
+
+/// 一处匹配
+/// - line_number 从 1 开始
+/// - offset 为这一行行首在整个文件中的字节偏移
+/// - start..end 为匹配在这一行内的字节区间
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Match<'a> {
+    pub line_number: usize,
+    pub offset: usize,
+    pub start: usize,
+    pub end: usize,
+    pub line: &'a str,
+}
+
+// * 和 lines() 一样去掉行尾的 \n 和 \r\n，但同时给出行号和行首的字节偏移
+pub fn numbered_lines(contents: &str) -> impl Iterator<Item = (usize, usize, &str)> {
     contents
-        .lines()
-        .filter(|line| line.contains(query))
+        .split_inclusive('\n')
+        .scan(0, |offset, raw| {
+            let start = *offset;
+            *offset += raw.len();
+            let line = raw.strip_suffix('\n').unwrap_or(raw);
+            Some((start, line.strip_suffix('\r').unwrap_or(line)))
+        })
+        .enumerate()
+        .map(|(i, (offset, line))| (i + 1, offset, line))
+}
+
+fn search_with<'a>(
+    contents: &'a str,
+    find: impl Fn(&str) -> Option<(usize, usize)>,
+) -> Vec<Match<'a>> {
+    // *使用迭代器适配器来使代码更简明
+    numbered_lines(contents)
+        .filter_map(|(line_number, offset, line)| {
+            find(line).map(|(start, end)| Match {
+                line_number,
+                offset,
+                start,
+                end,
+                line,
+            })
+        })
         .collect()
 }
 
-pub fn search_case_insensitive<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
-    let query = query.to_lowercase();
-    let mut results = Vec::new();
+// * 先在整个缓冲区里找匹配，再找出它所在的行，用 matcher 在这一行里确认匹配区间
+// 模式含有 \n 时缓冲区里的匹配可能跨行，确认时会被排除，所以结果和逐行搜索一致
+fn search_buffer<'a>(
+    contents: &'a str,
+    find: impl Fn(usize) -> Option<(usize, usize)>,
+    matcher: &Matcher,
+) -> Vec<Match<'a>> {
+    let bytes = contents.as_bytes();
+    let mut matches = Vec::new();
+    // * pos 总是某一行的行首，line_number 是它的行号
+    let mut pos = 0;
+    let mut line_number = 1;
+    while pos < bytes.len() {
+        let found = match find(pos) {
+            Some((start, _)) => start,
+            None => break,
+        };
+        // 跳过匹配之前的行，同时数出行号
+        let mut offset = pos;
+        while let Some(i) = fast::memchr(b'\n', &bytes[offset..found]) {
+            offset += i + 1;
+            line_number += 1;
+        }
+        let line_end = fast::memchr(b'\n', &bytes[found..]).map_or(bytes.len(), |i| found + i);
 
-    for line in contents.lines() {
-        if line.to_lowercase().contains(&query) {
-            results.push(line);
+        let line = &contents[offset..line_end];
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if let Some((start, end)) = matcher.find(line) {
+            matches.push(Match {
+                line_number,
+                offset,
+                start,
+                end,
+                line,
+            });
         }
+        pos = line_end + 1;
+        line_number += 1;
     }
+    matches
+}
+
+pub fn search<'a>(query: &str, contents: &'a str) -> Vec<Match<'a>> {
+    search_with(contents, |line| {
+        line.find(query).map(|start| (start, start + query.len()))
+    })
+}
+
+pub fn search_case_insensitive<'a>(query: &str, contents: &'a str) -> Vec<Match<'a>> {
+    // * query 只折叠一次，每一行在比较时逐个字符折叠，不再分配新的 String
+    let query = fold::fold_str(query);
+    search_with(contents, |line| fold::find(line, &query))
+}
+
+/// -w：只选中 query 作为完整单词出现的行
+pub fn search_words<'a>(query: &str, contents: &'a str) -> Vec<Match<'a>> {
+    Matcher::Word(Box::new(Matcher::Literal(query.to_string()))).search(contents)
+}
 
-    results
+/// -x：只选中和 query 完全相同的行
+pub fn search_lines<'a>(query: &str, contents: &'a str) -> Vec<Match<'a>> {
+    Matcher::Line(Box::new(Matcher::Literal(query.to_string()))).search(contents)
+}
+
+/// 近似搜索：和 query 的编辑距离不超过 `max_edits` 的行，连同距离一起返回
+///
+/// 结果按距离从小到大排列，距离相同的按行号
+pub fn search_fuzzy<'a>(
+    query: &str,
+    contents: &'a str,
+    max_edits: usize,
+) -> Vec<(Match<'a>, usize)> {
+    let fuzzy = Fuzzy::new(query, max_edits, true);
+    let mut matches: Vec<(Match, usize)> = numbered_lines(contents)
+        .filter_map(|(line_number, offset, line)| {
+            let m = fuzzy.find_at(line, 0)?;
+            let found = Match {
+                line_number,
+                offset,
+                start: m.start,
+                end: m.end,
+                line,
+            };
+            Some((found, m.distance))
+        })
+        .collect();
+    matches.sort_by_key(|(found, distance)| (*distance, found.line_number));
+    matches
+}
+
+// 忽略大小写通过参数交给 Regex，不改写用户的模式
+pub fn build_regex(query: &str, case_sensitive: bool) -> Result<Regex, regex::ParseError> {
+    Regex::with_case_insensitive(query, !case_sensitive)
+}
+
+pub fn search_regex<'a>(regex: &Regex, contents: &'a str) -> Vec<Match<'a>> {
+    search_with(contents, |line| regex.find(line))
 }
 
 fn main() {
     // * 直接使用 env::args 返回的迭代器
-    let config = Config::new(env::args()).unwrap_or_else(|err| {
-        eprintln!("Problem parsing arguments: {}", err);
-        process::exit(1);
+    let config = Config::load(env::args()).unwrap_or_else(|err| match err {
+        ArgsError::Help(text) | ArgsError::Version(text) => {
+            print!("{}", text);
+            process::exit(0);
+        }
+        err => {
+            let err = MinigrepError::from(err);
+            eprintln!("Problem parsing arguments: {}", err);
+            eprintln!("Try 'minigrep --help' for more information.");
+            process::exit(err.exit_code());
+        }
     });
 
-    // --snip--
+    if config.print_config {
+        print!("{}", config.describe());
+        process::exit(0);
+    }
+
+    // * 退出码：0 有匹配，1 没有匹配，2 有文件没能搜索；run 返回的错误按种类给出退出码
+    let quiet = config.output.mode == OutputMode::Quiet;
+    match run(config) {
+        Ok(summary) => process::exit(summary.exit_code(quiet)),
+        Err(e) => {
+            eprintln!("Application error: {}", e);
+
+            process::exit(e.exit_code());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> impl Iterator<Item = String> {
+        let mut all = vec!["minigrep".to_string()];
+        all.extend(args.iter().map(|arg| arg.to_string()));
+        all.into_iter()
+    }
+
+    #[test]
+    fn walk_options() {
+        let config = Config::new(args(&["--include=*.rs", "--follow", "pool", "src"])).unwrap();
+        assert_eq!(vec!["pool"], config.patterns);
+        assert_eq!(vec!["src"], config.filenames);
+        assert_eq!(1, config.walk.include.len());
+        assert!(config.walk.follow_symlinks);
+
+        assert_eq!(
+            Some(ArgsError::UnknownOption("--colour".to_string())),
+            Config::new(args(&["--colour", "pool", "src"])).err()
+        );
+    }
+
+    fn lines<'a>(matches: Vec<Match<'a>>) -> Vec<&'a str> {
+        matches.into_iter().map(|m| m.line).collect()
+    }
+
+    #[test]
+    fn output_options() {
+        let config = Config::new(args(&["-nbC2", "--after-context=1", "tell", "-"])).unwrap();
+        assert!(config.output.line_number);
+        assert!(config.output.byte_offset);
+        assert_eq!(2, config.output.before_context);
+        assert_eq!(1, config.output.after_context);
+
+        assert_eq!(
+            "invalid value 'x' for option '--after-context'",
+            Config::new(args(&["-A", "x", "tell", "poem.txt"]))
+                .err()
+                .unwrap()
+                .to_string()
+        );
+        assert_eq!(
+            Some(ArgsError::MissingValue("-B".to_string())),
+            Config::new(args(&["tell", "poem.txt", "-B"])).err()
+        );
+    }
+
+    #[test]
+    fn positional_arguments() {
+        let config = Config::new(args(&["-s", "--", "-n", "poem.txt"])).unwrap();
+        assert_eq!(vec!["-n"], config.patterns);
+        assert!(config.case_sensitive);
+        assert!(!config.output.line_number);
+
+        assert_eq!(vec!["-"], Config::new(args(&["tell"])).unwrap().filenames);
+        assert_eq!(
+            vec!["a.txt", "b.txt"],
+            Config::new(args(&["tell", "a.txt", "b.txt"]))
+                .unwrap()
+                .filenames
+        );
+        assert_eq!(
+            Some(ArgsError::MissingArgument("QUERY")),
+            Config::new(args(&[])).err()
+        );
+        assert!(matches!(
+            Config::new(args(&["-h"])).err(),
+            Some(ArgsError::Help(_))
+        ));
+    }
+
+    #[test]
+    fn multiple_patterns() {
+        let config = Config::new(args(&["-e", "safe", "-efast", "-f", "p.txt", "a.txt"])).unwrap();
+        assert_eq!(vec!["safe", "fast"], config.patterns);
+        assert_eq!(vec![PathBuf::from("p.txt")], config.pattern_files);
+        assert_eq!(vec!["a.txt"], config.filenames);
+
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.";
+        let matcher = Matcher::new(&Config::new(args(&["-e", "three", "-e", "Rust"])).unwrap());
+        let matcher = matcher.unwrap();
+        assert!(matches!(matcher, Matcher::Multi(_)));
+        assert_eq!(
+            vec!["Rust:", "Pick three."],
+            lines(matcher.search(contents))
+        );
+
+        // 区间取最左边的，同一位置开始时取最长的
+        let matcher =
+            Matcher::new(&Config::new(args(&["-i", "-e", "FAST", "-e", "safe, f"])).unwrap());
+        let matcher = matcher.unwrap();
+        assert!(matches!(matcher, Matcher::Any(_)));
+        assert_eq!(Some((0, 7)), matcher.find("safe, fast, productive."));
+    }
+
+    #[test]
+    fn whole_buffer_search() {
+        let contents = "ab\r\n\nxab\nbab\na\nbx";
+        for query in ["ab", "b", "", "a\nb", "\r"] {
+            let matcher = Matcher::Literal(query.to_string());
+            assert_eq!(
+                search(query, contents),
+                matcher.search(contents),
+                "{:?}",
+                query
+            );
+
+            let ac = Matcher::Multi(AhoCorasick::new(&[query, "zz"]));
+            assert_eq!(search(query, contents), ac.search(contents), "{:?}", query);
+        }
+    }
+
+    #[test]
+    fn binary_files() {
+        let path = env::temp_dir().join(format!("minigrep_binary_{}", process::id()));
+        fs::write(&path, b"ok\0\ntell me\n").unwrap();
+        let input = Input::File {
+            path: path.clone(),
+            walked: false,
+        };
+        let matcher = Matcher::Literal("tell".to_string());
+        let search = |binary| {
+            let options = OutputOptions {
+                binary,
+                ..OutputOptions::default()
+            };
+            let mut out = Vec::new();
+            let mut printer = Printer::new(options.clone());
+            let count = search_input(&input, &matcher, &options, &mut printer, &mut out).unwrap();
+            (count, String::from_utf8(out).unwrap())
+        };
+        let matches = search(BinaryMode::Matches);
+        let text = search(BinaryMode::Text);
+        let skip = search(BinaryMode::Skip);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            (1, format!("Binary file {} matches\n", path.display())),
+            matches
+        );
+        assert_eq!((1, "tell me\n".to_string()), text);
+        assert_eq!((0, String::new()), skip);
+
+        let config = Config::new(args(&["-a", "--binary-skip", "tell"])).unwrap();
+        assert_eq!(BinaryMode::Skip, config.output.binary);
+    }
+
+    #[test]
+    fn match_records() {
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.";
+
+        assert_eq!(
+            vec![Match {
+                line_number: 2,
+                offset: 6,
+                start: 15,
+                end: 19,
+                line: "safe, fast, productive.",
+            }],
+            search("duct", contents)
+        );
+    }
+
+    #[test]
+    fn case_insensitive_span() {
+        // 'İ' 折叠后变成 3 个字节，区间仍然是原始行中的区间
+        let contents = "xİSTANBUL";
+        let m = search_case_insensitive("stan", contents)[0];
+        assert_eq!("STAN", &m.line[m.start..m.end]);
+    }
+
+    #[test]
+    fn grep_modes() {
+        let config = Config::new(args(&["-vcm", "3", "tell", "poem.txt"])).unwrap();
+        assert!(config.invert_match);
+        assert_eq!(OutputMode::Count, config.output.mode);
+        assert_eq!(Some(3), config.output.max_count);
+
+        let config = Config::new(args(&["-cq", "tell", "poem.txt"])).unwrap();
+        assert_eq!(OutputMode::Quiet, config.output.mode);
+        assert_eq!(Some(1), config.output.limit());
+
+        let config = Config::new(args(&["--json", "-m", "2", "tell", "poem.txt"])).unwrap();
+        assert_eq!(OutputMode::Json, config.output.mode);
+        assert_eq!(Some(2), config.output.limit());
+    }
+
+    #[test]
+    fn replace_options() {
+        let config = Config::new(args(&["--replace=$1", "-E", "(t)ell", "poem.txt"])).unwrap();
+        assert_eq!(Some("$1".to_string()), config.replace);
+        assert!(!config.in_place);
+        let config = Config::new(args(&["--replace", "x", "--in-place", "tell", "poem.txt"]));
+        assert!(config.unwrap().in_place);
+
+        let error = Config::new(args(&["--in-place", "tell", "poem.txt"])).err();
+        assert_eq!(
+            Some("option '--in-place' requires '--replace'".to_string()),
+            error.map(|e| e.to_string())
+        );
+    }
+
+    #[test]
+    fn watch_options() {
+        let config = Config::new(args(&["--watch", "-n", "ERROR", "app.log", "logs"])).unwrap();
+        assert!(config.watch);
+
+        let error = Config::new(args(&["--watch", "ERROR"])).err();
+        assert_eq!(Some(ArgsError::Requires("--watch", "FILE")), error);
+        let error = Config::new(args(&["--interactive", "--watch", "ERROR", "app.log"])).err();
+        assert_eq!(
+            Some(ArgsError::Conflicts("--interactive", "--watch")),
+            error
+        );
+        let error = Config::new(args(&["--watch", "-l", "ERROR", "app.log"])).err();
+        assert_eq!(
+            Some("option '--watch' can't be used with '--files-with-matches'".to_string()),
+            error.map(|e| e.to_string())
+        );
+    }
+
+    #[test]
+    fn invert_match() {
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.";
+
+        let matcher = Matcher::Invert(Box::new(Matcher::Literal("e".to_string())));
+        assert_eq!(vec!["Rust:"], lines(matcher.search(contents)));
+    }
+
+    #[test]
+    fn words_and_lines() {
+        let contents = "\
+trust
+rust_lang
+Rust:
+rust, née Rust
+naïverust
+rust";
+        let words = search_words("rust", contents);
+        assert_eq!(vec!["rust, née Rust", "rust"], lines(words.clone()));
+        assert_eq!((0, 4), (words[0].start, words[0].end));
+        // é、ï 也是单词字符
+        assert!(search_words("e", "née").is_empty());
+        assert_eq!(vec!["rust"], lines(search_lines("rust", contents)));
+
+        let config = Config::new(args(&["-ix", "-E", "rust|rust:", "poem.txt"])).unwrap();
+        let matcher = Matcher::new(&config).unwrap();
+        assert_eq!(vec!["Rust:", "rust"], lines(matcher.search(contents)));
+        let config = Config::new(args(&["-w", "-e", "rust", "-e", "rust, née", "f"])).unwrap();
+        let matcher = Matcher::new(&config).unwrap();
+        assert_eq!(Some((0, 10)), matcher.find("rust, née Rust"));
+    }
+
+    #[test]
+    fn fuzzy_matching() {
+        let contents = "\
+Rust:
+safe, fast, prodcutive.
+Pick three.
+productive
+unproductive days";
+        let found = search_fuzzy("productive", contents, 2);
+        let closest: Vec<(usize, usize)> = found
+            .iter()
+            .map(|(m, distance)| (m.line_number, *distance))
+            .collect();
+        assert_eq!(vec![(4, 0), (5, 0), (2, 2)], closest);
+        assert_eq!(
+            "prodcutive",
+            &found[2].0.line[found[2].0.start..found[2].0.end]
+        );
+
+        let config = Config::new(args(&["--fuzzy=1", "-iw", "PRODUCTIV", "poem.txt"])).unwrap();
+        assert_eq!(Some(1), config.max_edits);
+        let matcher = Matcher::new(&config).unwrap();
+        assert_eq!(vec!["productive"], lines(matcher.search(contents)));
+        assert_eq!(Some(1), matcher.distance("productive"));
+
+        let error = Config::new(args(&["--fuzzy", "1", "-E", "rust", "poem.txt"])).err();
+        assert_eq!(Some(ArgsError::Conflicts("--fuzzy", "--regex")), error);
+    }
+
+    #[test]
+    fn exit_codes() {
+        let summary = |matched, errors| Summary {
+            matched,
+            errors,
+            ..Summary::default()
+        };
+        assert_eq!(0, summary(true, 0).exit_code(false));
+        assert_eq!(1, summary(false, 0).exit_code(false));
+        assert_eq!(2, summary(true, 1).exit_code(false));
+        assert_eq!(0, summary(true, 1).exit_code(true));
+        assert_eq!(2, summary(false, 1).exit_code(true));
+
+        // 命令行上给出的文件读不了时也只报告，不中断搜索；只有标准输出被关闭才中断
+        let named = Input::File {
+            path: PathBuf::from("nope.txt"),
+            walked: false,
+        };
+        assert!(report_error(&named, io::ErrorKind::NotFound.into()).is_ok());
+        assert!(report_error(&named, io::ErrorKind::BrokenPipe.into()).is_err());
+
+        let mut summary = Summary::default();
+        for count in [2, 0, 1] {
+            summary.add(count);
+        }
+        assert!(summary.matched);
+        assert_eq!(
+            (3, 2, 3),
+            (
+                summary.searched,
+                summary.files_matched,
+                summary.matched_lines
+            )
+        );
+    }
+
+    #[test]
+    fn color_option() {
+        let config = Config::new(args(&["--color=always", "tell", "poem.txt"])).unwrap();
+        assert_eq!(ColorChoice::Always, config.color);
+        assert_eq!(
+            ColorChoice::Auto,
+            Config::new(args(&["tell"])).unwrap().color
+        );
+        assert_eq!(
+            Some(ArgsError::InvalidValue {
+                option: "--color".to_string(),
+                value: "yes".to_string()
+            }),
+            Config::new(args(&["--color", "yes", "tell"])).err()
+        );
+    }
+
+    #[test]
+    fn every_span() {
+        let matcher = Matcher::Literal("ab".to_string());
+        assert_eq!(vec![(0, 2), (3, 5)], matcher.spans("ab ab"));
+
+        // "ẞ" 和 "ß" 都折叠成 "ss"，"İ" 折叠成 "i̇"，字节长度都会变化
+        let matcher = Matcher::CaseInsensitive(fold::fold_str("ß"));
+        assert_eq!(vec![(0, 3), (4, 6), (7, 9)], matcher.spans("ẞ ß SS"));
+        let matcher = Matcher::CaseInsensitive(fold::fold_str("i"));
+        assert_eq!(vec![(3, 4)], matcher.spans("İ i"));
+
+        let matcher = Matcher::Regex(build_regex("a*", true).unwrap());
+        assert_eq!(vec![(1, 3)], matcher.spans("baa"));
+        let matcher = Matcher::Invert(Box::new(Matcher::Literal("x".to_string())));
+        assert!(matcher.spans("abc").is_empty());
+    }
+
+    #[test]
+    fn layered_sources() {
+        let path = Path::new("/repo/.minigreprc");
+        let files = profile::parse_file(
+            OPTIONS,
+            path,
+            "--color=never\n-C1\n--exclude=target\n",
+            None,
+            true,
+        )
+        .unwrap();
+        let parsed =
+            args::parse(OPTIONS, args(&["-A3", "--exclude=*.log", "tell"]).skip(1)).unwrap();
+        let config = Config::build(files, parsed).unwrap();
+
+        assert_eq!(ColorChoice::Never, config.color);
+        assert_eq!(
+            (1, 3),
+            (config.output.before_context, config.output.after_context)
+        );
+        assert_eq!(2, config.walk.exclude.len());
+
+        let described = config.describe();
+        let line = |name: &str| {
+            described
+                .lines()
+                .find(|line| line.starts_with(&format!("{} =", name)))
+                .unwrap()
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        assert_eq!(
+            "color = never # project config /repo/.minigreprc",
+            line("color")
+        );
+        assert_eq!(
+            "before-context = 1 # project config /repo/.minigreprc",
+            line("before-context")
+        );
+        assert_eq!("after-context = 3 # command line", line("after-context"));
+        assert_eq!(
+            "exclude = target, *.log # project config /repo/.minigreprc, command line",
+            line("exclude")
+        );
+        assert_eq!("follow = false # default", line("follow"));
+    }
+
+    #[test]
+    fn negated_switches() {
+        let path = Path::new("/repo/.minigreprc");
+        let files = profile::parse_file(
+            OPTIONS,
+            path,
+            "-nv\n--json\n--follow\n--no-ignore\n--binary-skip\n",
+            None,
+            true,
+        )
+        .unwrap();
+        let parsed = args::parse(
+            OPTIONS,
+            args(&[
+                "--no-line-number",
+                "--no-invert-match",
+                "--no-count",
+                "--no-follow",
+                "--ignore",
+                "--no-binary-skip",
+                "tell",
+            ])
+            .skip(1),
+        )
+        .unwrap();
+        let config = Config::build(files, parsed).unwrap();
+        assert!(!config.output.line_number);
+        assert!(!config.invert_match);
+        // --no-count 不影响配置文件给出的 --json
+        assert_eq!(OutputMode::Json, config.output.mode);
+        assert!(!config.walk.follow_symlinks);
+        assert!(!config.walk.no_ignore);
+        assert_eq!(BinaryMode::Matches, config.output.binary);
+        assert!(config
+            .describe()
+            .lines()
+            .any(|line| line.starts_with("follow = false ") && line.ends_with("# command line")));
+
+        let config = Config::new(args(&["--json", "--no-json", "tell"])).unwrap();
+        assert_eq!(OutputMode::Lines, config.output.mode);
+    }
+
+    #[test]
+    fn regex_mode() {
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Trust me.";
+
+        let regex = build_regex(r"^\w+:$|ve\.$", true).unwrap();
+        assert_eq!(
+            vec!["Rust:", "safe, fast, productive."],
+            lines(search_regex(&regex, contents))
+        );
+
+        let regex = build_regex("^(pick|trust)", false).unwrap();
+        assert_eq!(
+            vec!["Pick three.", "Trust me."],
+            lines(search_regex(&regex, contents))
+        );
+    }
+
+    #[test]
+    fn regex_parse_error() {
+        let err = build_regex("(rust", true).unwrap_err();
+        assert_eq!(
+            "regex parse error at position 0: unclosed group",
+            err.to_string()
+        );
+        // -i 不改写模式：错误位置不变，模式自己带 (?i) 也可以
+        let err = build_regex("(rust", false).unwrap_err();
+        assert_eq!(
+            "regex parse error at position 0: unclosed group",
+            err.to_string()
+        );
+        assert!(build_regex("(?i)rust", false).unwrap().is_match("RUST"));
+    }
 }
@@ -0,0 +1,900 @@
+///* minigrep 的正则匹配模式
+// 一个自带的小型正则引擎，不依赖外部 crate：
+// - 先把模式解析成语法树（Node），再编译成指令序列（Inst），最后用 Pike VM（Thompson NFA 模拟）执行
+// - 同一时刻每条指令最多只有一个线程，所以匹配时间与 文本长度 × 指令数 成线性关系，不会像回溯引擎那样指数爆炸
+//
+// 支持的语法：
+// - 字面量、`.`（不匹配换行）、转义 `\.` `\n` `\t`
+// - 字符类 `[a-z_]` `[^0-9]`，以及 `\d \w \s \D \W \S`
+// - 锚点 `^` `$`，单词边界 `\b` `\B`
+// - 分组 `(...)`、非捕获分组 `(?:...)`、选择 `a|b`
+// - 重复 `*` `+` `?` `{n}` `{n,}` `{n,m}`，后面再加 `?` 为惰性匹配
+// - 模式开头的 `(?i)` 表示忽略大小写：和普通的 -i 一样按 Unicode 完全折叠比较（见 14case_fold.rs），
+//   模式中的字面量编译成折叠后的字符，文本中的一个字符折叠成几个字符时要连续匹配几条 Char 指令，
+//   所以 `(?i)straße` 能匹配 "STRASSE"，`(?i)ss` 也能匹配 "ß"，但匹配不能停在 'ß' 的中间
+use std::error::Error;
+use std::fmt;
+
+use crate::fold::fold_char;
+
+// * 重复次数的上限，避免 a{100000} 这种模式编译出巨大的指令序列
+const MAX_REPEAT: u32 = 1000;
+
+// * 编译后的指令数上限：嵌套的重复会相乘，((a{1000}){1000}){1000} 每一层都没超过 MAX_REPEAT，
+//   展开后却有 10 亿条指令，所以编译之前先算出总数
+const MAX_INSTS: usize = 100_000;
+
+// * 解析错误：记录出错的位置（按字符计）和原因，通过 Box<dyn Error> 一路返回给 run 的调用者
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub pos: usize,
+    pub msg: &'static str,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "regex parse error at position {}: {}",
+            self.pos, self.msg
+        )
+    }
+}
+
+impl Error for ParseError {}
+
+// ---------------------------------------------------------------------
+// 语法树
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Perl {
+    Digit,
+    Word,
+    Space,
+}
+
+impl Perl {
+    fn matches(self, c: char) -> bool {
+        match self {
+            Perl::Digit => c.is_ascii_digit(),
+            Perl::Word => is_word_char(c),
+            Perl::Space => c.is_whitespace(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ClassItem {
+    Range(char, char),
+    // * bool 表示取反，例如 \D
+    Perl(Perl, bool),
+}
+
+impl ClassItem {
+    fn matches(&self, c: char) -> bool {
+        match *self {
+            ClassItem::Range(lo, hi) => lo <= c && c <= hi,
+            ClassItem::Perl(perl, negated) => perl.matches(c) != negated,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Class {
+    items: Vec<ClassItem>,
+    negated: bool,
+}
+
+impl Class {
+    fn matches(&self, c: char, case_insensitive: bool) -> bool {
+        let hit = if case_insensitive {
+            // 忽略大小写时，原字符、折叠后的字符、大写任意一个落在字符类中都算命中
+            let (folded, upper) = (simple_fold(c), simple_upper(c));
+            self.items
+                .iter()
+                .any(|item| item.matches(c) || item.matches(folded) || item.matches(upper))
+        } else {
+            self.items.iter().any(|item| item.matches(c))
+        };
+        hit != self.negated
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Look {
+    Start,
+    End,
+    // * true 为 \b，false 为 \B
+    WordBoundary(bool),
+}
+
+impl Look {
+    fn holds(self, text: &str, at: usize) -> bool {
+        match self {
+            Look::Start => at == 0,
+            Look::End => at == text.len(),
+            Look::WordBoundary(expect) => {
+                let before = text[..at].chars().next_back().is_some_and(is_word_char);
+                let after = text[at..].chars().next().is_some_and(is_word_char);
+                (before != after) == expect
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Empty,
+    Char(char),
+    Any,
+    Class(Class),
+    Look(Look),
+    // * Some(i) 为第 i 个捕获分组，None 为非捕获分组
+    Group(Box<Node>, Option<usize>),
+    Concat(Vec<Node>),
+    Alt(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: u32,
+        max: Option<u32>,
+        greedy: bool,
+    },
+}
+
+// ---------------------------------------------------------------------
+// 解析器：递归下降，优先级从低到高依次为 选择 -> 连接 -> 重复 -> 原子
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    groups: usize,
+}
+
+impl Parser {
+    fn error<T>(&self, pos: usize, msg: &'static str) -> Result<T, ParseError> {
+        Err(ParseError { pos, msg })
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek();
+        if c.is_some() {
+            self.pos += 1;
+        }
+        c
+    }
+
+    fn eat(&mut self, s: &str) -> bool {
+        let n = s.chars().count();
+        if self.pos + n <= self.chars.len()
+            && s.chars()
+                .eq(self.chars[self.pos..self.pos + n].iter().copied())
+        {
+            self.pos += n;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse(mut self) -> Result<(Node, usize), ParseError> {
+        let node = self.parse_alt()?;
+        // parse_alt 只会在遇到 ')' 或者结尾时停下
+        if self.peek().is_some() {
+            return self.error(self.pos, "unmatched ')'");
+        }
+        Ok((node, self.groups))
+    }
+
+    fn parse_alt(&mut self) -> Result<Node, ParseError> {
+        let mut branches = vec![self.parse_concat()?];
+        while self.peek() == Some('|') {
+            self.bump();
+            branches.push(self.parse_concat()?);
+        }
+        Ok(if branches.len() == 1 {
+            branches.pop().unwrap()
+        } else {
+            Node::Alt(branches)
+        })
+    }
+
+    fn parse_concat(&mut self) -> Result<Node, ParseError> {
+        let mut items = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.parse_atom()?;
+            items.push(self.parse_repeat(atom)?);
+        }
+        Ok(match items.len() {
+            0 => Node::Empty,
+            1 => items.pop().unwrap(),
+            _ => Node::Concat(items),
+        })
+    }
+
+    fn parse_atom(&mut self) -> Result<Node, ParseError> {
+        let start = self.pos;
+        let c = self.bump().unwrap();
+        match c {
+            '(' => {
+                let index = if self.eat("?:") {
+                    None
+                } else if self.peek() == Some('?') {
+                    return self.error(self.pos, "unsupported group flag");
+                } else {
+                    self.groups += 1;
+                    Some(self.groups)
+                };
+                let inner = self.parse_alt()?;
+                if self.bump() != Some(')') {
+                    return self.error(start, "unclosed group");
+                }
+                Ok(Node::Group(Box::new(inner), index))
+            }
+            '[' => self.parse_class(start),
+            '.' => Ok(Node::Any),
+            '^' => Ok(Node::Look(Look::Start)),
+            '$' => Ok(Node::Look(Look::End)),
+            '\\' => self.parse_escape(start),
+            '*' | '+' | '?' => self.error(start, "nothing to repeat"),
+            c => Ok(Node::Char(c)),
+        }
+    }
+
+    fn parse_escape(&mut self, start: usize) -> Result<Node, ParseError> {
+        match self.bump() {
+            None => self.error(start, "trailing backslash"),
+            Some('b') => Ok(Node::Look(Look::WordBoundary(true))),
+            Some('B') => Ok(Node::Look(Look::WordBoundary(false))),
+            Some(c) => match self.escape_item(c, start)? {
+                ClassItem::Range(c, _) => Ok(Node::Char(c)),
+                item => Ok(Node::Class(Class {
+                    items: vec![item],
+                    negated: false,
+                })),
+            },
+        }
+    }
+
+    // * 反斜杠后面的字符，在字符类内外含义相同，统一在这里处理
+    fn escape_item(&self, c: char, start: usize) -> Result<ClassItem, ParseError> {
+        let item = match c {
+            'd' => ClassItem::Perl(Perl::Digit, false),
+            'D' => ClassItem::Perl(Perl::Digit, true),
+            'w' => ClassItem::Perl(Perl::Word, false),
+            'W' => ClassItem::Perl(Perl::Word, true),
+            's' => ClassItem::Perl(Perl::Space, false),
+            'S' => ClassItem::Perl(Perl::Space, true),
+            'n' => ClassItem::Range('\n', '\n'),
+            't' => ClassItem::Range('\t', '\t'),
+            'r' => ClassItem::Range('\r', '\r'),
+            c if c.is_alphanumeric() => return self.error(start, "unknown escape sequence"),
+            c => ClassItem::Range(c, c),
+        };
+        Ok(item)
+    }
+
+    fn parse_class(&mut self, start: usize) -> Result<Node, ParseError> {
+        let negated = self.eat("^");
+        let mut items = Vec::new();
+        let mut first = true;
+        loop {
+            let item_start = self.pos;
+            let lo = match self.bump() {
+                None => return self.error(start, "unclosed character class"),
+                // 紧跟在 [ 或 [^ 之后的 ] 当作普通字符
+                Some(']') if !first => break,
+                Some('\\') => {
+                    let c = match self.bump() {
+                        Some(c) => c,
+                        None => return self.error(item_start, "trailing backslash"),
+                    };
+                    self.escape_item(c, item_start)?
+                }
+                Some(c) => ClassItem::Range(c, c),
+            };
+            first = false;
+
+            // a-z 形式的区间；末尾的 - 当作普通字符
+            let is_range = self.peek() == Some('-')
+                && !matches!(self.chars.get(self.pos + 1), Some(']') | None);
+            if !is_range {
+                items.push(lo);
+                continue;
+            }
+            self.bump();
+            let hi = match self.bump() {
+                Some('\\') => match self.bump() {
+                    Some(c) => self.escape_item(c, item_start)?,
+                    None => return self.error(item_start, "trailing backslash"),
+                },
+                Some(c) => ClassItem::Range(c, c),
+                None => return self.error(start, "unclosed character class"),
+            };
+            match (lo, hi) {
+                (ClassItem::Range(lo, _), ClassItem::Range(hi, _)) if lo <= hi => {
+                    items.push(ClassItem::Range(lo, hi))
+                }
+                _ => return self.error(item_start, "invalid range in character class"),
+            }
+        }
+        Ok(Node::Class(Class { items, negated }))
+    }
+
+    fn parse_repeat(&mut self, mut atom: Node) -> Result<Node, ParseError> {
+        loop {
+            let start = self.pos;
+            let (min, max) = match self.peek() {
+                Some('{') => match self.parse_counted()? {
+                    Some(bounds) => bounds,
+                    // 不是合法的 {n,m}，把 { 当作普通字符留给下一个原子
+                    None => return Ok(atom),
+                },
+                Some(op @ ('*' | '+' | '?')) => {
+                    self.bump();
+                    match op {
+                        '*' => (0, None),
+                        '+' => (1, None),
+                        _ => (0, Some(1)),
+                    }
+                }
+                _ => return Ok(atom),
+            };
+            if let Some(max) = max {
+                if min > max {
+                    return self.error(start, "invalid repetition range");
+                }
+            }
+            if min > MAX_REPEAT || max.is_some_and(|max| max > MAX_REPEAT) {
+                return self.error(start, "repetition count too large");
+            }
+            let greedy = !self.eat("?");
+            atom = Node::Repeat {
+                node: Box::new(atom),
+                min,
+                max,
+                greedy,
+            };
+        }
+    }
+
+    // * 解析 {n} {n,} {n,m}，成功时消耗掉整个花括号
+    fn parse_counted(&mut self) -> Result<Option<(u32, Option<u32>)>, ParseError> {
+        let start = self.pos;
+        let mut end = start + 1;
+        while end < self.chars.len() && self.chars[end] != '}' {
+            end += 1;
+        }
+        if end == self.chars.len() {
+            return Ok(None);
+        }
+        let body: String = self.chars[start + 1..end].iter().collect();
+        let number = |s: &str| -> Result<Option<u32>, ParseError> {
+            if s.is_empty() || !s.chars().all(|c| c.is_ascii_digit()) {
+                return Ok(None);
+            }
+            s.parse().map(Some).or(Err(ParseError {
+                pos: start,
+                msg: "repetition count too large",
+            }))
+        };
+        let bounds = match body.split_once(',') {
+            None => number(&body)?.map(|n| (n, Some(n))),
+            Some((lo, "")) => number(lo)?.map(|n| (n, None)),
+            Some((lo, hi)) => match (number(lo)?, number(hi)?) {
+                (Some(lo), Some(hi)) => Some((lo, Some(hi))),
+                _ => None,
+            },
+        };
+        if bounds.is_some() {
+            self.pos = end + 1;
+        }
+        Ok(bounds)
+    }
+}
+
+// ---------------------------------------------------------------------
+// 编译：语法树 -> 指令序列
+
+#[derive(Debug, Clone)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(Class),
+    Look(Look),
+    // * 两个分支都尝试，第一个优先级更高
+    Split(usize, usize),
+    Jmp(usize),
+    // * 把当前位置记录到捕获槽里
+    Save(usize),
+    Match,
+}
+
+struct Compiler {
+    insts: Vec<Inst>,
+    // * 忽略大小写时字面量编译成折叠后的字符
+    case_insensitive: bool,
+}
+
+impl Compiler {
+    fn push(&mut self, inst: Inst) -> usize {
+        self.insts.push(inst);
+        self.insts.len() - 1
+    }
+
+    fn pc(&self) -> usize {
+        self.insts.len()
+    }
+
+    fn patch_second(&mut self, at: usize, target: usize) {
+        match &mut self.insts[at] {
+            Inst::Split(_, second) => *second = target,
+            Inst::Jmp(to) => *to = target,
+            _ => unreachable!(),
+        }
+    }
+
+    fn split(&mut self) -> usize {
+        // 先占位，分支目标等编译完循环体再回填
+        let next = self.pc() + 1;
+        self.push(Inst::Split(next, next))
+    }
+
+    // 和 compile 的展开方式一致，算出 node 编译后的指令数；用饱和运算，不会溢出
+    fn size(&self, node: &Node) -> usize {
+        match node {
+            Node::Empty => 0,
+            Node::Char(c) if self.case_insensitive => fold_char(*c).count(),
+            Node::Char(_) | Node::Any | Node::Class(_) | Node::Look(_) => 1,
+            Node::Group(inner, index) => self.size(inner) + if index.is_some() { 2 } else { 0 },
+            Node::Concat(items) => items
+                .iter()
+                .fold(0, |total, item| total.saturating_add(self.size(item))),
+            // 除了最后一个分支，每个分支前有一条 Split，后面有一条 Jmp
+            Node::Alt(branches) => branches
+                .iter()
+                .fold(2 * (branches.len() - 1), |total, branch| {
+                    total.saturating_add(self.size(branch))
+                }),
+            Node::Repeat { node, min, max, .. } => {
+                let body = self.size(node);
+                let optional = match max {
+                    None => body.saturating_add(2),
+                    Some(max) => body.saturating_add(1).saturating_mul((max - min) as usize),
+                };
+                body.saturating_mul(*min as usize).saturating_add(optional)
+            }
+        }
+    }
+
+    fn compile(&mut self, node: &Node) {
+        match node {
+            Node::Empty => {}
+            Node::Char(c) if self.case_insensitive => {
+                for folded in fold_char(*c) {
+                    self.push(Inst::Char(folded));
+                }
+            }
+            Node::Char(c) => {
+                self.push(Inst::Char(*c));
+            }
+            Node::Any => {
+                self.push(Inst::Any);
+            }
+            Node::Class(class) => {
+                self.push(Inst::Class(class.clone()));
+            }
+            Node::Look(look) => {
+                self.push(Inst::Look(*look));
+            }
+            Node::Group(inner, index) => match index {
+                Some(i) => {
+                    self.push(Inst::Save(2 * i));
+                    self.compile(inner);
+                    self.push(Inst::Save(2 * i + 1));
+                }
+                None => self.compile(inner),
+            },
+            Node::Concat(items) => {
+                for item in items {
+                    self.compile(item);
+                }
+            }
+            Node::Alt(branches) => {
+                let mut jumps = Vec::new();
+                let (last, rest) = branches.split_last().unwrap();
+                for branch in rest {
+                    let split = self.split();
+                    self.compile(branch);
+                    jumps.push(self.push(Inst::Jmp(0)));
+                    let next = self.pc();
+                    self.patch_second(split, next);
+                }
+                self.compile(last);
+                let end = self.pc();
+                for jump in jumps {
+                    self.patch_second(jump, end);
+                }
+            }
+            Node::Repeat {
+                node,
+                min,
+                max,
+                greedy,
+            } => {
+                for _ in 0..*min {
+                    self.compile(node);
+                }
+                match max {
+                    // * 没有上限：L: split(body, end); body; jmp L
+                    None => {
+                        let split = self.split();
+                        self.compile(node);
+                        self.push(Inst::Jmp(split));
+                        let end = self.pc();
+                        self.finish_split(split, end, *greedy);
+                    }
+                    // * 有上限：剩下的 max - min 次都是可选的，跳过任何一次都直接到结尾
+                    Some(max) => {
+                        let mut splits = Vec::new();
+                        for _ in *min..*max {
+                            splits.push(self.split());
+                            self.compile(node);
+                        }
+                        let end = self.pc();
+                        for split in splits {
+                            self.finish_split(split, end, *greedy);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // 贪婪时优先进入循环体，惰性时优先跳过
+    fn finish_split(&mut self, split: usize, end: usize, greedy: bool) {
+        let body = split + 1;
+        self.insts[split] = if greedy {
+            Inst::Split(body, end)
+        } else {
+            Inst::Split(end, body)
+        };
+    }
+}
+
+// ---------------------------------------------------------------------
+// 执行：Pike VM
+
+type Slots = Vec<Option<usize>>;
+
+// * 线程列表：按优先级保存线程，seen 用来保证每条指令在同一位置只加入一次
+struct Threads {
+    seen: Vec<usize>,
+    stamp: usize,
+    list: Vec<(usize, Slots)>,
+}
+
+impl Threads {
+    fn new(size: usize) -> Threads {
+        Threads {
+            seen: vec![0; size],
+            stamp: 1,
+            list: Vec::new(),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.stamp += 1;
+        self.list.clear();
+    }
+
+    fn visit(&mut self, pc: usize) -> bool {
+        if self.seen[pc] == self.stamp {
+            return false;
+        }
+        self.seen[pc] = self.stamp;
+        true
+    }
+}
+
+enum Frame {
+    Explore(usize),
+    Restore(usize, Option<usize>),
+}
+
+/// 编译好的正则表达式，在 run 中只编译一次，之后对每一行复用
+#[derive(Debug, Clone)]
+pub struct Regex {
+    insts: Vec<Inst>,
+    slots: usize,
+    case_insensitive: bool,
+}
+
+impl Regex {
+    /// 解析并编译 `pattern`，语法错误时返回 `ParseError`
+    pub fn new(pattern: &str) -> Result<Regex, ParseError> {
+        Regex::with_case_insensitive(pattern, false)
+    }
+
+    /// 和 new 一样；`case_insensitive` 为 true 时忽略大小写，效果和模式开头写 `(?i)` 相同
+    ///
+    /// 不改写模式，错误的位置和用户写的一致，模式本身以 `(?i)` 开头也没问题
+    pub fn with_case_insensitive(
+        pattern: &str,
+        case_insensitive: bool,
+    ) -> Result<Regex, ParseError> {
+        let mut parser = Parser {
+            chars: pattern.chars().collect(),
+            pos: 0,
+            groups: 0,
+        };
+        let case_insensitive = parser.eat("(?i)") || case_insensitive;
+        let (node, groups) = parser.parse()?;
+
+        let mut compiler = Compiler {
+            insts: Vec::new(),
+            case_insensitive,
+        };
+        if compiler.size(&node) > MAX_INSTS {
+            return Err(ParseError {
+                pos: 0,
+                msg: "pattern too large",
+            });
+        }
+        compiler.push(Inst::Save(0));
+        compiler.compile(&node);
+        compiler.push(Inst::Save(1));
+        compiler.push(Inst::Match);
+
+        Ok(Regex {
+            insts: compiler.insts,
+            slots: 2 * (groups + 1),
+            case_insensitive,
+        })
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        self.find(text).is_some()
+    }
+
+    /// 返回最左边匹配的字节区间 `(start, end)`
+    pub fn find(&self, text: &str) -> Option<(usize, usize)> {
+        self.find_at(text, 0)
+    }
+
+    /// 从字节位置 `start` 开始查找；锚点和单词边界仍然参照整个 `text` 判断
+    pub fn find_at(&self, text: &str, start: usize) -> Option<(usize, usize)> {
+        let slots = self.exec(text, start)?;
+        Some((slots[0]?, slots[1]?))
+    }
+
+    /// 和 find_at 一样，另外给出每个捕获组的区间：下标 0 为整个匹配，没有参与匹配的分组为 None
+    pub fn captures_at(&self, text: &str, start: usize) -> Option<Vec<Option<(usize, usize)>>> {
+        let slots = self.exec(text, start)?;
+        Some(
+            slots
+                .chunks(2)
+                .map(|pair| Some((pair[0]?, pair[1]?)))
+                .collect(),
+        )
+    }
+
+    fn exec(&self, text: &str, start: usize) -> Option<Slots> {
+        let mut clist = Threads::new(self.insts.len());
+        let mut nlist = Threads::new(self.insts.len());
+        let mut matched = None;
+        let mut at = start;
+
+        loop {
+            // * 还没找到匹配时，在每个位置都起一个新线程（优先级最低），相当于模式前面隐含了 .*?
+            if matched.is_none() {
+                self.add_thread(&mut clist, 0, at, text, &mut vec![None; self.slots]);
+            }
+            if clist.list.is_empty() && matched.is_some() {
+                break;
+            }
+
+            let ch = text[at..].chars().next();
+            let next = at + ch.map_or(0, char::len_utf8);
+            for (pc, mut slots) in std::mem::take(&mut clist.list) {
+                // 匹配当前字符之后线程到达的指令
+                let step = match &self.insts[pc] {
+                    Inst::Match => {
+                        // * 更低优先级的线程全部丢弃，这就是 leftmost-first 语义
+                        matched = Some(slots);
+                        break;
+                    }
+                    Inst::Char(_) => ch.and_then(|ch| self.char_step(pc, ch)),
+                    Inst::Any => ch.filter(|&ch| ch != '\n').map(|_| pc + 1),
+                    Inst::Class(class) => ch
+                        .filter(|&ch| class.matches(ch, self.case_insensitive))
+                        .map(|_| pc + 1),
+                    _ => unreachable!(),
+                };
+                if let Some(to) = step {
+                    self.add_thread(&mut nlist, to, next, text, &mut slots);
+                }
+            }
+
+            if ch.is_none() {
+                break;
+            }
+            at = next;
+            std::mem::swap(&mut clist, &mut nlist);
+            nlist.clear();
+        }
+
+        matched
+    }
+
+    // * 沿着 Split/Jmp/Save/Look 这些不消耗字符的指令展开，用显式栈避免深度递归
+    fn add_thread(&self, list: &mut Threads, pc: usize, at: usize, text: &str, slots: &mut Slots) {
+        let mut stack = vec![Frame::Explore(pc)];
+        while let Some(frame) = stack.pop() {
+            let pc = match frame {
+                Frame::Restore(slot, old) => {
+                    slots[slot] = old;
+                    continue;
+                }
+                Frame::Explore(pc) => pc,
+            };
+            if !list.visit(pc) {
+                continue;
+            }
+            match &self.insts[pc] {
+                Inst::Jmp(to) => stack.push(Frame::Explore(*to)),
+                Inst::Split(first, second) => {
+                    stack.push(Frame::Explore(*second));
+                    stack.push(Frame::Explore(*first));
+                }
+                Inst::Save(slot) => {
+                    // 先压入恢复帧，这样展开完后续分支后槽位会还原
+                    stack.push(Frame::Restore(*slot, slots[*slot]));
+                    slots[*slot] = Some(at);
+                    stack.push(Frame::Explore(pc + 1));
+                }
+                Inst::Look(look) => {
+                    if look.holds(text, at) {
+                        stack.push(Frame::Explore(pc + 1));
+                    }
+                }
+                _ => list.list.push((pc, slots.clone())),
+            }
+        }
+    }
+
+    // * 从 pc 处的 Char 指令开始匹配字符 ch；忽略大小写时 ch 折叠后的每个字符依次匹配连续的 Char 指令
+    fn char_step(&self, pc: usize, ch: char) -> Option<usize> {
+        if !self.case_insensitive {
+            return matches!(self.insts[pc], Inst::Char(c) if c == ch).then_some(pc + 1);
+        }
+        let mut pc = pc;
+        for folded in fold_char(ch) {
+            if !matches!(self.insts.get(pc), Some(Inst::Char(c)) if *c == folded) {
+                return None;
+            }
+            pc += 1;
+        }
+        Some(pc)
+    }
+}
+
+/// `\w` 和 `\b` 使用的单词字符：Unicode 字母、数字和下划线
+pub fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+// * 字符类一次只匹配一个字符：折叠成多个字符（'ß' -> "ss"）时保持原字符
+fn simple_fold(c: char) -> char {
+    let mut folded = fold_char(c);
+    match (folded.next(), folded.next()) {
+        (Some(f), None) => f,
+        _ => c,
+    }
+}
+
+// 只做一对一的大小写映射，像 'ß' -> "SS" 这种一对多的情况保持原字符
+
+fn simple_upper(c: char) -> char {
+    let mut upper = c.to_uppercase();
+    match (upper.next(), upper.next()) {
+        (Some(u), None) => u,
+        _ => c,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(pattern: &str, text: &str) -> Option<(usize, usize)> {
+        Regex::new(pattern).unwrap().find(text)
+    }
+
+    #[test]
+    fn literal_and_dot() {
+        assert_eq!(Some((9, 13)), find("duct", "safe, productive."));
+        assert_eq!(Some((0, 3)), find("r.s", "rust"));
+        assert_eq!(None, find("a.b", "a\nb"));
+    }
+
+    #[test]
+    fn classes_and_escapes() {
+        assert_eq!(Some((4, 7)), find(r"\d+", "abc 123 def"));
+        assert_eq!(Some((0, 3)), find("[a-c]+", "abcd"));
+        assert_eq!(Some((3, 4)), find("[^a-c]", "abcd"));
+        assert_eq!(Some((1, 2)), find(r"[\].]", "a.b"));
+        assert_eq!(Some((3, 4)), find(r"\s", "foo bar"));
+    }
+
+    #[test]
+    fn anchors_and_boundaries() {
+        assert_eq!(Some((0, 4)), find("^Rust", "Rust: safe"));
+        assert_eq!(None, find("^safe", "Rust: safe"));
+        assert_eq!(Some((6, 10)), find("safe$", "Rust: safe"));
+        assert_eq!(Some((6, 10)), find(r"\bsafe\b", "Rust: safe"));
+        assert_eq!(None, find(r"\brust", "Trust me."));
+    }
+
+    #[test]
+    fn alternation_and_repetition() {
+        assert_eq!(Some((0, 4)), find("fast|safe", "safe, fast"));
+        assert_eq!(Some((0, 4)), find("(ab)+", "ababa"));
+        assert_eq!(Some((0, 2)), find("a{2}", "aaaa"));
+        assert_eq!(Some((0, 3)), find("a{1,3}", "aaaa"));
+        assert_eq!(Some((0, 4)), find("a{2,}", "aaaa"));
+        assert_eq!(Some((0, 1)), find("a+?", "aaaa"));
+        assert_eq!(Some((0, 5)), find("x{,1}", "x{,1}"));
+    }
+
+    #[test]
+    fn capture_groups() {
+        let regex = Regex::new(r"(\w+)@(\w+)(\.com)?").unwrap();
+        assert_eq!(
+            Some(vec![Some((4, 13)), Some((4, 8)), Some((9, 13)), None]),
+            regex.captures_at("at: user@host!", 0)
+        );
+        assert_eq!(None, regex.captures_at("user@", 0));
+    }
+
+    #[test]
+    fn case_insensitive_flag() {
+        assert_eq!(Some((0, 4)), find("(?i)rust", "Rust:"));
+        assert_eq!(Some((0, 5)), find("(?i)[r-u]+", "TRUST"));
+        assert_eq!(Some((1, 5)), find("(?i)[r-u]+", "ARUST"));
+        assert_eq!(None, find("rust", "Rust:"));
+        // 和普通的 -i 一样按完全折叠比较，匹配不能停在一个字符的中间
+        assert_eq!(Some((0, 7)), find("(?i)straße", "STRASSE"));
+        assert_eq!(Some((0, 5)), find("(?i)gro(ss|x)", "GROß"));
+        assert_eq!(Some((1, 4)), find("(?i)ἀι", "x\u{1f88}"));
+        assert_eq!(None, find("(?i)s", "ß"));
+    }
+
+    #[test]
+    fn parse_errors() {
+        let err = |pattern| Regex::new(pattern).unwrap_err();
+        assert_eq!("unclosed group", err("(ab").msg);
+        assert_eq!("unmatched ')'", err("ab)").msg);
+        assert_eq!("nothing to repeat", err("*a").msg);
+        assert_eq!("unclosed character class", err("[ab").msg);
+        assert_eq!("invalid range in character class", err("[z-a]").msg);
+        assert_eq!("invalid repetition range", err("a{3,1}").msg);
+        // 每一层都没有超过 MAX_REPEAT，但嵌套后展开的指令数相乘
+        assert_eq!("pattern too large", err("((a{1000}){1000}){1000}").msg);
+        assert_eq!("pattern too large", err("(a{1000}){1000}").msg);
+        assert!(Regex::new("(a{10}){1000}").is_ok());
+        assert_eq!(
+            ParseError {
+                pos: 2,
+                msg: "trailing backslash"
+            },
+            err(r"ab\")
+        );
+    }
+}
@@ -0,0 +1,326 @@
+///* 递归搜索目录
+// - walk 按文件名排序深度优先遍历目录，保证每次输出的顺序一致
+// - include / exclude 使用 glob 过滤：不含 '/' 的模式只匹配文件名，含 '/' 的模式匹配相对于根目录的路径
+// - 默认跳过符号链接；follow_symlinks 为 true 时跟随，并记录访问过的目录防止链接成环
+// - 默认遵守 .gitignore / .ignore（见 ignore 模块），no_ignore 为 true 时搜索所有文件
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::ignore::Ignore;
+
+// * 只检查文件开头这么多字节里有没有 NUL，和 grep 的做法一样
+const BINARY_CHECK_LEN: usize = 8192;
+
+/// 简单的 glob：`*` 不跨越 '/'，`**` 可以跨越 '/'，`?` 匹配单个字符，`[a-z]` / `[!a-z]` 为字符集合
+#[derive(Debug, Clone)]
+pub struct Glob {
+    pattern: Vec<char>,
+    has_slash: bool,
+}
+
+impl Glob {
+    pub fn new(pattern: &str) -> Glob {
+        Glob {
+            pattern: pattern.chars().collect(),
+            has_slash: pattern.contains('/'),
+        }
+    }
+
+    pub fn pattern(&self) -> String {
+        self.pattern.iter().collect()
+    }
+
+    /// `rel` 为相对于搜索根目录的路径
+    pub fn matches(&self, rel: &Path) -> bool {
+        if self.has_slash {
+            return self.matches_path(rel);
+        }
+        match rel.file_name() {
+            Some(name) => glob_match(
+                &self.pattern,
+                &name.to_string_lossy().chars().collect::<Vec<_>>(),
+            ),
+            None => false,
+        }
+    }
+
+    /// 不管模式里有没有 '/'，都和整个相对路径匹配
+    pub fn matches_path(&self, rel: &Path) -> bool {
+        // 统一用 '/' 作为分隔符
+        let text: Vec<char> = rel
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/")
+            .chars()
+            .collect();
+        glob_match(&self.pattern, &text)
+    }
+}
+
+fn glob_match(pattern: &[char], text: &[char]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some('*') if pattern.get(1) == Some(&'*') => {
+            // * "**/" 还可以匹配零层目录，例如 src/**/*.rs 匹配 src/main.rs
+            let rest = &pattern[2..];
+            if rest.first() == Some(&'/') && glob_match(&rest[1..], text) {
+                return true;
+            }
+            (0..=text.len()).any(|i| glob_match(rest, &text[i..]))
+        }
+        Some('*') => {
+            let rest = &pattern[1..];
+            for i in 0..=text.len() {
+                if glob_match(rest, &text[i..]) {
+                    return true;
+                }
+                if text.get(i) == Some(&'/') {
+                    break;
+                }
+            }
+            false
+        }
+        Some('?') => !text.is_empty() && text[0] != '/' && glob_match(&pattern[1..], &text[1..]),
+        Some('[') => match class_match(&pattern[1..], text.first().copied()) {
+            Some((true, len)) => glob_match(&pattern[1 + len..], &text[1..]),
+            Some((false, _)) => false,
+            // 没有闭合的 [ 当作普通字符
+            None => text.first() == Some(&'[') && glob_match(&pattern[1..], &text[1..]),
+        },
+        Some(&c) => text.first() == Some(&c) && glob_match(&pattern[1..], &text[1..]),
+    }
+}
+
+// * 返回 (是否命中, 字符集合占用的模式长度)，集合没有闭合时返回 None
+fn class_match(pattern: &[char], c: Option<char>) -> Option<(bool, usize)> {
+    let negated = matches!(pattern.first(), Some('!') | Some('^'));
+    let mut i = negated as usize;
+    let mut hit = false;
+    let mut first = true;
+    loop {
+        let lo = *pattern.get(i)?;
+        if lo == ']' && !first {
+            break;
+        }
+        first = false;
+        if pattern.get(i + 1) == Some(&'-') && pattern.get(i + 2).is_some_and(|&hi| hi != ']') {
+            let hi = pattern[i + 2];
+            hit |= c.is_some_and(|c| lo <= c && c <= hi);
+            i += 3;
+        } else {
+            hit |= c == Some(lo);
+            i += 1;
+        }
+    }
+    let hit = c.is_some() && hit != negated;
+    Some((hit, i + 1))
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct WalkOptions {
+    pub include: Vec<Glob>,
+    pub exclude: Vec<Glob>,
+    pub follow_symlinks: bool,
+    // * --no-ignore
+    pub no_ignore: bool,
+}
+
+/// 递归收集 `root` 下所有需要搜索的文件
+///
+/// 根目录本身打不开时返回错误；遍历中途遇到的错误（如没有权限）打印到标准错误后继续
+pub fn walk(root: &Path, options: &WalkOptions) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut visited = HashSet::new();
+    if options.follow_symlinks {
+        visited.insert(fs::canonicalize(root)?);
+    }
+    // 先读一次根目录，让根目录的错误直接返回给调用者
+    let entries = read_dir_sorted(root)?;
+    let mut ignore = if options.no_ignore {
+        Ignore::disabled()
+    } else {
+        Ignore::for_root(root)
+    };
+    ignore.enter(root);
+    walk_entries(
+        root,
+        entries,
+        options,
+        &mut ignore,
+        &mut visited,
+        &mut files,
+    );
+    Ok(files)
+}
+
+fn walk_entries(
+    root: &Path,
+    entries: Vec<PathBuf>,
+    options: &WalkOptions,
+    ignore: &mut Ignore,
+    visited: &mut HashSet<PathBuf>,
+    files: &mut Vec<PathBuf>,
+) {
+    for path in entries {
+        let rel = path.strip_prefix(root).unwrap_or(&path);
+        if options.exclude.iter().any(|glob| glob.matches(rel)) {
+            continue;
+        }
+
+        // * symlink_metadata 不会跟随链接，可以判断出条目本身是不是符号链接
+        let meta = match fs::symlink_metadata(&path) {
+            Ok(meta) => meta,
+            Err(e) => {
+                eprintln!("minigrep: {}: {}", path.display(), e);
+                continue;
+            }
+        };
+        let meta = if meta.file_type().is_symlink() {
+            if !options.follow_symlinks {
+                continue;
+            }
+            match fs::metadata(&path) {
+                Ok(meta) => meta,
+                Err(e) => {
+                    eprintln!("minigrep: {}: {}", path.display(), e);
+                    continue;
+                }
+            }
+        } else {
+            meta
+        };
+
+        if ignore.is_ignored(&path, meta.is_dir()) {
+            continue;
+        }
+
+        if meta.is_dir() {
+            if options.follow_symlinks {
+                // 同一个真实目录只进入一次，避免链接成环时无限递归
+                match fs::canonicalize(&path) {
+                    Ok(real) => {
+                        if !visited.insert(real) {
+                            continue;
+                        }
+                    }
+                    Err(e) => {
+                        eprintln!("minigrep: {}: {}", path.display(), e);
+                        continue;
+                    }
+                }
+            }
+            match read_dir_sorted(&path) {
+                Ok(entries) => {
+                    // * 子目录中的忽略文件只对这个目录生效
+                    let mark = ignore.enter(&path);
+                    walk_entries(root, entries, options, ignore, visited, files);
+                    ignore.leave(mark);
+                }
+                Err(e) => eprintln!("minigrep: {}: {}", path.display(), e),
+            }
+        } else if meta.is_file()
+            && (options.include.is_empty() || options.include.iter().any(|glob| glob.matches(rel)))
+        {
+            files.push(path);
+        }
+    }
+}
+
+fn read_dir_sorted(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut entries = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    entries.sort();
+    Ok(entries)
+}
+
+/// 开头含有 NUL 字节的文件当作二进制文件
+pub fn is_binary(contents: &[u8]) -> bool {
+    contents[..contents.len().min(BINARY_CHECK_LEN)].contains(&0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_patterns() {
+        let matches = |pattern, path| Glob::new(pattern).matches(Path::new(path));
+        assert!(matches("*.rs", "src/main.rs"));
+        assert!(!matches("*.rs", "src/main.rs.bak"));
+        assert!(matches("src/*.rs", "src/main.rs"));
+        assert!(!matches("src/*.rs", "src/bin/main.rs"));
+        assert!(matches("src/**/*.rs", "src/main.rs"));
+        assert!(matches("src/**/*.rs", "src/bin/main.rs"));
+        assert!(matches("?ain.[rt][!a-z]", "main.r1"));
+        assert!(!matches("?ain.[rt][!a-z]", "main.rs"));
+        assert!(matches("target", "a/target"));
+    }
+
+    #[test]
+    fn walk_sorted_and_filtered() {
+        let root = std::env::temp_dir().join(format!("minigrep_walk_{}", std::process::id()));
+        fs::create_dir_all(root.join("b/target")).unwrap();
+        fs::create_dir_all(root.join("a")).unwrap();
+        fs::write(root.join("b/two.txt"), "two").unwrap();
+        fs::write(root.join("b/target/skip.txt"), "skip").unwrap();
+        fs::write(root.join("a/one.txt"), "one").unwrap();
+        fs::write(root.join("a/one.log"), "one").unwrap();
+
+        let options = WalkOptions {
+            include: vec![Glob::new("*.txt")],
+            exclude: vec![Glob::new("target")],
+            follow_symlinks: false,
+            no_ignore: true,
+        };
+        let files = walk(&root, &options).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(vec![root.join("a/one.txt"), root.join("b/two.txt")], files);
+    }
+
+    #[test]
+    fn walk_respects_ignore_files() {
+        let root = std::env::temp_dir().join(format!("minigrep_ignore_{}", std::process::id()));
+        fs::create_dir_all(root.join("target/debug")).unwrap();
+        fs::create_dir_all(root.join("src/gen")).unwrap();
+        fs::write(root.join(".gitignore"), "target/\n*.log\n").unwrap();
+        fs::write(root.join("src/.ignore"), "gen/\n!keep.log\n").unwrap();
+        fs::write(root.join("target/debug/out.txt"), "").unwrap();
+        fs::write(root.join("src/gen/code.rs"), "").unwrap();
+        fs::write(root.join("src/main.rs"), "").unwrap();
+        fs::write(root.join("src/keep.log"), "").unwrap();
+        fs::write(root.join("src/drop.log"), "").unwrap();
+
+        let files = walk(&root, &WalkOptions::default()).unwrap();
+        let all = walk(
+            &root,
+            &WalkOptions {
+                no_ignore: true,
+                ..WalkOptions::default()
+            },
+        )
+        .unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(
+            vec![
+                root.join(".gitignore"),
+                root.join("src/.ignore"),
+                root.join("src/keep.log"),
+                root.join("src/main.rs")
+            ],
+            files
+        );
+        assert_eq!(7, all.len());
+    }
+
+    #[test]
+    fn binary_detection() {
+        assert!(is_binary(b"ELF\0\x01"));
+        assert!(!is_binary("Rust:\nsafe, fast, productive.".as_bytes()));
+    }
+}
@@ -1,170 +1,176 @@
 ///* 为 ThreadPool 实现 Drop trait 对线程池中的每一个线程调用 join，这样这些线程将会执行完他们的请求。
-/// 接着会为 ThreadPool 实现一个告诉线程他们应该停止接收新请求并结束的方式
-///
+// 接着会为 ThreadPool 实现一个告诉线程他们应该停止接收新请求并结束的方式
+// * 这里不再用 take(2)：收到 SIGINT / SIGTERM 后停止 accept，等正在处理的请求完成（最多 SHUTDOWN_TIMEOUT 秒），
+//   再 drop ThreadPool 等待所有 worker 退出；超过 deadline 时 handler 还在运行，join 会一直等它，所以直接退出
+//
 // ---------------------------------------------------------------------
-use std::sync::mpsc;
-use std::sync::Arc;
-use std::sync::Mutex;
-use std::thread;
-
-pub struct ThreadPool {
-    workers: Vec<Worker>,
-    sender: mpsc::Sender<Message>,
-}
-
-type Job = Box<dyn FnOnce() + Send + 'static>;
-
-// * 定义消息类型，job or terminate
-enum Message {
-    NewJob(Job),
-    Terminate,
-}
-
-impl ThreadPool {
-    /// Create a new ThreadPool.
-    ///
-    /// The size is the number of threads in the pool.
-    ///
-    /// # Panics
-    ///
-    /// The `new` function will panic if the size is zero.
-    pub fn new(size: usize) -> ThreadPool {
-        assert!(size > 0);
-
-        let (sender, receiver) = mpsc::channel();
-
-        let receiver = Arc::new(Mutex::new(receiver));
-
-        let mut workers = Vec::with_capacity(size);
-
-        for id in 0..size {
-            workers.push(Worker::new(id, Arc::clone(&receiver)));
-        }
-
-        ThreadPool { workers, sender }
-    }
+// * ThreadPool 的实现在 lib.rs 中（书中的 hello crate）
+#[path = "lib.rs"]
+mod hello;
 
-    pub fn execute<F>(&self, f: F)
-    where
-        F: FnOnce() + Send + 'static,
-    {
-        let job = Box::new(f);
-
-        self.sender.send(Message::NewJob(job)).unwrap();
-    }
-}
+// * 增量的 HTTP/1.1 请求解析，取代固定 1024 字节的缓冲区
+#[path = "4http.rs"]
+mod http;
 
-//* 为 ThreadPool 实现 Drop Trait，销毁时，清理线程池
-// 先发停止消息，然后等待线程退出
-impl Drop for ThreadPool {
-    fn drop(&mut self) {
-        println!("Sending terminate message to all workers.");
+// * 按 method 和路径模式分发请求，取代写死的 if / else
+#[path = "5router.rs"]
+mod router;
 
-        //* 向线程发送信号使其停止接收任务
-        for _ in &self.workers {
-            // 发送数量相等的消息（每个 worker 持有的接收端一样）
-            self.sender.send(Message::Terminate).unwrap();
-        }
+// * 持久连接：一个连接上依次处理多个请求（keep-alive、pipelining、空闲超时）
+#[path = "6connection.rs"]
+mod connection;
 
-        println!("Shutting down all workers.");
+// * 静态文件：MIME 类型、路径安全检查、ETag / Last-Modified 缓存和 Range 请求
+#[path = "7static_files.rs"]
+mod static_files;
 
-        for worker in &mut self.workers {
-            println!("Shutting down worker {}", worker.id);
-            // * 并不能直接调用 join，因为只有每一个 worker 的可变借用，而 join 会获取其参数的所有权
-            // 为了解决这个问题，需要一个方法将 thread 移动出拥有其所有权的 Worker 实例以便 join 可以消费这个线程
-            // 将 thread 设计成 Option，通过 take 转义所有权
-            if let Some(thread) = worker.thread.take() {
-                thread.join().unwrap();
-            }
-        }
-    }
-}
-
-struct Worker {
-    id: usize,
-    //* Worker 存放的是 Option<thread::JoinHandle<()>，就可以在 Option 上调用 take 方法将值从 Some 成员中移动出来而对 None 成员不做处理
-    //* 换句话说，正在运行的 Worker 的 thread 将是 Some 成员值，而当需要清理 worker 时，将 Some 替换为 None，这样 worker 就没有可以运行的线程了
-    thread: Option<thread::JoinHandle<()>>,
-}
-
-impl Worker {
-    fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Message>>>) -> Worker {
-        let thread = thread::spawn(move || loop {
-            let message = receiver.lock().unwrap().recv().unwrap();
-
-            match message {
-                Message::NewJob(job) => {
-                    println!("Worker {} got a job; executing.", id);
-
-                    job();
-                }
-                Message::Terminate => {
-                    println!("Worker {} was told to terminate.", id);
-
-                    break;
-                }
-            }
-        });
-
-        Worker {
-            id,
-            thread: Some(thread),
-        }
-    }
-}
+// * 信号处理和连接登记，用于优雅关闭
+#[path = "8shutdown.rs"]
+mod shutdown;
 
 // ---------------------------------------------------------------------
 
+use connection::Options;
 use hello::ThreadPool;
+use http::Response;
+use router::Router;
+use shutdown::Connections;
+use static_files::StaticFiles;
+use std::env;
 use std::fs;
-use std::io::prelude::*;
+use std::io;
 use std::net::TcpListener;
 use std::net::TcpStream;
+use std::process;
+use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
 
+// 没有新连接时，隔多久再检查一次是否收到了关闭信号
+const ACCEPT_POLL: Duration = Duration::from_millis(50);
+
 fn main() {
     let listener = TcpListener::bind("127.0.0.1:7878").unwrap();
+    // * 阻塞的 accept 不会因为信号返回，所以改成非阻塞，轮询 shutdown::requested()
+    listener.set_nonblocking(true).unwrap();
+    if let Err(e) = shutdown::install_handlers() {
+        eprintln!("failed to install signal handlers: {}", e);
+    }
     let pool = ThreadPool::new(4);
+    // /static/ 下的文件来自第一个命令行参数指定的目录，默认是当前目录下的 static
+    let root = env::args().nth(1).unwrap_or_else(|| String::from("static"));
+    // * 每个连接交给线程池时带上一份 Arc，所有 worker 共用同一个 Router
+    let router = Arc::new(routes(&root));
+    let connections = Arc::new(Connections::default());
+
+    while !shutdown::requested() {
+        let stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                if e.kind() != io::ErrorKind::WouldBlock {
+                    eprintln!("failed to accept connection: {}", e);
+                }
+                thread::sleep(ACCEPT_POLL);
+                continue;
+            }
+        };
+        let router = Arc::clone(&router);
+        let connections = Arc::clone(&connections);
+        connections.accepted();
 
-    for stream in listener.incoming() {
-        let stream = stream.unwrap();
-
-        pool.execute(|| {
-            handle_connection(stream);
+        pool.execute(move || {
+            handle_connection(stream, &router, &connections);
         });
     }
 
+    // 关闭 listener，之后的连接会被拒绝
+    drop(listener);
     println!("Shutting down.");
+    let finished = connections.close_all(shutdown_timeout());
+    let report = connections.report();
+    println!(
+        "Drained {} request(s), aborted {}.",
+        report.drained, report.aborted
+    );
+    if !finished {
+        // * 被中止的请求的 handler 还没有返回，deadline 要限制的是进程退出的时间，不能再 join 它们
+        println!("Exiting without waiting for the busy workers.");
+        process::exit(0);
+    }
+    // * ThreadPool 的 Drop 发送 Terminate 并 join 所有 worker
+    drop(pool);
 }
 
-fn handle_connection(mut stream: TcpStream) {
-    let mut buffer = [0; 1024];
-    stream.read(&mut buffer).unwrap();
-
-    let get = b"GET / HTTP/1.1\r\n";
-    let sleep = b"GET /sleep HTTP/1.1\r\n";
-
-    let (status_line, filename) = if buffer.starts_with(get) {
-        ("HTTP/1.1 200 OK", "hello.html")
-    } else if buffer.starts_with(sleep) {
-        thread::sleep(Duration::from_secs(5));
-        ("HTTP/1.1 200 OK", "hello.html")
-    } else {
-        ("HTTP/1.1 404 NOT FOUND", "404.html")
-    };
+// 等待正在处理的请求的最长时间，环境变量 SHUTDOWN_TIMEOUT（秒），默认 10 秒
+fn shutdown_timeout() -> Duration {
+    env::var("SHUTDOWN_TIMEOUT")
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+        .map_or(Duration::from_secs(10), Duration::from_secs)
+}
 
-    let contents = fs::read_to_string(filename).unwrap();
+fn routes(root: &str) -> Router {
+    let files = StaticFiles::new(root).listing(true);
+    Router::new()
+        .get("/", |_, _| html_file(200, "hello.html"))
+        .get("/sleep", |_, _| {
+            thread::sleep(Duration::from_secs(5));
+            html_file(200, "hello.html")
+        })
+        .get("/hello/:name", |_, params| {
+            let name = params.get("name").unwrap_or_default();
+            Response::new(200)
+                .header("Content-Type", "text/plain; charset=utf-8")
+                .body(format!("Hello, {}!\n", name))
+        })
+        // 原样返回请求体，可以用来检查 Content-Length 和 chunked 请求体的解析
+        .post("/echo", |request, _| {
+            Response::new(200)
+                .header("Content-Type", "application/octet-stream")
+                .body(request.body.clone())
+        })
+        .get("/static/*path", move |request, params| {
+            files.serve(request, params.get("path").unwrap_or_default())
+        })
+        .not_found(|_, _| html_file(404, "404.html"))
+}
 
-    let response = format!(
-        "{}\r\nContent-Length: {}\r\n\r\n{}",
-        status_line,
-        contents.len(),
-        contents
-    );
+fn html_file(status: u16, filename: &str) -> Response {
+    match fs::read_to_string(filename) {
+        Ok(contents) => Response::new(status)
+            .header("Content-Type", "text/html; charset=utf-8")
+            .body(contents),
+        Err(e) => {
+            eprintln!("failed to read {}: {}", filename, e);
+            Response::new(500)
+        }
+    }
+}
 
-    stream.write(response.as_bytes()).unwrap();
-    stream.flush().unwrap();
+fn handle_connection(mut stream: TcpStream, router: &Router, connections: &Arc<Connections>) {
+    let options = Options::default();
+    // * 先登记，排队计数才会减一
+    let connection = match connections.open(&stream) {
+        Ok(connection) => connection,
+        // 排队时服务器开始关闭了：和关闭时收到的其他请求一样回复 503，而不是直接断开
+        Err(e) => {
+            eprintln!("dropping connection: {}", e);
+            let _ = stream
+                .set_nonblocking(false)
+                .and_then(|_| connection::shutting_down().write_to(&mut stream));
+            return;
+        }
+    };
+    // * 读超时是轮询间隔：serve 每次超时检查是否空闲太久，或者有别的连接在等 worker
+    // 有的平台上 accept 得到的连接会继承 listener 的非阻塞模式
+    if let Err(e) = stream
+        .set_nonblocking(false)
+        .and_then(|_| stream.set_read_timeout(Some(options.poll_interval)))
+    {
+        eprintln!("failed to configure connection: {}", e);
+        return;
+    }
+    connection::serve(&mut stream, router, &options, Some(&connection));
 }
 
 /*
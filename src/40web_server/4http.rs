@@ -0,0 +1,841 @@
+///* HTTP/1.1 请求解析
+// 书中的 handle_connection 只读一次 1024 字节，再用 starts_with 判断请求行；
+// 请求超过 1 KB 或者被拆成几次 TCP 读取时就会出错。这里换成一个增量解析器：
+// - Parser 保存已经收到的字节，每收到一段就 feed 进去，再调用 parse，请求完整时返回 Some(Request)
+// - 请求之后多出来的字节留在 Parser 里，是下一个请求的开头（同一个连接上的多个请求）
+// - 请求行：method、target（拆成 path 和 query）、version；只接受 HTTP/1.0 和 HTTP/1.1
+// - 头部名字不区分大小写；以空格或制表符开头的行是上一个头部的续行（obs-fold），合并成一个空格
+//   同名的头部按出现顺序用 ", " 合并
+// - 请求体：Content-Length 或者 Transfer-Encoding: chunked（chunk 之后的 trailer 并入头部）
+//   chunked 必须是最后一个编码，不是时返回 400；其他编码（gzip 等）不支持，返回 501
+// - 头部解析完之后记下进度，之后每次 parse 只看新收到的字节，大的请求体不会被反复扫描
+// - 出错时返回 HttpError，带上要回复的状态码：格式错误 400，头部太大 431，请求体太大 413
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::sync::Arc;
+
+/// 各部分的大小上限，超过时返回 431 或 413
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    // * 请求行加所有头部的字节数
+    pub max_head: usize,
+    pub max_headers: usize,
+    pub max_body: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            max_head: 8 * 1024,
+            max_headers: 100,
+            max_body: 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Version {
+    Http10,
+    Http11,
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Version::Http10 => write!(f, "HTTP/1.0"),
+            Version::Http11 => write!(f, "HTTP/1.1"),
+        }
+    }
+}
+
+/// 按出现顺序保存的头部，查找时不区分大小写
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Headers {
+    entries: Vec<(String, String)>,
+}
+
+impl Headers {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// 同名的头部合并成一个值，名字保留第一次出现时的写法
+    pub fn insert(&mut self, name: &str, value: &str) {
+        match self
+            .entries
+            .iter_mut()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        {
+            Some((_, existing)) => {
+                existing.push_str(", ");
+                existing.push_str(value);
+            }
+            None => self.entries.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    /// 逗号分隔的值（比如 Connection、Transfer-Encoding）中是否含有 `token`
+    pub fn has_token(&self, name: &str, token: &str) -> bool {
+        self.get(name).is_some_and(|value| {
+            value
+                .split(',')
+                .any(|item| item.trim().eq_ignore_ascii_case(token))
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+    pub method: String,
+    // * path 和 query 保持原样，没有做百分号解码
+    pub path: String,
+    pub query: Option<String>,
+    pub version: Version,
+    pub headers: Headers,
+    pub body: Vec<u8>,
+}
+
+/// 解析失败：status 为应该回复的状态码
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpError {
+    pub status: u16,
+    pub reason: &'static str,
+}
+
+impl HttpError {
+    fn bad_request(reason: &'static str) -> HttpError {
+        HttpError {
+            status: 400,
+            reason,
+        }
+    }
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {}: {}",
+            self.status,
+            status_text(self.status),
+            self.reason
+        )
+    }
+}
+
+impl std::error::Error for HttpError {}
+
+/// 增量解析器：feed 收到的字节，parse 取出完整的请求
+#[derive(Debug, Default)]
+pub struct Parser {
+    buffer: Vec<u8>,
+    limits: Limits,
+    // 已经找过 "\r\n\r\n" 的字节数
+    scanned: usize,
+    // * 头部已经解析完、还在等请求体的请求
+    pending: Option<Pending>,
+}
+
+#[derive(Debug)]
+struct Pending {
+    request: Request,
+    // 请求体在 buffer 中的起点
+    body_start: usize,
+    body: Body,
+}
+
+#[derive(Debug)]
+enum Body {
+    Length(usize),
+    // 已经解析完的 chunk 之后的位置（相对于 body_start），chunk 的数据已经放进 request.body
+    Chunked(usize),
+}
+
+impl Parser {
+    pub fn new(limits: Limits) -> Parser {
+        Parser {
+            buffer: Vec::new(),
+            limits,
+            scanned: 0,
+            pending: None,
+        }
+    }
+
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// 缓冲区里是否还有没解析的字节
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    /// 缓冲区里有完整的请求时取出它；还需要更多字节时返回 Ok(None)
+    ///
+    /// 出错之后缓冲区的内容就不可信了，调用者应该回复错误并关闭连接
+    pub fn parse(&mut self) -> Result<Option<Request>, HttpError> {
+        if self.pending.is_none() {
+            match self.parse_head()? {
+                Some(pending) => self.pending = Some(pending),
+                None => return Ok(None),
+            }
+        }
+        let pending = self.pending.as_mut().unwrap();
+        let data = &self.buffer[pending.body_start..];
+        let len = match &mut pending.body {
+            Body::Length(length) => {
+                if data.len() < *length {
+                    return Ok(None);
+                }
+                pending.request.body = data[..*length].to_vec();
+                *length
+            }
+            Body::Chunked(next) => {
+                match parse_chunked(data, next, &mut pending.request.body, &self.limits)? {
+                    Some((trailers, len)) => {
+                        for (name, value) in trailers.iter() {
+                            pending.request.headers.insert(name, value);
+                        }
+                        len
+                    }
+                    None => return Ok(None),
+                }
+            }
+        };
+
+        let consumed = pending.body_start + len;
+        let request = self.pending.take().unwrap().request;
+        self.buffer.drain(..consumed);
+        self.scanned = 0;
+        Ok(Some(request))
+    }
+
+    // 找到并解析请求行和头部，确定请求体怎么读；头部还不完整时返回 Ok(None)
+    fn parse_head(&mut self) -> Result<Option<Pending>, HttpError> {
+        // "\r\n\r\n" 可能跨过上次找到的末尾，往回退 3 个字节
+        let from = self.scanned.saturating_sub(3);
+        let head_end = match find(&self.buffer[from..], b"\r\n\r\n") {
+            Some(i) => from + i,
+            None if self.buffer.len() > self.limits.max_head => {
+                return Err(HttpError {
+                    status: 431,
+                    reason: "request head too large",
+                })
+            }
+            None => {
+                self.scanned = self.buffer.len();
+                return Ok(None);
+            }
+        };
+        if head_end > self.limits.max_head {
+            return Err(HttpError {
+                status: 431,
+                reason: "request head too large",
+            });
+        }
+
+        let head = std::str::from_utf8(&self.buffer[..head_end])
+            .map_err(|_| HttpError::bad_request("request head is not valid UTF-8"))?;
+        let request = parse_head(head, &self.limits)?;
+
+        let body = if let Some(codings) = request.headers.get("transfer-encoding") {
+            // * 同时给出两者时无法确定请求在哪里结束，按 RFC 9112 拒绝，避免请求走私
+            if request.headers.get("content-length").is_some() {
+                return Err(HttpError::bad_request(
+                    "both Content-Length and Transfer-Encoding",
+                ));
+            }
+            transfer_coding(codings)?;
+            Body::Chunked(0)
+        } else {
+            let length = match request.headers.get("content-length") {
+                Some(value) => content_length(value)?,
+                None => 0,
+            };
+            if length > self.limits.max_body {
+                return Err(HttpError {
+                    status: 413,
+                    reason: "request body too large",
+                });
+            }
+            Body::Length(length)
+        };
+        Ok(Some(Pending {
+            request,
+            body_start: head_end + 4,
+            body,
+        }))
+    }
+}
+
+/// 读到 EOF 时：缓冲区为空说明客户端正常关闭了连接，否则请求不完整
+#[derive(Debug)]
+pub enum ReadError {
+    Io(io::Error),
+    Http(HttpError),
+}
+
+impl From<io::Error> for ReadError {
+    fn from(e: io::Error) -> ReadError {
+        ReadError::Io(e)
+    }
+}
+
+impl From<HttpError> for ReadError {
+    fn from(e: HttpError) -> ReadError {
+        ReadError::Http(e)
+    }
+}
+
+/// 从 `stream` 读取下一个完整的请求；连接在两个请求之间被关闭时返回 Ok(None)
+pub fn read_request<R: Read>(
+    stream: &mut R,
+    parser: &mut Parser,
+) -> Result<Option<Request>, ReadError> {
+    let mut buf = [0; 4096];
+    loop {
+        if let Some(request) = parser.parse()? {
+            return Ok(Some(request));
+        }
+        let n = stream.read(&mut buf)?;
+        if n == 0 {
+            if parser.is_empty() {
+                return Ok(None);
+            }
+            return Err(HttpError::bad_request("incomplete request").into());
+        }
+        parser.feed(&buf[..n]);
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+// RFC 9110 的 token 字符，用于 method 和头部名字
+fn is_token(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+fn parse_head(head: &str, limits: &Limits) -> Result<Request, HttpError> {
+    let mut lines = head.split("\r\n");
+    let request_line = lines.next().unwrap_or_default();
+    let mut parts = request_line.split(' ');
+    let (method, target, version) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(target), Some(version), None) => (method, target, version),
+        _ => return Err(HttpError::bad_request("malformed request line")),
+    };
+    if !is_token(method) {
+        return Err(HttpError::bad_request("invalid method"));
+    }
+    if !target.starts_with('/') && target != "*" {
+        return Err(HttpError::bad_request("invalid request target"));
+    }
+    if target.bytes().any(|b| b <= b' ' || b == 0x7f) {
+        return Err(HttpError::bad_request("invalid request target"));
+    }
+    let version = match version {
+        "HTTP/1.1" => Version::Http11,
+        "HTTP/1.0" => Version::Http10,
+        v if v.starts_with("HTTP/") => {
+            return Err(HttpError {
+                status: 505,
+                reason: "unsupported HTTP version",
+            })
+        }
+        _ => return Err(HttpError::bad_request("malformed request line")),
+    };
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path, Some(query.to_string())),
+        None => (target, None),
+    };
+
+    let headers = parse_headers(lines, limits)?;
+    // * HTTP/1.1 的请求必须带 Host
+    if version == Version::Http11 && headers.get("host").is_none() {
+        return Err(HttpError::bad_request("missing Host header"));
+    }
+    Ok(Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        query,
+        version,
+        headers,
+        body: Vec::new(),
+    })
+}
+
+fn parse_headers<'a>(
+    lines: impl Iterator<Item = &'a str>,
+    limits: &Limits,
+) -> Result<Headers, HttpError> {
+    // 先处理续行，再合并同名头部
+    let mut fields: Vec<(&str, String)> = Vec::new();
+    for line in lines {
+        if line.starts_with([' ', '\t']) {
+            match fields.last_mut() {
+                Some((_, value)) => {
+                    value.push(' ');
+                    value.push_str(line.trim());
+                }
+                None => return Err(HttpError::bad_request("continuation line without a header")),
+            }
+            continue;
+        }
+        let (name, value) = line
+            .split_once(':')
+            .ok_or(HttpError::bad_request("malformed header"))?;
+        // 名字和冒号之间不能有空白
+        if !is_token(name) {
+            return Err(HttpError::bad_request("invalid header name"));
+        }
+        fields.push((name, value.trim().to_string()));
+        if fields.len() > limits.max_headers {
+            return Err(HttpError {
+                status: 431,
+                reason: "too many headers",
+            });
+        }
+    }
+
+    let mut headers = Headers::default();
+    for (name, value) in fields {
+        if value.bytes().any(|b| (b < b' ' && b != b'\t') || b == 0x7f) {
+            return Err(HttpError::bad_request("invalid header value"));
+        }
+        headers.insert(name, &value);
+    }
+    Ok(headers)
+}
+
+// * 重复的 Content-Length 合并后是 "5, 5"：值都相同时可以接受，不同则无法确定长度
+fn content_length(value: &str) -> Result<usize, HttpError> {
+    let mut length = None;
+    for item in value.split(',') {
+        let item = item.trim();
+        if item.is_empty() || !item.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(HttpError::bad_request("invalid Content-Length"));
+        }
+        let n: usize = item
+            .parse()
+            .map_err(|_| HttpError::bad_request("invalid Content-Length"))?;
+        if length.is_some_and(|length| length != n) {
+            return Err(HttpError::bad_request("conflicting Content-Length"));
+        }
+        length = Some(n);
+    }
+    length.ok_or(HttpError::bad_request("invalid Content-Length"))
+}
+
+// * Transfer-Encoding 按顺序列出各个编码，最后一个才决定请求体在哪里结束：
+//   只支持单独的 chunked；chunked 不在最后时无法确定长度，是格式错误
+fn transfer_coding(value: &str) -> Result<(), HttpError> {
+    let codings: Vec<&str> = value.split(',').map(str::trim).collect();
+    let chunked = |coding: &&str| coding.eq_ignore_ascii_case("chunked");
+    match codings.split_last() {
+        Some((last, rest)) if chunked(last) => {
+            if rest.iter().any(chunked) {
+                Err(HttpError::bad_request("chunked applied more than once"))
+            } else if !rest.is_empty() {
+                Err(HttpError {
+                    status: 501,
+                    reason: "unsupported transfer encoding",
+                })
+            } else {
+                Ok(())
+            }
+        }
+        _ if codings.iter().any(chunked) => Err(HttpError::bad_request(
+            "chunked is not the final transfer encoding",
+        )),
+        _ => Err(HttpError {
+            status: 501,
+            reason: "unsupported transfer encoding",
+        }),
+    }
+}
+
+// 从 *next 开始继续解析 chunked 请求体，完整的 chunk 追加到 body 并推进 *next；
+// 数据还不完整时返回 None，完整时返回 (trailer, 用掉的字节数)
+fn parse_chunked(
+    data: &[u8],
+    next: &mut usize,
+    body: &mut Vec<u8>,
+    limits: &Limits,
+) -> Result<Option<(Headers, usize)>, HttpError> {
+    loop {
+        let mut pos = *next;
+        let line_end = match find(&data[pos..], b"\r\n") {
+            Some(i) => pos + i,
+            None if data.len() - pos > 1024 => {
+                return Err(HttpError::bad_request("chunk size line too long"))
+            }
+            None => return Ok(None),
+        };
+        let line = std::str::from_utf8(&data[pos..line_end])
+            .map_err(|_| HttpError::bad_request("invalid chunk size"))?;
+        // chunk 扩展（;name=value）忽略
+        let size = line.split(';').next().unwrap_or_default().trim();
+        if size.is_empty() || !size.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(HttpError::bad_request("invalid chunk size"));
+        }
+        let size = usize::from_str_radix(size, 16)
+            .map_err(|_| HttpError::bad_request("invalid chunk size"))?;
+        pos = line_end + 2;
+
+        if size == 0 {
+            // * 最后一个 chunk 之后是 trailer，以空行结束
+            if data[pos..].starts_with(b"\r\n") {
+                return Ok(Some((Headers::default(), pos + 2)));
+            }
+            let end = match find(&data[pos..], b"\r\n\r\n") {
+                Some(i) => pos + i,
+                None if data.len() - pos > limits.max_head => {
+                    return Err(HttpError {
+                        status: 431,
+                        reason: "trailers too large",
+                    })
+                }
+                None => return Ok(None),
+            };
+            let trailers = std::str::from_utf8(&data[pos..end])
+                .map_err(|_| HttpError::bad_request("trailers are not valid UTF-8"))?;
+            let trailers = parse_headers(trailers.split("\r\n"), limits)?;
+            return Ok(Some((trailers, end + 4)));
+        }
+
+        // * size 来自客户端，可能接近 usize::MAX：先和剩余的额度比较，再做加法
+        if size > limits.max_body - body.len() {
+            return Err(HttpError {
+                status: 413,
+                reason: "request body too large",
+            });
+        }
+        let end = pos
+            .checked_add(size)
+            .ok_or(HttpError::bad_request("invalid chunk size"))?;
+        if data.len() < end + 2 {
+            return Ok(None);
+        }
+        if &data[end..end + 2] != b"\r\n" {
+            return Err(HttpError::bad_request("chunk not followed by CRLF"));
+        }
+        body.extend_from_slice(&data[pos..end]);
+        *next = end + 2;
+    }
+}
+
+/// 百分号解码（path 中的一段或者 query 中的值）；编码不合法或者结果不是 UTF-8 时返回 None
+pub fn percent_decode(s: &str) -> Option<String> {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return None;
+            }
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+pub fn status_text(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        206 => "Partial Content",
+        301 => "Moved Permanently",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        413 => "Content Too Large",
+        416 => "Range Not Satisfiable",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        503 => "Service Unavailable",
+        505 => "HTTP Version Not Supported",
+        _ => "Unknown",
+    }
+}
+
+/// 写出时才从文件读取的响应体，大文件不用整个放进内存，HEAD 请求也不会去读它
+#[derive(Debug, Clone)]
+pub struct FileBody {
+    file: Arc<File>,
+    start: u64,
+    len: u64,
+}
+
+impl PartialEq for FileBody {
+    fn eq(&self, other: &FileBody) -> bool {
+        Arc::ptr_eq(&self.file, &other.file) && self.start == other.start && self.len == other.len
+    }
+}
+
+impl FileBody {
+    fn copy_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let mut file = &*self.file;
+        file.seek(SeekFrom::Start(self.start))?;
+        let copied = io::copy(&mut file.take(self.len), out)?;
+        // * Content-Length 已经写出去了，文件在这期间变短时只能报错，让调用者关闭连接
+        if copied < self.len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "file shrank while it was being sent",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// 一个响应；Content-Length 在写出时根据 body（或者 file）自动加上
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Headers,
+    pub body: Vec<u8>,
+    // * 有值时响应体是文件中的一段，body 不用
+    pub file: Option<FileBody>,
+}
+
+impl Response {
+    pub fn new(status: u16) -> Response {
+        Response {
+            status,
+            headers: Headers::default(),
+            body: Vec::new(),
+            file: None,
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Response {
+        self.headers.insert(name, value);
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Response {
+        self.body = body.into();
+        self.file = None;
+        self
+    }
+
+    /// 响应体是 `file` 中从 `start` 开始的 `len` 个字节，写出时再读取
+    pub fn file(mut self, file: File, start: u64, len: u64) -> Response {
+        self.body.clear();
+        self.file = Some(FileBody {
+            file: Arc::new(file),
+            start,
+            len,
+        });
+        self
+    }
+
+    fn content_length(&self) -> u64 {
+        self.file
+            .as_ref()
+            .map_or(self.body.len() as u64, |file| file.len)
+    }
+
+    /// 解析错误对应的响应，回复之后连接会被关闭
+    pub fn error(error: &HttpError) -> Response {
+        Response::new(error.status)
+            .header("Content-Type", "text/plain; charset=utf-8")
+            .header("Connection", "close")
+            .body(format!("{}\n", error))
+    }
+
+    pub fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        self.write_head(out)?;
+        match &self.file {
+            Some(file) => file.copy_to(out)?,
+            None => out.write_all(&self.body)?,
+        }
+        out.flush()
+    }
+
+    /// 只写出状态行和头部（HEAD 请求），Content-Length 仍然是 body 的长度
+    pub fn write_head<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let mut head = format!("HTTP/1.1 {} {}\r\n", self.status, status_text(self.status));
+        for (name, value) in self.headers.iter() {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        // * 204 和 304 没有响应体，也不写 Content-Length（304 的 Content-Length 指的是完整响应的长度）
+        if self.status != 204 && self.status != 304 {
+            head.push_str(&format!("Content-Length: {}\r\n", self.content_length()));
+        }
+        head.push_str("\r\n");
+        out.write_all(head.as_bytes())?;
+        out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_all(data: &[u8]) -> Result<Vec<Request>, HttpError> {
+        let mut parser = Parser::new(Limits::default());
+        parser.feed(data);
+        let mut requests = Vec::new();
+        while let Some(request) = parser.parse()? {
+            requests.push(request);
+        }
+        Ok(requests)
+    }
+
+    #[test]
+    fn split_reads_and_bodies() {
+        let raw = b"POST /users?id=7&x HTTP/1.1\r\nHost: localhost\r\ncontent-LENGTH: 5\r\nX-Tag: a\r\nx-tag: b\r\nX-Long: one\r\n  two\r\n\r\nhelloGET / HTTP/1.0\r\n\r\n";
+        // 一次只收到一个字节也能解析
+        let mut parser = Parser::new(Limits::default());
+        let mut requests = Vec::new();
+        for byte in raw.iter() {
+            parser.feed(&[*byte]);
+            requests.extend(parser.parse().unwrap());
+        }
+        assert_eq!(2, requests.len());
+        let request = &requests[0];
+        assert_eq!("POST", request.method);
+        assert_eq!("/users", request.path);
+        assert_eq!(Some("id=7&x".to_string()), request.query);
+        assert_eq!(Version::Http11, request.version);
+        assert_eq!(Some("a, b"), request.headers.get("X-TAG"));
+        assert_eq!(Some("one two"), request.headers.get("x-long"));
+        assert_eq!(b"hello".to_vec(), request.body);
+        assert_eq!(Version::Http10, requests[1].version);
+        assert!(parser.is_empty());
+
+        let chunked = b"PUT /f HTTP/1.1\r\nHost: h\r\nTransfer-Encoding: chunked\r\n\r\n4;ext=1\r\nWiki\r\n6\r\npedia \r\nE\r\nin \r\n\r\nchunks.\r\n0\r\nChecksum: abc\r\n\r\n";
+        let request = parse_all(chunked).unwrap().remove(0);
+        assert_eq!(b"Wikipedia in \r\n\r\nchunks.".to_vec(), request.body);
+        assert_eq!(Some("abc"), request.headers.get("checksum"));
+        // chunked 请求体也可以一个字节一个字节地收到
+        let mut parser = Parser::new(Limits::default());
+        let mut requests = Vec::new();
+        for byte in chunked.iter() {
+            parser.feed(&[*byte]);
+            requests.extend(parser.parse().unwrap());
+        }
+        assert_eq!(vec![request], requests);
+
+        // 请求体比 1 KB 大，分几次读取
+        let body = "x".repeat(5000);
+        let raw = format!(
+            "POST / HTTP/1.1\r\nHost: h\r\nContent-Length: 5000\r\n\r\n{}",
+            body
+        );
+        let mut stream = raw.as_bytes();
+        let mut parser = Parser::new(Limits::default());
+        let request = read_request(&mut stream, &mut parser).unwrap().unwrap();
+        assert_eq!(5000, request.body.len());
+        assert!(read_request(&mut stream, &mut parser).unwrap().is_none());
+    }
+
+    #[test]
+    fn malformed_requests() {
+        let status = |data: &[u8]| parse_all(data).map(|_| 0).unwrap_or_else(|e| e.status);
+        assert_eq!(400, status(b"GET /\r\n\r\n"));
+        assert_eq!(400, status(b"GET / HTTP/1.1\r\n\r\n"));
+        assert_eq!(400, status(b"GET  / HTTP/1.1\r\nHost: h\r\n\r\n"));
+        assert_eq!(400, status(b"G(T / HTTP/1.1\r\nHost: h\r\n\r\n"));
+        assert_eq!(400, status(b"GET / HTTP/1.1\r\nHost : h\r\n\r\n"));
+        assert_eq!(400, status(b"GET / HTTP/1.1\r\nHost: h\r\nbad\r\n\r\n"));
+        assert_eq!(505, status(b"GET / HTTP/2.0\r\nHost: h\r\n\r\n"));
+        assert_eq!(
+            400,
+            status(
+                b"POST / HTTP/1.1\r\nHost: h\r\nContent-Length: 1\r\nContent-Length: 2\r\n\r\nab"
+            )
+        );
+        assert_eq!(
+            0,
+            status(
+                b"POST / HTTP/1.1\r\nHost: h\r\nContent-Length: 1\r\nContent-Length: 1\r\n\r\na"
+            )
+        );
+        assert_eq!(
+            400,
+            status(b"POST / HTTP/1.1\r\nHost: h\r\nContent-Length: 3\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n")
+        );
+        assert_eq!(
+            400,
+            status(b"POST / HTTP/1.1\r\nHost: h\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n")
+        );
+        // chunked 不在最后时是格式错误，在 chunked 之前还有其他编码时不支持
+        let te = |codings: &str| {
+            status(
+                format!(
+                    "POST / HTTP/1.1\r\nHost: h\r\nTransfer-Encoding: {}\r\n\r\n0\r\n\r\n",
+                    codings
+                )
+                .as_bytes(),
+            )
+        };
+        assert_eq!(0, te("Chunked"));
+        assert_eq!(400, te("chunked, gzip"));
+        assert_eq!(400, te("chunked, chunked"));
+        assert_eq!(501, te("gzip, chunked"));
+        assert_eq!(501, te("gzip"));
+
+        let mut parser = Parser::new(Limits {
+            max_head: 64,
+            max_headers: 2,
+            max_body: 4,
+        });
+        parser.feed(format!("GET /{} HTTP/1.1\r\n", "a".repeat(100)).as_bytes());
+        assert_eq!(431, parser.parse().unwrap_err().status);
+        let mut parser = Parser::new(Limits {
+            max_body: 4,
+            ..Limits::default()
+        });
+        parser.feed(b"POST / HTTP/1.1\r\nHost: h\r\nContent-Length: 5\r\n\r\n");
+        assert_eq!(413, parser.parse().unwrap_err().status);
+        // 前面已经有一个 chunk 时，巨大的 chunk size 也不能让长度相加溢出
+        assert_eq!(
+            413,
+            status(b"POST / HTTP/1.1\r\nHost: h\r\nTransfer-Encoding: chunked\r\n\r\n1\r\na\r\nffffffffffffffff\r\n")
+        );
+
+        // 连接在请求中途被关闭
+        let mut stream = &b"GET / HTTP/1.1\r\nHo"[..];
+        let mut parser = Parser::new(Limits::default());
+        assert!(matches!(
+            read_request(&mut stream, &mut parser),
+            Err(ReadError::Http(HttpError { status: 400, .. }))
+        ));
+
+        let mut out = Vec::new();
+        Response::error(&HttpError::bad_request("malformed request line"))
+            .write_to(&mut out)
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        assert!(
+            out.ends_with("Content-Length: 40\r\n\r\n400 Bad Request: malformed request line\n")
+        );
+    }
+}
@@ -0,0 +1,300 @@
+///* Router：按 method 和路径模式分发请求
+// 取代 handle_connection 里写死的 if / else：
+// - 路径模式按 / 分段：普通的段必须和解码后的路径段相同；`:name` 匹配任意一段，值在 Params 中；
+//   最后一段可以是 `*name`（或者只写 `*`），匹配剩下的所有段（可以为空）
+// - 多个模式都能匹配时取最具体的：逐段比较，普通段优先于 :name，:name 优先于 *
+// - 路径能匹配但 method 不对时回复 405，Allow 头列出这个路径支持的 method（有 GET 时也有 HEAD）
+// - 都不匹配时交给 not_found 处理，默认回复 404
+// - HEAD 请求没有单独注册时使用 GET 的处理函数，写出响应时会去掉响应体
+// - handler panic 时回复 500：panic 不会再展开到 worker，一个坏请求带不走线程池中的线程
+// - Router 创建后只读，放在 Arc 里给线程池的每个 worker 共享
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+
+use crate::http::{self, Request, Response};
+
+/// 路径中 :name 和 *name 匹配到的值，已经做过百分号解码
+#[derive(Debug, Default, PartialEq)]
+pub struct Params {
+    values: Vec<(String, String)>,
+}
+
+impl Params {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+pub type Handler = Arc<dyn Fn(&Request, &Params) -> Response + Send + Sync>;
+
+// * 段的种类，顺序就是匹配的优先级
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Static(String),
+    Param(String),
+    Wildcard(String),
+}
+
+impl Segment {
+    fn rank(&self) -> u8 {
+        match self {
+            Segment::Static(_) => 0,
+            Segment::Param(_) => 1,
+            Segment::Wildcard(_) => 2,
+        }
+    }
+}
+
+struct Route {
+    method: String,
+    segments: Vec<Segment>,
+    handler: Handler,
+}
+
+impl Route {
+    // 匹配成功时返回 Params；path 中的一段解码失败时当作不匹配
+    fn matches(&self, path: &[&str]) -> Option<Params> {
+        let mut params = Params::default();
+        for (i, segment) in self.segments.iter().enumerate() {
+            match segment {
+                // * 和 :name 一样先解码再比较，/users/m%65 也能匹配 /users/me
+                Segment::Static(expected) => {
+                    if http::percent_decode(path.get(i)?)? != *expected {
+                        return None;
+                    }
+                }
+                Segment::Param(name) => {
+                    let value = http::percent_decode(path.get(i)?)?;
+                    params.values.push((name.clone(), value));
+                }
+                Segment::Wildcard(name) => {
+                    let rest = path[i.min(path.len())..].join("/");
+                    params
+                        .values
+                        .push((name.clone(), http::percent_decode(&rest)?));
+                    return Some(params);
+                }
+            }
+        }
+        (path.len() == self.segments.len()).then_some(params)
+    }
+
+    fn rank(&self) -> Vec<u8> {
+        self.segments.iter().map(Segment::rank).collect()
+    }
+}
+
+fn call(handler: &Handler, request: &Request, params: &Params) -> Response {
+    panic::catch_unwind(AssertUnwindSafe(|| handler(request, params))).unwrap_or_else(|_| {
+        Response::new(500)
+            .header("Content-Type", "text/plain; charset=utf-8")
+            .body("500 Internal Server Error\n")
+    })
+}
+
+fn split(path: &str) -> Vec<&str> {
+    path.trim_start_matches('/').split('/').collect()
+}
+
+#[derive(Default)]
+pub struct Router {
+    routes: Vec<Route>,
+    not_found: Option<Handler>,
+}
+
+impl Router {
+    pub fn new() -> Router {
+        Router::default()
+    }
+
+    /// 注册一个处理函数，`pattern` 形如 "/users/:id" 或 "/static/*path"
+    ///
+    /// # Panics
+    ///
+    /// `pattern` 不以 / 开头，或者 * 不在最后一段时 panic
+    pub fn route<F>(mut self, method: &str, pattern: &str, handler: F) -> Router
+    where
+        F: Fn(&Request, &Params) -> Response + Send + Sync + 'static,
+    {
+        assert!(
+            pattern.starts_with('/'),
+            "route pattern must start with '/'"
+        );
+        let parts = split(pattern);
+        let segments: Vec<Segment> = parts
+            .iter()
+            .enumerate()
+            .map(|(i, part)| {
+                if let Some(name) = part.strip_prefix(':') {
+                    Segment::Param(name.to_string())
+                } else if let Some(name) = part.strip_prefix('*') {
+                    assert!(i + 1 == parts.len(), "'*' must be the last segment");
+                    Segment::Wildcard(name.to_string())
+                } else {
+                    Segment::Static(part.to_string())
+                }
+            })
+            .collect();
+        self.routes.push(Route {
+            method: method.to_string(),
+            segments,
+            handler: Arc::new(handler),
+        });
+        self
+    }
+
+    pub fn get<F>(self, pattern: &str, handler: F) -> Router
+    where
+        F: Fn(&Request, &Params) -> Response + Send + Sync + 'static,
+    {
+        self.route("GET", pattern, handler)
+    }
+
+    pub fn post<F>(self, pattern: &str, handler: F) -> Router
+    where
+        F: Fn(&Request, &Params) -> Response + Send + Sync + 'static,
+    {
+        self.route("POST", pattern, handler)
+    }
+
+    /// 没有路由匹配时使用的处理函数
+    pub fn not_found<F>(mut self, handler: F) -> Router
+    where
+        F: Fn(&Request, &Params) -> Response + Send + Sync + 'static,
+    {
+        self.not_found = Some(Arc::new(handler));
+        self
+    }
+
+    pub fn dispatch(&self, request: &Request) -> Response {
+        let path = split(&request.path);
+        let mut allowed: Vec<&str> = Vec::new();
+        let mut best: Option<(Vec<u8>, &Route, Params)> = None;
+        for route in &self.routes {
+            let params = match route.matches(&path) {
+                Some(params) => params,
+                None => continue,
+            };
+            let head_as_get = request.method == "HEAD" && route.method == "GET";
+            if route.method != request.method && !head_as_get {
+                if !allowed.contains(&route.method.as_str()) {
+                    allowed.push(&route.method);
+                }
+                continue;
+            }
+            let rank = route.rank();
+            if best.as_ref().is_none_or(|(best, _, _)| rank < *best) {
+                best = Some((rank, route, params));
+            }
+        }
+
+        if let Some((_, route, params)) = best {
+            return call(&route.handler, request, &params);
+        }
+        if !allowed.is_empty() {
+            // GET 的处理函数也处理 HEAD
+            if allowed.contains(&"GET") && !allowed.contains(&"HEAD") {
+                allowed.push("HEAD");
+            }
+            allowed.sort_unstable();
+            return Response::new(405)
+                .header("Allow", &allowed.join(", "))
+                .header("Content-Type", "text/plain; charset=utf-8")
+                .body("405 Method Not Allowed\n");
+        }
+        match &self.not_found {
+            Some(handler) => call(handler, request, &Params::default()),
+            None => Response::new(404)
+                .header("Content-Type", "text/plain; charset=utf-8")
+                .body("404 Not Found\n"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{Headers, Version};
+
+    fn request(method: &str, path: &str) -> Request {
+        Request {
+            method: method.to_string(),
+            path: path.to_string(),
+            query: None,
+            version: Version::Http11,
+            headers: Headers::default(),
+            body: Vec::new(),
+        }
+    }
+
+    fn body(response: Response) -> String {
+        String::from_utf8(response.body).unwrap()
+    }
+
+    fn router() -> Router {
+        Router::new()
+            .get("/", |_, _| Response::new(200).body("home"))
+            .get("/users/:id", |_, params| {
+                Response::new(200).body(format!("user {}", params.get("id").unwrap()))
+            })
+            .get("/users/me", |_, _| Response::new(200).body("me"))
+            .post("/users/:id", |_, _| Response::new(201))
+            .get("/users/:id/posts/:post", |_, params| {
+                let (id, post) = (params.get("id").unwrap(), params.get("post").unwrap());
+                Response::new(200).body(format!("{}/{}", id, post))
+            })
+            .get("/panic", |_, _| panic!("handler panicked"))
+            .get("/static/*path", |_, params| {
+                Response::new(200).body(format!("file {}", params.get("path").unwrap()))
+            })
+    }
+
+    #[test]
+    fn params_and_wildcards() {
+        let router = router();
+        assert_eq!("home", body(router.dispatch(&request("GET", "/"))));
+        assert_eq!(
+            "user 42",
+            body(router.dispatch(&request("GET", "/users/42")))
+        );
+        assert_eq!(
+            "user a b",
+            body(router.dispatch(&request("GET", "/users/a%20b")))
+        );
+        // 普通段比 :id 优先，和注册的顺序无关
+        assert_eq!("me", body(router.dispatch(&request("GET", "/users/me"))));
+        assert_eq!("me", body(router.dispatch(&request("GET", "/users/m%65"))));
+        assert_eq!(
+            "7/9",
+            body(router.dispatch(&request("GET", "/users/7/posts/9")))
+        );
+        assert_eq!(
+            "file css/site.css",
+            body(router.dispatch(&request("GET", "/static/css/site.css")))
+        );
+        assert_eq!("file ", body(router.dispatch(&request("GET", "/static/"))));
+        assert_eq!(201, router.dispatch(&request("POST", "/users/1")).status);
+        assert_eq!(500, router.dispatch(&request("GET", "/panic")).status);
+    }
+
+    #[test]
+    fn not_found_and_method_not_allowed() {
+        let router = router();
+        let response = router.dispatch(&request("DELETE", "/users/1"));
+        assert_eq!(405, response.status);
+        assert_eq!(Some("GET, HEAD, POST"), response.headers.get("allow"));
+        let response = router.dispatch(&request("POST", "/"));
+        assert_eq!(Some("GET, HEAD"), response.headers.get("allow"));
+        assert_eq!(
+            404,
+            router.dispatch(&request("GET", "/users/1/posts")).status
+        );
+        assert_eq!(404, router.dispatch(&request("GET", "/users/%zz")).status);
+
+        let router = router.not_found(|request, _| Response::new(404).body(request.path.clone()));
+        assert_eq!("/nope", body(router.dispatch(&request("GET", "/nope"))));
+    }
+}
@@ -0,0 +1,373 @@
+///* 持久连接：一个 TCP 连接上依次处理多个请求
+// 书中的 handle_connection 每个连接只处理一个请求就关闭；HTTP/1.1 的连接默认是持久的：
+// - HTTP/1.1 默认保持连接，请求带 Connection: close 时回复之后关闭
+//   HTTP/1.0 默认关闭，请求带 Connection: keep-alive 时才保持
+// - 客户端可以不等响应就连续发送多个请求（pipelining）：Parser 中多出来的字节就是下一个请求，
+//   按收到的顺序逐个处理、逐个回复，响应的顺序和请求一致
+// - 空闲超时：等待下一个请求的时间超过 idle_timeout 时关闭连接；请求收到一半就超时则回复 408
+// - * 线程池只有几个 worker，空闲的持久连接不能一直占着：读超时设成很短的 poll_interval，
+//   每次超时检查有没有已经 accept、还在排队等 worker 的连接；有的话空闲的连接立即关闭，
+//   正在处理的请求回复时带上 Connection: close
+// - 一个连接最多处理 max_requests 个请求，最后一个响应带上 Connection: close
+// - 请求解析出错时回复错误并关闭：出错之后无法确定下一个请求从哪里开始
+// - HEAD 请求的响应只写头部，否则多出来的响应体会被客户端当成下一个响应的开头
+// - 服务器开始关闭之后（见 8shutdown.rs），当前请求回复完就关闭连接；
+//   之后才收到的请求（包括只收到一半的）不再处理，回复 503 并关闭，而不是一声不响地断开
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+
+use crate::http::{self, HttpError, Limits, Parser, ReadError, Response, Version};
+use crate::router::Router;
+use crate::shutdown::Connection;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Options {
+    pub idle_timeout: Duration,
+    // * 由调用者设置为 TcpStream 的读超时，serve 每次超时时检查是否该关闭连接
+    pub poll_interval: Duration,
+    pub max_requests: usize,
+    pub limits: Limits,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            idle_timeout: Duration::from_secs(5),
+            poll_interval: Duration::from_millis(100),
+            max_requests: 100,
+            limits: Limits::default(),
+        }
+    }
+}
+
+/// 在 `stream` 上处理请求，直到连接该关闭为止；返回回复了的请求数
+///
+/// `connection` 为登记在 Connections 中的这个连接，用来在关闭服务器时结束 keep-alive
+pub fn serve<S: Read + Write>(
+    stream: &mut S,
+    router: &Router,
+    options: &Options,
+    connection: Option<&Connection>,
+) -> usize {
+    let mut parser = Parser::new(options.limits);
+    let mut served = 0;
+    let mut idle_since = Instant::now();
+    while served < options.max_requests {
+        let request = match http::read_request(stream, &mut parser) {
+            Ok(Some(request)) => request,
+            // 客户端在两个请求之间关闭了连接
+            Ok(None) => break,
+            // 关闭时读的一端被关掉，收到一半的请求会变成 "incomplete request"
+            Err(ReadError::Http(_)) if connection.is_some_and(Connection::closing) => {
+                respond(stream, &shutting_down(), false);
+                break;
+            }
+            Err(ReadError::Http(error)) => {
+                respond(stream, &Response::error(&error), false);
+                break;
+            }
+            Err(ReadError::Io(e)) if is_timeout(&e) => {
+                // 已经收到的字节留在 parser 里，继续读就行
+                let yield_worker =
+                    parser.is_empty() && connection.is_some_and(Connection::others_waiting);
+                if !yield_worker && idle_since.elapsed() < options.idle_timeout {
+                    continue;
+                }
+                // 空闲的连接直接关闭；请求只收到一部分时告诉客户端超时了
+                if !parser.is_empty() {
+                    let error = HttpError {
+                        status: 408,
+                        reason: "timed out waiting for the request",
+                    };
+                    respond(stream, &Response::error(&error), false);
+                }
+                break;
+            }
+            Err(ReadError::Io(e)) => {
+                eprintln!("failed to read request: {}", e);
+                break;
+            }
+        };
+
+        if connection.is_some_and(|c| !c.begin_request()) {
+            respond(stream, &shutting_down(), request.method == "HEAD");
+            break;
+        }
+        served += 1;
+        let mut response = router.dispatch(&request);
+        let keep_alive = keep_alive(&request)
+            && served < options.max_requests
+            && !connection.is_some_and(|c| c.closing() || c.others_waiting())
+            && !response.headers.has_token("connection", "close");
+        if keep_alive {
+            // HTTP/1.1 的连接本来就是持久的，不需要再说明
+            if request.version == Version::Http10 {
+                response.headers.insert("Connection", "keep-alive");
+            }
+            response.headers.insert(
+                "Keep-Alive",
+                &format!(
+                    "timeout={}, max={}",
+                    options.idle_timeout.as_secs(),
+                    options.max_requests - served
+                ),
+            );
+        } else if !response.headers.has_token("connection", "close") {
+            response.headers.insert("Connection", "close");
+        }
+
+        let written = respond(stream, &response, request.method == "HEAD");
+        let open = connection.is_none_or(Connection::end_request);
+        if !written || !keep_alive || !open {
+            break;
+        }
+        idle_since = Instant::now();
+    }
+    served
+}
+
+/// 服务器正在关闭，请求没有被处理；带有 Connection: close
+pub fn shutting_down() -> Response {
+    Response::error(&HttpError {
+        status: 503,
+        reason: "the server is shutting down",
+    })
+}
+
+// 请求希望保持连接
+fn keep_alive(request: &http::Request) -> bool {
+    match request.version {
+        Version::Http11 => !request.headers.has_token("connection", "close"),
+        Version::Http10 => request.headers.has_token("connection", "keep-alive"),
+    }
+}
+
+// set_read_timeout 超时时，Unix 上返回 WouldBlock，Windows 上返回 TimedOut
+fn is_timeout(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
+// 写出失败说明客户端已经走了，返回 false
+fn respond<W: Write>(stream: &mut W, response: &Response, head_only: bool) -> bool {
+    let result = if head_only {
+        response.write_head(stream)
+    } else {
+        response.write_to(stream)
+    };
+    match result {
+        Ok(()) => true,
+        Err(e) => {
+            eprintln!("failed to write response: {}", e);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shutdown::Connections;
+    use std::collections::VecDeque;
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::Arc;
+
+    // 每次 read 返回一段预先准备好的数据；数据用完之后返回 EOF，或者模拟读超时
+    struct MockStream {
+        reads: VecDeque<Vec<u8>>,
+        timeout: bool,
+        // 每次读超时时调用
+        on_timeout: Option<Box<dyn FnMut()>>,
+        written: Vec<u8>,
+    }
+
+    impl MockStream {
+        fn new(reads: &[&str], timeout: bool) -> MockStream {
+            MockStream {
+                reads: reads.iter().map(|s| s.as_bytes().to_vec()).collect(),
+                timeout,
+                on_timeout: None,
+                written: Vec::new(),
+            }
+        }
+
+        fn responses(&self) -> Vec<String> {
+            let written = String::from_utf8(self.written.clone()).unwrap();
+            written
+                .split("HTTP/1.1 ")
+                .skip(1)
+                .map(|s| s.to_string())
+                .collect()
+        }
+    }
+
+    impl Read for MockStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self.reads.pop_front() {
+                Some(data) => {
+                    buf[..data.len()].copy_from_slice(&data);
+                    Ok(data.len())
+                }
+                None if self.timeout => {
+                    if let Some(on_timeout) = &mut self.on_timeout {
+                        on_timeout();
+                    }
+                    Err(io::ErrorKind::WouldBlock.into())
+                }
+                None => Ok(0),
+            }
+        }
+    }
+
+    impl Write for MockStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.written.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn router() -> Router {
+        Router::new().get("/:n", |_, params| {
+            Response::new(200).body(params.get("n").unwrap().to_string())
+        })
+    }
+
+    #[test]
+    fn pipelining_and_connection_close() {
+        let router = router();
+        let options = Options::default();
+
+        // 三个请求在同一次读取中到达，按顺序回复
+        let mut stream = MockStream::new(
+            &[
+                "GET /1 HTTP/1.1\r\nHost: h\r\n\r\nGET /2 HTTP/1.1\r\nHost: h\r\n\r\nGET /3 HT",
+                "TP/1.1\r\nHost: h\r\n\r\n",
+            ],
+            false,
+        );
+        assert_eq!(3, serve(&mut stream, &router, &options, None));
+        let responses = stream.responses();
+        assert_eq!(3, responses.len());
+        for (i, response) in responses.iter().enumerate() {
+            assert!(response.ends_with(&format!("\r\n\r\n{}", i + 1)));
+            assert!(response.contains("Keep-Alive: timeout=5"));
+        }
+
+        // Connection: close 之后的请求不再处理
+        let mut stream = MockStream::new(
+            &["GET /1 HTTP/1.1\r\nHost: h\r\nConnection: close\r\n\r\nGET /2 HTTP/1.1\r\nHost: h\r\n\r\n"],
+            false,
+        );
+        assert_eq!(1, serve(&mut stream, &router, &options, None));
+        assert!(stream.responses()[0].contains("Connection: close\r\n"));
+
+        // HTTP/1.0 默认关闭，要求保持时回复 keep-alive
+        let mut stream =
+            MockStream::new(&["GET /1 HTTP/1.0\r\n\r\nGET /2 HTTP/1.0\r\n\r\n"], false);
+        assert_eq!(1, serve(&mut stream, &router, &options, None));
+        let mut stream = MockStream::new(
+            &["GET /1 HTTP/1.0\r\nConnection: keep-alive\r\n\r\nGET /2 HTTP/1.0\r\n\r\n"],
+            false,
+        );
+        assert_eq!(2, serve(&mut stream, &router, &options, None));
+        let responses = stream.responses();
+        assert!(responses[0].contains("Connection: keep-alive\r\n"));
+        assert!(responses[1].contains("Connection: close\r\n"));
+
+        // HEAD 的响应没有响应体，下一个响应紧接在头部之后
+        let mut stream = MockStream::new(
+            &["HEAD /1 HTTP/1.1\r\nHost: h\r\n\r\nGET /2 HTTP/1.1\r\nHost: h\r\n\r\n"],
+            false,
+        );
+        assert_eq!(2, serve(&mut stream, &router, &options, None));
+        let responses = stream.responses();
+        assert!(responses[0].contains("Content-Length: 1\r\n\r\n"));
+        assert!(responses[0].ends_with("\r\n\r\n"));
+    }
+
+    #[test]
+    fn limits_timeouts_and_errors() {
+        let router = router();
+        let request = "GET /1 HTTP/1.1\r\nHost: h\r\n\r\n";
+        let options = Options {
+            max_requests: 2,
+            idle_timeout: Duration::from_millis(20),
+            ..Options::default()
+        };
+        let mut stream = MockStream::new(&[request, request, request], false);
+        assert_eq!(2, serve(&mut stream, &router, &options, None));
+        let responses = stream.responses();
+        assert!(responses[0].contains("Keep-Alive: timeout=0, max=1\r\n"));
+        assert!(responses[1].contains("Connection: close\r\n"));
+        assert!(!responses[1].contains("Keep-Alive"));
+
+        // 空闲超时：没有收到请求时直接关闭，收到一半时回复 408
+        let mut stream = MockStream::new(&[request], true);
+        assert_eq!(1, serve(&mut stream, &router, &options, None));
+        assert_eq!(1, stream.responses().len());
+        let mut stream = MockStream::new(&["GET /1 HTTP/1.1\r\n"], true);
+        assert_eq!(0, serve(&mut stream, &router, &options, None));
+        assert!(stream.responses()[0].starts_with("408 Request Timeout\r\n"));
+
+        // 解析出错之后不再处理后面的字节
+        let mut stream = MockStream::new(&["GET /1 HTTP/1.1\r\n\r\n", request], false);
+        assert_eq!(0, serve(&mut stream, &router, &options, None));
+        let responses = stream.responses();
+        assert_eq!(1, responses.len());
+        assert!(responses[0].starts_with("400 Bad Request\r\n"));
+    }
+
+    #[test]
+    fn idle_connections_yield_to_waiting_ones() {
+        let router = router();
+        let request = "GET /1 HTTP/1.1\r\nHost: h\r\n\r\n";
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let _client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (socket, _) = listener.accept().unwrap();
+        let connections = Arc::new(Connections::default());
+        let connection = connections.open(&socket).unwrap();
+        let options = Options::default();
+
+        // 空闲时有新的连接在排队：不等 idle_timeout，立即让出 worker
+        let mut stream = MockStream::new(&[request], true);
+        let waiting = Arc::clone(&connections);
+        stream.on_timeout = Some(Box::new(move || waiting.accepted()));
+        let start = Instant::now();
+        assert_eq!(1, serve(&mut stream, &router, &options, Some(&connection)));
+        assert!(start.elapsed() < options.idle_timeout);
+        assert!(stream.responses()[0].contains("Keep-Alive"));
+
+        // 处理请求时已经有连接在排队：回复之后就关闭
+        let mut stream = MockStream::new(&[request, request], false);
+        assert_eq!(1, serve(&mut stream, &router, &options, Some(&connection)));
+        assert!(stream.responses()[0].contains("Connection: close\r\n"));
+    }
+
+    #[test]
+    fn requests_after_shutdown_get_503() {
+        let router = router();
+        let request = "GET /1 HTTP/1.1\r\nHost: h\r\n\r\n";
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let _client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (socket, _) = listener.accept().unwrap();
+        let connections = Arc::new(Connections::default());
+        let options = Options::default();
+
+        // 连接空闲时开始关闭，之后收到的完整请求和半个请求都回复 503
+        let connection = connections.open(&socket).unwrap();
+        connections.close_all(Duration::ZERO);
+        for reads in [&[request][..], &["GET /1 HTTP/1.1\r\n"][..]] {
+            let mut stream = MockStream::new(reads, false);
+            assert_eq!(0, serve(&mut stream, &router, &options, Some(&connection)));
+            let responses = stream.responses();
+            assert_eq!(1, responses.len());
+            assert!(responses[0].starts_with("503 Service Unavailable\r\n"));
+            assert!(responses[0].contains("Connection: close\r\n"));
+        }
+    }
+}
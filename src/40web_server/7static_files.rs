@@ -0,0 +1,580 @@
+///* 静态文件：把 URL 路径映射到 root 目录下的文件
+// - 路径先按段百分号解码，再逐段拼到 root 后面：. 和 .. 、反斜杠、NUL 一律拒绝（403）；
+//   原始路径中的 %2F、%5C 也拒绝，解码后会变成分隔符；最后确认 canonicalize 的结果仍在 root 里（符号链接）
+// - Content-Type 按扩展名决定，不认识的扩展名是 application/octet-stream；按字节读取，二进制文件也没问题
+// - 文件内容不读进内存，写出响应时才从打开的文件复制过去（见 http::FileBody），HEAD 请求不读文件
+// - 缓存：ETag 由文件大小和修改时间组成，另外带上 Last-Modified；
+//   请求带 If-None-Match（优先）或 If-Modified-Since 且文件没变时回复 304，不带响应体
+// - Range: bytes=a-b / a- / -n 只返回文件的一部分（206 + Content-Range），超出文件大小时 416；
+//   多个区间的请求直接返回整个文件；If-Range 和当前版本不一致时忽略 Range
+// - 目录：路径不以 / 结尾时先重定向到带 / 的地址（相对链接才正确），有 index.html 就返回它，
+//   否则在打开了 listing 时列出目录内容，不然 404
+use std::fs::{self, File};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::http::{Headers, Request, Response};
+
+pub struct StaticFiles {
+    root: PathBuf,
+    listing: bool,
+}
+
+impl StaticFiles {
+    pub fn new(root: impl Into<PathBuf>) -> StaticFiles {
+        StaticFiles {
+            root: root.into(),
+            listing: false,
+        }
+    }
+
+    /// 没有 index.html 的目录列出其中的文件
+    pub fn listing(mut self, listing: bool) -> StaticFiles {
+        self.listing = listing;
+        self
+    }
+
+    /// 处理一个请求，`path` 是路由中 *path 匹配到的、已经解码的相对路径
+    pub fn serve(&self, request: &Request, path: &str) -> Response {
+        let raw = request.path.to_ascii_lowercase();
+        if raw.contains("%2f") || raw.contains("%5c") {
+            return text(403, "403 Forbidden\n");
+        }
+        let file = match self.resolve(path) {
+            Some(file) => file,
+            None => return text(403, "403 Forbidden\n"),
+        };
+        match self.respond(request, &file) {
+            Ok(response) => response,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => text(404, "404 Not Found\n"),
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied => text(403, "403 Forbidden\n"),
+            Err(e) => {
+                eprintln!("failed to serve {}: {}", file.display(), e);
+                text(500, "500 Internal Server Error\n")
+            }
+        }
+    }
+
+    // 拼出文件路径，不安全的路径返回 None
+    fn resolve(&self, path: &str) -> Option<PathBuf> {
+        let mut resolved = self.root.clone();
+        for segment in path.split('/').filter(|s| !s.is_empty()) {
+            if segment.contains(['\\', '\0']) {
+                return None;
+            }
+            // * 只接受普通的文件名：排除 . 、.. 以及 Windows 上的 C: 这种前缀
+            let mut components = Path::new(segment).components();
+            match (components.next(), components.next()) {
+                (Some(Component::Normal(_)), None) => resolved.push(segment),
+                _ => return None,
+            }
+        }
+        // 符号链接可能指向 root 之外；文件不存在时交给后面回复 404
+        if let (Ok(root), Ok(real)) = (self.root.canonicalize(), resolved.canonicalize()) {
+            if !real.starts_with(root) {
+                return None;
+            }
+        }
+        Some(resolved)
+    }
+
+    fn respond(&self, request: &Request, path: &Path) -> io::Result<Response> {
+        if !fs::metadata(path)?.is_dir() {
+            return file(request, path);
+        }
+        if !request.path.ends_with('/') {
+            let mut location = format!("{}/", request.path);
+            if let Some(query) = &request.query {
+                location.push('?');
+                location.push_str(query);
+            }
+            return Ok(Response::new(301).header("Location", &location));
+        }
+        let index = path.join("index.html");
+        if index.is_file() {
+            return file(request, &index);
+        }
+        if self.listing {
+            return listing(request, path);
+        }
+        Err(io::ErrorKind::NotFound.into())
+    }
+}
+
+fn text(status: u16, body: &str) -> Response {
+    Response::new(status)
+        .header("Content-Type", "text/plain; charset=utf-8")
+        .body(body)
+}
+
+fn file(request: &Request, path: &Path) -> io::Result<Response> {
+    let file = File::open(path)?;
+    let metadata = file.metadata()?;
+    let len = metadata.len();
+    let modified = metadata.modified().unwrap_or(UNIX_EPOCH);
+    let nanos = modified.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let etag = format!("\"{:x}-{:x}\"", len, nanos.as_nanos());
+
+    // * no-cache 表示可以缓存，但每次使用前都要用 ETag / Last-Modified 向服务器确认
+    let mut response = Response::new(200)
+        .header("ETag", &etag)
+        .header("Last-Modified", &http_date(modified))
+        .header("Cache-Control", "no-cache");
+    if not_modified(&request.headers, &etag, modified) {
+        response.status = 304;
+        return Ok(response);
+    }
+    response = response
+        .header("Content-Type", mime_type(path))
+        .header("Accept-Ranges", "bytes");
+
+    let range = request
+        .headers
+        .get("range")
+        .filter(|_| if_range(&request.headers, &etag, modified))
+        .and_then(|value| parse_range(value, len));
+    match range {
+        None => Ok(response.file(file, 0, len)),
+        Some(Ok((start, end))) => {
+            response.status = 206;
+            Ok(response
+                .header("Content-Range", &format!("bytes {}-{}/{}", start, end, len))
+                .file(file, start, end - start + 1))
+        }
+        Some(Err(())) => Ok(text(416, "416 Range Not Satisfiable\n")
+            .header("Content-Range", &format!("bytes */{}", len))),
+    }
+}
+
+// 客户端缓存的版本还是最新的
+fn not_modified(headers: &Headers, etag: &str, modified: SystemTime) -> bool {
+    // * 有 If-None-Match 时忽略 If-Modified-Since；这里用弱比较，W/ 前缀不影响结果
+    if let Some(value) = headers.get("if-none-match") {
+        return value.split(',').any(|tag| {
+            let tag = tag.trim();
+            tag == "*" || tag.trim_start_matches("W/") == etag
+        });
+    }
+    match headers.get("if-modified-since").and_then(parse_http_date) {
+        Some(since) => seconds(modified) <= seconds(since),
+        None => false,
+    }
+}
+
+// 没有 If-Range，或者 If-Range 给出的 ETag / 日期就是当前的版本
+fn if_range(headers: &Headers, etag: &str, modified: SystemTime) -> bool {
+    match headers.get("if-range") {
+        None => true,
+        Some(value) if value.starts_with('"') => value == etag,
+        Some(value) => {
+            parse_http_date(value).is_some_and(|date| seconds(date) == seconds(modified))
+        }
+    }
+}
+
+fn seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+// 解析单个区间，结果是闭区间；None 表示忽略 Range（格式不对或者有多个区间），Err 表示超出了文件
+fn parse_range(value: &str, len: u64) -> Option<Result<(u64, u64), ()>> {
+    let spec = value.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') {
+        return None;
+    }
+    let number = |s: &str| {
+        if !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit()) {
+            s.parse::<u64>().ok()
+        } else {
+            None
+        }
+    };
+    let (start, end) = spec.split_once('-')?;
+    if start.is_empty() {
+        // 最后 n 个字节
+        let n = number(end)?;
+        if n == 0 || len == 0 {
+            return Some(Err(()));
+        }
+        return Some(Ok((len.saturating_sub(n), len - 1)));
+    }
+    let start = number(start)?;
+    let end = match end {
+        "" => u64::MAX,
+        end => number(end)?,
+    };
+    if end < start {
+        return None;
+    }
+    if start >= len {
+        return Some(Err(()));
+    }
+    Some(Ok((start, end.min(len - 1))))
+}
+
+fn mime_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    match extension.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "json" => "application/json",
+        "txt" | "md" | "rs" | "toml" => "text/plain; charset=utf-8",
+        "xml" => "application/xml",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "pdf" => "application/pdf",
+        "wasm" => "application/wasm",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "mp3" => "audio/mpeg",
+        "mp4" => "video/mp4",
+        "zip" => "application/zip",
+        _ => "application/octet-stream",
+    }
+}
+
+fn listing(request: &Request, dir: &Path) -> io::Result<Response> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let mut name = entry.file_name().to_string_lossy().into_owned();
+        if entry.file_type()?.is_dir() {
+            name.push('/');
+        }
+        entries.push(name);
+    }
+    entries.sort();
+
+    let title = format!("Index of {}", html_escape(&request.path));
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>{0}</title>\n</head>\n<body>\n<h1>{0}</h1>\n<ul>\n",
+        title
+    );
+    if request.path != "/" {
+        html.push_str("<li><a href=\"../\">../</a></li>\n");
+    }
+    for name in entries {
+        html.push_str(&format!(
+            "<li><a href=\"{}\">{}</a></li>\n",
+            percent_encode(&name),
+            html_escape(&name)
+        ));
+    }
+    html.push_str("</ul>\n</body>\n</html>\n");
+    Ok(Response::new(200)
+        .header("Content-Type", "text/html; charset=utf-8")
+        .body(html))
+}
+
+fn html_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// 链接中的文件名：除了不需要编码的字符和目录末尾的 / 以外都编码
+fn percent_encode(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~/".contains(&b) {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", b));
+        }
+    }
+    encoded
+}
+
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+// * HTTP 日期（IMF-fixdate），例如 "Sun, 06 Nov 1994 08:49:37 GMT"；年月日按公历和天数互相换算
+fn http_date(time: SystemTime) -> String {
+    let secs = seconds(time);
+    let days = (secs / 86400) as i64;
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        // 1970-01-01 是星期四
+        WEEKDAYS[((days + 4) % 7) as usize],
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        secs % 86400 / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+// 只支持 IMF-fixdate，已经废弃的两种格式当作没有这个头部
+fn parse_http_date(s: &str) -> Option<SystemTime> {
+    let parts: Vec<&str> = s.split_whitespace().collect();
+    let [weekday, day, month, year, time, "GMT"] = parts[..] else {
+        return None;
+    };
+    if !WEEKDAYS.contains(&weekday.strip_suffix(',')?) {
+        return None;
+    }
+    let day: i64 = day.parse().ok()?;
+    let month = MONTHS.iter().position(|&m| m == month)? as i64 + 1;
+    let year: i64 = year.parse().ok()?;
+    // * 年份来自请求头，先限定范围，后面的换算和加法才不会溢出
+    if !(1970..=9999).contains(&year) {
+        return None;
+    }
+    let mut hms = time.split(':').map(|n| n.parse::<u64>().ok());
+    let (hour, minute, second) = (hms.next()??, hms.next()??, hms.next()??);
+    if !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 60 || hms.next().is_some() {
+        return None;
+    }
+    let days = u64::try_from(days_from_civil(year, month, day)).ok()?;
+    let secs = days
+        .checked_mul(86400)?
+        .checked_add(hour * 3600 + minute * 60 + second)?;
+    UNIX_EPOCH.checked_add(Duration::from_secs(secs))
+}
+
+// 1970-01-01 之后的天数 -> (年, 月, 日)
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::Version;
+
+    fn request(path: &str, headers: &[(&str, &str)]) -> Request {
+        let mut request = Request {
+            method: "GET".to_string(),
+            path: path.to_string(),
+            query: None,
+            version: Version::Http11,
+            headers: Headers::default(),
+            body: Vec::new(),
+        };
+        for (name, value) in headers {
+            request.headers.insert(name, value);
+        }
+        request
+    }
+
+    fn get(files: &StaticFiles, path: &str, headers: &[(&str, &str)]) -> Response {
+        let request = request(&format!("/static/{}", path), headers);
+        let decoded = crate::http::percent_decode(path).unwrap();
+        files.serve(&request, &decoded)
+    }
+
+    // 写出响应，取出响应体
+    fn body(response: &Response) -> Vec<u8> {
+        let mut out = Vec::new();
+        response.write_to(&mut out).unwrap();
+        let start = out.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+        out.split_off(start)
+    }
+
+    fn root(name: &str) -> PathBuf {
+        let root =
+            std::env::temp_dir().join(format!("hello_static_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("docs/a b")).unwrap();
+        fs::write(root.join("index.html"), "<h1>home</h1>").unwrap();
+        fs::write(root.join("logo.PNG"), [0x89, b'P', b'N', b'G', 0, 0xff]).unwrap();
+        fs::write(root.join("docs/notes.txt"), "0123456789").unwrap();
+        fs::write(root.join("docs/<b>.txt"), "").unwrap();
+        root
+    }
+
+    #[test]
+    fn files_directories_and_traversal() {
+        let root = root("files");
+        let files = StaticFiles::new(&root);
+
+        let response = get(&files, "logo.PNG", &[]);
+        assert_eq!(200, response.status);
+        assert_eq!(Some("image/png"), response.headers.get("content-type"));
+        assert_eq!(vec![0x89, b'P', b'N', b'G', 0, 0xff], body(&response));
+        assert!(response.body.is_empty());
+        assert_eq!(
+            Some("text/plain; charset=utf-8"),
+            get(&files, "docs/notes.txt", &[])
+                .headers
+                .get("content-type")
+        );
+        assert_eq!(404, get(&files, "missing.css", &[]).status);
+
+        // 目录：先补上 /，再返回 index.html；没有 index.html 时默认 404
+        let response = get(&files, "docs", &[]);
+        assert_eq!(301, response.status);
+        assert_eq!(Some("/static/docs/"), response.headers.get("location"));
+        assert_eq!(b"<h1>home</h1>".to_vec(), body(&get(&files, "", &[])));
+        assert_eq!(404, get(&files, "docs/", &[]).status);
+        let listing = StaticFiles::new(&root).listing(true);
+        let html = String::from_utf8(get(&listing, "docs/", &[]).body).unwrap();
+        assert!(html.contains("<a href=\"%3Cb%3E.txt\">&lt;b&gt;.txt</a>"));
+        assert!(html.contains("<a href=\"a%20b/\">a b/</a>"));
+        assert!(html.contains("<a href=\"../\">"));
+
+        for path in [
+            "../secret",
+            "docs/../../secret",
+            "%2e%2e/secret",
+            "docs%2F..%2F..",
+            "a\\..\\b",
+        ] {
+            assert_eq!(403, get(&files, path, &[]).status, "{}", path);
+        }
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(std::env::temp_dir(), root.join("tmp")).unwrap();
+            assert_eq!(403, get(&files, "tmp", &[]).status);
+        }
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn conditional_and_range_requests() {
+        let root = root("cache");
+        let files = StaticFiles::new(&root);
+        let response = get(&files, "docs/notes.txt", &[]);
+        let etag = response.headers.get("etag").unwrap().to_string();
+        let modified = response.headers.get("last-modified").unwrap().to_string();
+        assert_eq!(Some("bytes"), response.headers.get("accept-ranges"));
+
+        let response = get(
+            &files,
+            "docs/notes.txt",
+            &[("If-None-Match", &format!("\"x\", W/{}", etag))],
+        );
+        assert_eq!(304, response.status);
+        assert!(response.body.is_empty());
+        assert_eq!(
+            304,
+            get(
+                &files,
+                "docs/notes.txt",
+                &[("If-Modified-Since", &modified)]
+            )
+            .status
+        );
+        assert_eq!(
+            200,
+            get(
+                &files,
+                "docs/notes.txt",
+                &[("If-Modified-Since", "Sun, 06 Nov 1994 08:49:37 GMT")]
+            )
+            .status
+        );
+        // If-None-Match 优先于 If-Modified-Since
+        assert_eq!(
+            200,
+            get(
+                &files,
+                "docs/notes.txt",
+                &[("If-None-Match", "\"x\""), ("If-Modified-Since", &modified)]
+            )
+            .status
+        );
+
+        let range = |value: &str, if_range: Option<&str>| {
+            let mut headers = vec![("Range", value)];
+            headers.extend(if_range.map(|v| ("If-Range", v)));
+            let response = get(&files, "docs/notes.txt", &headers);
+            let text = String::from_utf8(body(&response)).unwrap();
+            let content_range = response.headers.get("content-range").map(str::to_string);
+            (response.status, text, content_range)
+        };
+        assert_eq!(
+            (206, "234".to_string(), Some("bytes 2-4/10".to_string())),
+            range("bytes=2-4", None)
+        );
+        assert_eq!(
+            (206, "789".to_string(), Some("bytes 7-9/10".to_string())),
+            range("bytes=7-", None)
+        );
+        assert_eq!(
+            (206, "6789".to_string(), Some("bytes 6-9/10".to_string())),
+            range("bytes=-4", None)
+        );
+        assert_eq!(
+            (206, "89".to_string(), Some("bytes 8-9/10".to_string())),
+            range("bytes=8-100", None)
+        );
+        assert_eq!(416, range("bytes=10-", None).0);
+        assert_eq!(Some("bytes */10".to_string()), range("bytes=10-", None).2);
+        assert_eq!(200, range("bytes=0-1,4-5", None).0);
+        assert_eq!(200, range("bytes=5-2", None).0);
+        assert_eq!(200, range("lines=1-2", None).0);
+        assert_eq!(206, range("bytes=0-0", Some(&etag)).0);
+        assert_eq!(206, range("bytes=0-0", Some(&modified)).0);
+        assert_eq!(200, range("bytes=0-0", Some("\"old\"")).0);
+
+        let time = UNIX_EPOCH + Duration::from_secs(784111777);
+        assert_eq!("Sun, 06 Nov 1994 08:49:37 GMT", http_date(time));
+        assert_eq!(Some(time), parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"));
+        assert_eq!(
+            "Thu, 29 Feb 2024 00:00:00 GMT",
+            http_date(UNIX_EPOCH + Duration::from_secs(1709164800))
+        );
+        assert_eq!(None, parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"));
+        assert_eq!(
+            None,
+            parse_http_date("Sun, 06 Nov 1000000000000 08:49:37 GMT")
+        );
+        assert_eq!(None, parse_http_date("Sun, 06 Nov 1969 08:49:37 GMT"));
+        assert_eq!(
+            200,
+            get(
+                &files,
+                "docs/notes.txt",
+                &[(
+                    "If-Modified-Since",
+                    "Sun, 06 Nov 1000000000000 08:49:37 GMT"
+                )]
+            )
+            .status
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
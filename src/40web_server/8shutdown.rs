@@ -0,0 +1,285 @@
+///* 收到 SIGINT / SIGTERM 时优雅地关闭 web server
+// 书中只能靠 take(2) 让 main 退出循环，ThreadPool 的 Drop 才有机会运行；这里改成由信号触发：
+// - 信号处理函数只设置一个 AtomicBool（信号处理函数里只能做这种异步信号安全的事），
+//   accept 循环使用非阻塞的 listener，每隔一小段时间检查一次，收到信号后不再接受新的连接
+// - Connections 记录每个打开的连接，以及它是否正在处理请求：
+//   开始关闭时，空闲的连接（在等待下一个请求）关掉读的一端：worker 读到 EOF 后关闭连接，
+//   刚好已经收到完整请求的还可以回复 503；正在处理的请求继续执行，回复时带上 Connection: close
+//   空闲的连接也一直登记到 worker 放开它为止，close_all 同样要等它，这样它被拒绝的请求也能计入统计
+// - 等到所有请求完成，或者超过 deadline 时强制关闭剩下的连接，这些请求记为 aborted
+//   * 只能关掉 socket，handler 本身还会运行到返回为止；close_all 返回 false 时 main 不再 join worker，直接退出
+// - 已经 accept 但还在线程池队列中、没有开始处理的连接也算作 aborted，close_all 返回时统计就是最终的
+// - 第二次收到信号时不再等待，直接退出
+// - 另外记录已经 accept、还在线程池队列中的连接数，空闲的持久连接据此让出 worker（见 6connection.rs）
+use std::collections::HashMap;
+use std::io;
+use std::net::{Shutdown, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::Duration;
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// 是否已经收到了 SIGINT 或 SIGTERM
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+/// 为 SIGINT 和 SIGTERM 安装处理函数；不是 Unix 的平台上什么都不做
+pub fn install_handlers() -> io::Result<()> {
+    #[cfg(unix)]
+    unix::install()?;
+    Ok(())
+}
+
+// * 只用标准库：直接声明 libc 中的 signal 和 _exit，Rust 程序在 Unix 上本来就链接了 libc
+#[cfg(unix)]
+mod unix {
+    use std::io;
+    use std::os::raw::c_int;
+    use std::sync::atomic::Ordering;
+
+    const SIGINT: c_int = 2;
+    const SIGTERM: c_int = 15;
+    const SIG_ERR: usize = usize::MAX;
+
+    extern "C" {
+        fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+        fn _exit(status: c_int) -> !;
+    }
+
+    extern "C" fn on_signal(signum: c_int) {
+        if super::REQUESTED.swap(true, Ordering::SeqCst) {
+            // 128 + 信号编号，和被信号终止的进程的退出码一致
+            unsafe { _exit(128 + signum) }
+        }
+    }
+
+    pub fn install() -> io::Result<()> {
+        for signum in [SIGINT, SIGTERM] {
+            if unsafe { signal(signum, on_signal) } == SIG_ERR {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 关闭时处理完的请求数和被中止的请求数
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Report {
+    pub drained: usize,
+    pub aborted: usize,
+}
+
+#[derive(Default)]
+struct State {
+    next_id: u64,
+    // 连接的 id -> (用来关闭连接的 TcpStream 副本, 是否正在处理请求)
+    open: HashMap<u64, (TcpStream, bool)>,
+    // 已经 accept、还没有 worker 开始处理的连接数
+    queued: usize,
+    closing: bool,
+    report: Report,
+}
+
+/// 所有打开的连接，由 main 和各个 worker 共享
+#[derive(Default)]
+pub struct Connections {
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+impl Connections {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+
+    /// main 接受一个连接、交给线程池之前调用
+    pub fn accepted(&self) {
+        self.lock().queued += 1;
+    }
+
+    /// worker 开始处理一个连接时登记；已经开始关闭时拒绝，连接记为 aborted
+    pub fn open(self: &Arc<Self>, stream: &TcpStream) -> io::Result<Connection> {
+        let mut state = self.lock();
+        let queued = state.queued > 0;
+        state.queued = state.queued.saturating_sub(1);
+        let stream = stream.try_clone()?;
+        if state.closing {
+            // close_all 返回时已经把还在排队的连接算作 aborted，不再重复计数
+            if queued {
+                state.report.aborted += 1;
+            }
+            return Err(io::Error::other("the server is shutting down"));
+        }
+        let id = state.next_id;
+        state.next_id += 1;
+        state.open.insert(id, (stream, false));
+        Ok(Connection {
+            connections: Arc::clone(self),
+            id,
+        })
+    }
+
+    /// 开始关闭：关掉空闲的连接，等待所有连接结束，最多等 `deadline`，然后关掉剩下的
+    ///
+    /// 所有请求都在 deadline 之前完成时返回 true；返回 false 时还有 handler 在运行，join worker 会一直等它
+    pub fn close_all(&self, deadline: Duration) -> bool {
+        let mut state = self.lock();
+        state.closing = true;
+        for (stream, busy) in state.open.values() {
+            if !*busy {
+                let _ = stream.shutdown(Shutdown::Read);
+            }
+        }
+        let (mut state, _) = self
+            .changed
+            .wait_timeout_while(state, deadline, |state| !state.open.is_empty())
+            .unwrap();
+        // * 从 open 中移除之后，这些连接上的请求不会再被计数：
+        //   超过 deadline 还没结束的连接，以及还在排队的连接，都在这里算作 aborted
+        let remaining = std::mem::take(&mut state.open);
+        state.report.aborted += remaining.len() + state.queued;
+        state.queued = 0;
+        for (stream, _) in remaining.values() {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+        !remaining.values().any(|(_, busy)| *busy)
+    }
+
+    /// close_all 之后调用，得到最终的统计
+    pub fn report(&self) -> Report {
+        self.lock().report
+    }
+}
+
+/// 一个已登记的连接，drop 时自动注销
+pub struct Connection {
+    connections: Arc<Connections>,
+    id: u64,
+}
+
+impl Connection {
+    /// 收到完整的请求、开始处理之前调用；已经开始关闭时返回 false，请求记为 aborted，调用者回复 503
+    pub fn begin_request(&self) -> bool {
+        let mut state = self.connections.lock();
+        if state.closing {
+            // 已经被 close_all 移除的连接在那时计过数了
+            if state.open.contains_key(&self.id) {
+                state.report.aborted += 1;
+            }
+            return false;
+        }
+        if let Some((_, busy)) = state.open.get_mut(&self.id) {
+            *busy = true;
+        }
+        true
+    }
+
+    /// 服务器正在关闭，当前的请求应该是这个连接上的最后一个
+    pub fn closing(&self) -> bool {
+        self.connections.lock().closing
+    }
+
+    /// 有连接在排队等 worker，这个连接不应该再空闲地占着 worker
+    pub fn others_waiting(&self) -> bool {
+        self.connections.lock().queued > 0
+    }
+
+    /// 回复之后调用；返回 false 时应该关闭连接
+    pub fn end_request(&self) -> bool {
+        let mut state = self.connections.lock();
+        let closing = state.closing;
+        if let Some((_, busy)) = state.open.get_mut(&self.id) {
+            *busy = false;
+            if closing {
+                state.open.remove(&self.id);
+                state.report.drained += 1;
+                self.connections.changed.notify_all();
+            }
+        }
+        !closing
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        let mut state = self.connections.lock();
+        if state.open.remove(&self.id).is_some() {
+            self.connections.changed.notify_all();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+    use std::thread;
+
+    // 返回 (服务端, 客户端) 两端
+    fn pair(listener: &TcpListener) -> (TcpStream, TcpStream) {
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        (server, client)
+    }
+
+    #[test]
+    fn drains_in_flight_requests_until_the_deadline() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let connections = Arc::new(Connections::default());
+        let (mut idle_socket, _idle_client) = pair(&listener);
+        let idle = connections.open(&idle_socket).unwrap();
+        // 空闲连接的 worker：读到 EOF 时缓冲区里正好有一个完整的请求
+        let idle_worker = thread::spawn(move || {
+            assert_eq!(0, idle_socket.read(&mut [0; 1]).unwrap());
+            assert!(!idle.begin_request());
+        });
+        let (fast, _fast_client) = pair(&listener);
+        let fast = connections.open(&fast).unwrap();
+        let (slow, _slow_client) = pair(&listener);
+        let slow = connections.open(&slow).unwrap();
+        assert!(fast.begin_request());
+        assert!(slow.begin_request());
+        assert!(!fast.closing());
+
+        // fast 在 deadline 之前完成，slow 一直没有完成
+        let worker = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            assert!(fast.closing());
+            assert!(!fast.end_request());
+        });
+        // 还有一个连接在线程池队列中
+        connections.accepted();
+        assert!(!connections.close_all(Duration::from_millis(500)));
+        // * close_all 返回时统计就是最终的：空闲连接上被拒绝的请求也已经计入
+        let report = Report {
+            drained: 1,
+            aborted: 3,
+        };
+        assert_eq!(report, connections.report());
+        worker.join().unwrap();
+        idle_worker.join().unwrap();
+        assert!(!slow.end_request());
+        drop(slow);
+
+        // 关闭之后才轮到的连接直接拒绝，它在 close_all 返回时已经计数了
+        let (late, _late_client) = pair(&listener);
+        assert!(connections.open(&late).is_err());
+        assert_eq!(report, connections.report());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn signal_sets_the_flag() {
+        extern "C" {
+            fn raise(signum: std::os::raw::c_int) -> std::os::raw::c_int;
+        }
+        install_handlers().unwrap();
+        assert!(!requested());
+        assert_eq!(0, unsafe { raise(15) });
+        assert!(requested());
+    }
+}
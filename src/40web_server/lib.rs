@@ -0,0 +1,141 @@
+///* ThreadPool（对应书中 hello crate 的 src/lib.rs）
+// 从 3shutdown_clean.rs 中拆出来，web server 和 minigrep 的并行搜索共用同一个实现
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::thread;
+
+pub struct ThreadPool {
+    workers: Vec<Worker>,
+    sender: mpsc::Sender<Message>,
+    // * 是否打印 worker 的运行日志；minigrep 这类把结果写到标准输出的程序需要关掉
+    logging: bool,
+}
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+// * 定义消息类型，job or terminate
+enum Message {
+    NewJob(Job),
+    Terminate,
+}
+
+impl ThreadPool {
+    /// Create a new ThreadPool.
+    ///
+    /// The size is the number of threads in the pool.
+    ///
+    /// # Panics
+    ///
+    /// The `new` function will panic if the size is zero.
+    pub fn new(size: usize) -> ThreadPool {
+        ThreadPool::with_logging(size, true)
+    }
+
+    /// Create a new ThreadPool, choosing whether workers print log messages.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the size is zero.
+    pub fn with_logging(size: usize, logging: bool) -> ThreadPool {
+        assert!(size > 0);
+
+        let (sender, receiver) = mpsc::channel();
+
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let mut workers = Vec::with_capacity(size);
+
+        for id in 0..size {
+            workers.push(Worker::new(id, Arc::clone(&receiver), logging));
+        }
+
+        ThreadPool {
+            workers,
+            sender,
+            logging,
+        }
+    }
+
+    pub fn execute<F>(&self, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let job = Box::new(f);
+
+        self.sender.send(Message::NewJob(job)).unwrap();
+    }
+}
+
+//* 为 ThreadPool 实现 Drop Trait，销毁时，清理线程池
+// 先发停止消息，然后等待线程退出
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        if self.logging {
+            println!("Sending terminate message to all workers.");
+        }
+
+        //* 向线程发送信号使其停止接收任务
+        for _ in &self.workers {
+            // 发送数量相等的消息（每个 worker 持有的接收端一样）
+            self.sender.send(Message::Terminate).unwrap();
+        }
+
+        if self.logging {
+            println!("Shutting down all workers.");
+        }
+
+        for worker in &mut self.workers {
+            if self.logging {
+                println!("Shutting down worker {}", worker.id);
+            }
+            // * 并不能直接调用 join，因为只有每一个 worker 的可变借用，而 join 会获取其参数的所有权
+            // 为了解决这个问题，需要一个方法将 thread 移动出拥有其所有权的 Worker 实例以便 join 可以消费这个线程
+            // 将 thread 设计成 Option，通过 take 转义所有权
+            if let Some(thread) = worker.thread.take() {
+                thread.join().unwrap();
+            }
+        }
+    }
+}
+
+struct Worker {
+    id: usize,
+    //* Worker 存放的是 Option<thread::JoinHandle<()>，就可以在 Option 上调用 take 方法将值从 Some 成员中移动出来而对 None 成员不做处理
+    //* 换句话说，正在运行的 Worker 的 thread 将是 Some 成员值，而当需要清理 worker 时，将 Some 替换为 None，这样 worker 就没有可以运行的线程了
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Worker {
+    fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Message>>>, logging: bool) -> Worker {
+        let thread = thread::spawn(move || loop {
+            let message = receiver.lock().unwrap().recv().unwrap();
+
+            match message {
+                Message::NewJob(job) => {
+                    if logging {
+                        println!("Worker {} got a job; executing.", id);
+                    }
+
+                    // * 任务 panic 时只结束这个任务，worker 继续接收后面的任务
+                    if panic::catch_unwind(AssertUnwindSafe(job)).is_err() && logging {
+                        println!("Worker {} job panicked; continuing.", id);
+                    }
+                }
+                Message::Terminate => {
+                    if logging {
+                        println!("Worker {} was told to terminate.", id);
+                    }
+
+                    break;
+                }
+            }
+        });
+
+        Worker {
+            id,
+            thread: Some(thread),
+        }
+    }
+}